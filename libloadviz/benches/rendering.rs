@@ -1,11 +1,12 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use libloadviz::renderer::Renderer;
+use libloadviz::renderer::{Renderer, Weather};
 
 fn rendering_busy(c: &mut Criterion) {
     let width = 100;
     let height = 100;
     let mut pixels = vec![0; width * height * 3];
     let renderer: Renderer = Default::default();
+    let weather: Weather = Default::default();
 
     let cpu_loads = vec![
         libloadviz::cpuload::CpuLoad {
@@ -19,7 +20,16 @@ fn rendering_busy(c: &mut Criterion) {
     ];
 
     c.bench_function("render 100x100 image", |b| {
-        b.iter(|| renderer.render_image(black_box(&cpu_loads), width, height, 0.0, &mut pixels));
+        b.iter(|| {
+            renderer.render_image(
+                black_box(&cpu_loads),
+                &weather,
+                width,
+                height,
+                0.0,
+                &mut pixels,
+            )
+        });
     });
 }
 
@@ -28,6 +38,7 @@ fn rendering_idle(c: &mut Criterion) {
     let height = 100;
     let mut pixels = vec![0; width * height * 3];
     let renderer: Renderer = Default::default();
+    let weather: Weather = Default::default();
 
     let cpu_loads = vec![
         libloadviz::cpuload::CpuLoad {
@@ -41,7 +52,16 @@ fn rendering_idle(c: &mut Criterion) {
     ];
 
     c.bench_function("render 100x100 image on an idle system", |b| {
-        b.iter(|| renderer.render_image(black_box(&cpu_loads), width, height, 0.0, &mut pixels));
+        b.iter(|| {
+            renderer.render_image(
+                black_box(&cpu_loads),
+                &weather,
+                width,
+                height,
+                0.0,
+                &mut pixels,
+            )
+        });
     });
 }
 
@@ -0,0 +1,111 @@
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct BatteryState {
+    /// 0 is empty, 1 is fully charged
+    pub charge_0_to_1: f32,
+
+    pub charging: bool,
+}
+
+/// One battery, as read from the system
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct BatteryReading {
+    pub state: BatteryState,
+
+    /// Energy stored now and when full, in watt hours. `None` for batteries
+    /// that only report a percentage.
+    pub energy_now_and_full_wh: Option<(f32, f32)>,
+}
+
+/// Combine the readings of several batteries into one. Laptops with two
+/// batteries exist, and they don't have to be the same size.
+///
+/// If all batteries report their energy, the charge is total energy over total
+/// capacity. Otherwise it is the average of the percentages.
+///
+/// Returns `None` if there are no batteries.
+pub fn combine(batteries: &[BatteryReading]) -> Option<BatteryState> {
+    if batteries.is_empty() {
+        return None;
+    }
+    let charging = batteries.iter().any(|battery| battery.state.charging);
+
+    let energies: Option<Vec<(f32, f32)>> = batteries
+        .iter()
+        .map(|battery| battery.energy_now_and_full_wh)
+        .collect();
+    if let Some(energies) = energies {
+        let now_wh: f32 = energies.iter().map(|(now, _)| now).sum();
+        let full_wh: f32 = energies.iter().map(|(_, full)| full).sum();
+        if full_wh > 0.0 {
+            return Some(BatteryState {
+                charge_0_to_1: (now_wh / full_wh).clamp(0.0, 1.0),
+                charging,
+            });
+        }
+    }
+
+    let total_charge: f32 = batteries
+        .iter()
+        .map(|battery| battery.state.charge_0_to_1)
+        .sum();
+    return Some(BatteryState {
+        charge_0_to_1: total_charge / batteries.len() as f32,
+        charging,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(
+        charge_0_to_1: f32,
+        charging: bool,
+        energy_now_and_full_wh: Option<(f32, f32)>,
+    ) -> BatteryReading {
+        return BatteryReading {
+            state: BatteryState {
+                charge_0_to_1,
+                charging,
+            },
+            energy_now_and_full_wh,
+        };
+    }
+
+    #[test]
+    fn test_combine() {
+        assert_eq!(None, combine(&[]));
+
+        let combined = combine(&[reading(1.0, false, None), reading(0.5, true, None)]);
+        assert_eq!(
+            Some(BatteryState {
+                charge_0_to_1: 0.75,
+                charging: true,
+            }),
+            combined
+        );
+    }
+
+    #[test]
+    fn test_combine_by_energy() {
+        // A nearly empty big battery and a full small one
+        let combined = combine(&[
+            reading(0.1, false, Some((8.0, 80.0))),
+            reading(1.0, false, Some((20.0, 20.0))),
+        ]);
+        assert_eq!(
+            Some(BatteryState {
+                charge_0_to_1: 0.28,
+                charging: false,
+            }),
+            combined
+        );
+
+        // Falls back to percentages unless all batteries report energy
+        let combined = combine(&[
+            reading(0.1, false, Some((8.0, 80.0))),
+            reading(1.0, false, None),
+        ]);
+        assert_eq!(Some(0.55), combined.map(|battery| battery.charge_0_to_1));
+    }
+}
@@ -0,0 +1,57 @@
+#![allow(clippy::needless_return)]
+
+/// Serves the load of this machine to LoadViz running somewhere else
+use std::{env, net::TcpListener, process::ExitCode};
+
+use libloadviz::{
+    load_source::LocalLoadSource,
+    remote::{self, RemoteAddress},
+};
+
+fn main() -> ExitCode {
+    if env::args().len() > 2 {
+        println!(
+            "Usage: {} [host:port | unix:/path/to/socket]",
+            env::args().next().unwrap()
+        );
+        println!();
+        println!(
+            "Listens on port {} on localhost only by default. Anybody who can reach",
+            remote::DEFAULT_PORT
+        );
+        println!("the agent can see the load, so think twice before using 0.0.0.0.");
+        return ExitCode::FAILURE;
+    }
+    let address = env::args()
+        .nth(1)
+        .unwrap_or_else(|| format!("127.0.0.1:{}", remote::DEFAULT_PORT));
+
+    match RemoteAddress::parse(&address) {
+        RemoteAddress::Tcp(host_and_port) => {
+            let listener = match TcpListener::bind(&host_and_port) {
+                Ok(listener) => listener,
+                Err(error) => {
+                    eprintln!("Listening on {}: {}", host_and_port, error);
+                    return ExitCode::FAILURE;
+                }
+            };
+            println!("Serving load on {}", host_and_port);
+            remote::serve_tcp(listener, Box::new(LocalLoadSource));
+        }
+
+        #[cfg(unix)]
+        RemoteAddress::Unix(path) => {
+            let listener = match std::os::unix::net::UnixListener::bind(&path) {
+                Ok(listener) => listener,
+                Err(error) => {
+                    eprintln!("Listening on {}: {}", path.display(), error);
+                    return ExitCode::FAILURE;
+                }
+            };
+            println!("Serving load on {}", path.display());
+            remote::serve_unix(listener, Box::new(LocalLoadSource));
+        }
+    }
+
+    return ExitCode::SUCCESS;
+}
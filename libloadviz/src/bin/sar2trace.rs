@@ -0,0 +1,62 @@
+#![allow(clippy::needless_return)]
+
+/// Converts `sadf -d -- -P ALL` output into a load trace for replaying
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    process::ExitCode,
+};
+
+use libloadviz::{sar::SarImporter, trace::TraceWriter};
+
+fn main() -> ExitCode {
+    if env::args().len() != 3 {
+        println!(
+            "Usage: {} <sadf-output.csv> <output.trace>",
+            env::args().next().unwrap()
+        );
+        println!();
+        println!("Create the input with: sadf -d /var/log/sysstat/sa15 -- -P ALL");
+        return ExitCode::FAILURE;
+    }
+    let input_name = env::args().nth(1).unwrap();
+    let output_name = env::args().nth(2).unwrap();
+
+    let input = match File::open(&input_name) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("Opening {}: {}", input_name, error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let writer =
+        File::create(&output_name).and_then(|output| TraceWriter::new(BufWriter::new(output)));
+    let mut writer = match writer {
+        Ok(writer) => writer,
+        Err(error) => {
+            eprintln!("Creating {}: {}", output_name, error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut count = 0;
+    for sample in SarImporter::new(BufReader::new(input)) {
+        let sample = match sample {
+            Ok(sample) => sample,
+            Err(error) => {
+                eprintln!("{}: {}", input_name, error);
+                return ExitCode::FAILURE;
+            }
+        };
+        if let Err(error) = writer.write_sample(&sample) {
+            eprintln!("Writing {}: {}", output_name, error);
+            return ExitCode::FAILURE;
+        }
+        count += 1;
+    }
+
+    println!("Wrote {} samples to {}", count, output_name);
+    return ExitCode::SUCCESS;
+}
@@ -2,7 +2,7 @@
 
 use std::{ffi::c_int, fs, mem, slice};
 
-use libloadviz::renderer::Renderer;
+use libloadviz::renderer::{srgb, Renderer, Weather};
 use libwebp_sys::*;
 
 struct AnimWriter {
@@ -110,6 +110,7 @@ fn main() {
 
     let mut pixels = vec![0u8; anim_writer.width * anim_writer.height * 3];
     let renderer: Renderer = Default::default();
+    let weather: Weather = Default::default();
 
     for i in 0..(frames_per_second * seconds) {
         let dt_seconds = i as f32 / frames_per_second as f32;
@@ -118,6 +119,7 @@ fn main() {
             // No crossfade yet, just render one image
             renderer.render_image(
                 &loads,
+                &weather,
                 anim_writer.width,
                 anim_writer.height,
                 dt_seconds,
@@ -128,6 +130,7 @@ fn main() {
             let mut pixels1 = vec![0u8; anim_writer.width * anim_writer.height * 3];
             renderer.render_image(
                 &loads,
+                &weather,
                 anim_writer.width,
                 anim_writer.height,
                 dt_seconds,
@@ -138,6 +141,7 @@ fn main() {
             let mut pixels2 = vec![0u8; anim_writer.width * anim_writer.height * 3];
             renderer.render_image(
                 &loads,
+                &weather,
                 anim_writer.width,
                 anim_writer.height,
                 // Render image before the first frame of the whole animation
@@ -151,9 +155,7 @@ fn main() {
                 let p2 = pixels2[i];
                 let seconds_into_crossfade = dt_seconds - (seconds as f32 - xfade_seconds as f32);
                 let crossfade_0_to_1 = seconds_into_crossfade / xfade_seconds as f32;
-                let weight1 = 1.0 - crossfade_0_to_1;
-                let weight2 = crossfade_0_to_1;
-                pixels[i] = (weight1 * p1 as f32 + weight2 * p2 as f32) as u8;
+                pixels[i] = srgb::mix(crossfade_0_to_1, p1, p2);
             }
         }
 
@@ -34,7 +34,7 @@ fn main() -> ExitCode {
 
     let mut pixels = vec![0u8; width * height * 3];
     let renderer: Renderer = Default::default();
-    renderer.render_image(&loads, width, height, 0.0, &mut pixels);
+    renderer.render_image(&loads, &Default::default(), width, height, 0.0, &mut pixels);
 
     let mut data: *mut u8 = std::ptr::null_mut();
     let size = unsafe {
@@ -0,0 +1,204 @@
+use std::time::{Duration, Instant};
+
+use crate::device_filter::DeviceFilter;
+use crate::load_reader::LoadReader;
+use crate::load_source::{LoadSource, LocalLoadSource};
+use crate::physics::{AnimationSpeed, DisplayedState};
+use crate::renderer::{Renderer, Visualization};
+use crate::theme::Theme;
+use crate::LoadViz;
+
+static DEFAULT_WIDTH: usize = 256;
+static DEFAULT_HEIGHT: usize = 256;
+
+/// Sets up a [`LoadViz`]. Get one from [`LoadViz::builder()`].
+///
+/// Anything you don't set gets the same defaults as the macOS app: the machine
+/// we're running on, with loop / RAM disks and virtual network interfaces
+/// ignored.
+pub struct LoadVizBuilder {
+    width: usize,
+    height: usize,
+    source: Option<Box<dyn LoadSource>>,
+    disk_filter: Option<DeviceFilter>,
+    network_filter: Option<DeviceFilter>,
+    sample_interval: Option<Duration>,
+    animation_speed: AnimationSpeed,
+    weather_enabled: bool,
+
+    /// Set up right away, so that [`visualization()`](Self::visualization)
+    /// can tell whether the name is any good
+    renderer: Renderer,
+}
+
+impl Default for LoadVizBuilder {
+    fn default() -> Self {
+        return Self {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            source: None,
+            disk_filter: None,
+            network_filter: None,
+            sample_interval: None,
+            animation_speed: Default::default(),
+            weather_enabled: true,
+            renderer: Default::default(),
+        };
+    }
+}
+
+impl LoadVizBuilder {
+    /// Image size in pixels, 256x256 by default. Can be changed later with
+    /// [`LoadViz::set_size()`].
+    pub fn size(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        return self;
+    }
+
+    /// Where the samples come from, see [`LoadViz::set_load_source()`]
+    pub fn load_source(mut self, source: Box<dyn LoadSource>) -> Self {
+        self.source = Some(source);
+        return self;
+    }
+
+    /// See [`LoadViz::set_disk_filter()`]
+    pub fn disk_filter(mut self, filter: DeviceFilter) -> Self {
+        self.disk_filter = Some(filter);
+        return self;
+    }
+
+    /// See [`LoadViz::set_network_filter()`]
+    pub fn network_filter(mut self, filter: DeviceFilter) -> Self {
+        self.network_filter = Some(filter);
+        return self;
+    }
+
+    /// See [`LoadViz::set_sample_interval()`]
+    pub fn sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = Some(interval);
+        return self;
+    }
+
+    /// See [`LoadViz::set_animation_speed()`]
+    pub fn animation_speed(mut self, speed: AnimationSpeed) -> Self {
+        self.animation_speed = speed;
+        return self;
+    }
+
+    /// See [`LoadViz::set_weather_enabled()`]
+    pub fn weather_enabled(mut self, enabled: bool) -> Self {
+        self.weather_enabled = enabled;
+        return self;
+    }
+
+    /// See [`LoadViz::set_transparent_background()`]
+    pub fn transparent_background(mut self, transparent: bool) -> Self {
+        self.renderer.set_transparent_background(transparent);
+        return self;
+    }
+
+    /// See [`LoadViz::set_theme()`]
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.renderer.set_theme(theme);
+        return self;
+    }
+
+    /// See [`Renderer::set_noise_seed()`]
+    pub fn noise_seed(mut self, seed: u64) -> Self {
+        self.renderer.set_noise_seed(seed);
+        return self;
+    }
+
+    /// See [`LoadViz::add_visualization()`]. Add before choosing it with
+    /// [`visualization()`](Self::visualization).
+    pub fn add_visualization(mut self, visualization: Box<dyn Visualization>) -> Self {
+        self.renderer.add_visualization(visualization);
+        return self;
+    }
+
+    /// See [`LoadViz::set_visualization()`]
+    pub fn visualization(mut self, name: &str) -> Result<Self, String> {
+        self.renderer.select_visualization(name)?;
+        return Ok(self);
+    }
+
+    pub fn build(self) -> LoadViz {
+        let source = self.source.unwrap_or_else(|| Box::new(LocalLoadSource));
+        let mut load_reader = LoadReader::new(source);
+        if let Some(disk_filter) = self.disk_filter {
+            load_reader.disk_filter = disk_filter;
+        }
+        if let Some(network_filter) = self.network_filter {
+            load_reader.network_filter = network_filter;
+        }
+        if let Some(interval) = self.sample_interval {
+            load_reader.sample_interval = interval;
+        }
+
+        let mut displayed = DisplayedState::new();
+        displayed.speed = self.animation_speed;
+
+        return LoadViz {
+            width: self.width,
+            height: self.height,
+            pixels: vec![0; self.width * self.height * 3],
+            displayed,
+            t0: Instant::now(),
+            load_reader,
+            renderer: self.renderer,
+            weather_enabled: self.weather_enabled,
+            push_handle: None,
+            callback_status: None,
+            callback_error: None,
+            last_error: None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Scenario, ScenarioSource};
+
+    #[test]
+    fn test_build_and_render() {
+        let mut loadviz = LoadViz::builder()
+            .size(8, 4)
+            .load_source(Box::new(ScenarioSource::new(
+                Scenario::preset("demo").unwrap(),
+            )))
+            .disk_filter(DeviceFilter::excluding(&["sd*"]))
+            .build();
+        assert!(!loadviz.load_reader.disk_filter.matches("sda"));
+        assert!(loadviz.load_reader.network_filter.matches("eth0"));
+
+        let image = loadviz.render();
+        assert_eq!(8, image.width);
+        assert_eq!(4, image.height);
+        assert_eq!(8 * 4 * 3, image.pixels.len());
+
+        loadviz.set_size(2, 3);
+        assert_eq!((2, 3), loadviz.size());
+        assert_eq!(2 * 3 * 3, loadviz.render().pixels.len());
+    }
+
+    #[test]
+    fn test_renderer_options() {
+        let loadviz = LoadViz::builder()
+            .sample_interval(Duration::from_millis(250))
+            .weather_enabled(false)
+            .visualization("bars")
+            .unwrap()
+            .build();
+        assert_eq!(
+            Duration::from_millis(250),
+            loadviz.load_reader.sample_interval
+        );
+        assert!(!loadviz.weather_enabled);
+        assert_eq!("bars", loadviz.renderer.visualization_name());
+
+        let error = LoadViz::builder().visualization("pie").err().unwrap();
+        assert!(error.contains("classic, bars"), "{}", error);
+    }
+}
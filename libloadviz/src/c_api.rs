@@ -0,0 +1,893 @@
+//! Everything the macOS app, or any other C caller, needs to drive a
+//! [`LoadViz`].
+//!
+//! The header is generated into `include/loadviz.h` by `build.rs`.
+//!
+//! Functions that can fail return a [`LoadVizStatus`]. After a failure,
+//! [`loadviz_last_error()`] says what went wrong.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::producer::FrameProducer;
+use crate::push::{CallbackSource, PushSource};
+use crate::render_target::get_minimum_length;
+use crate::shared::{Frame, SharedLoadViz};
+use crate::{AnimationSpeed, CpuLoad, LoadViz, PixelFormat, RenderTarget};
+
+/// `\0` terminated so that we can hand it out without allocating
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// How many columns a [`LoadVizLoadCallback`] can fill in
+static CALLBACK_CAPACITY: usize = 256;
+
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadVizStatus {
+    Ok = 0,
+
+    /// The `loadviz` pointer, or some other pointer argument, was `NULL`
+    NullPointer,
+
+    /// Out of range number, or a string we couldn't make sense of
+    InvalidArgument,
+
+    /// Failed to open or create a file
+    IoError,
+}
+
+/// Fill in up to `capacity` entries of `loads` with the current load of each
+/// column, and return how many were filled in. Return 0 if there is nothing
+/// new.
+pub type LoadVizLoadCallback = Option<
+    unsafe extern "C" fn(user_data: *mut c_void, loads: *mut CpuLoad, capacity: usize) -> usize,
+>;
+
+/// Called on the rendering thread with each new frame. The frame is only
+/// valid during the call, don't release it.
+pub type LoadVizFrameCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, frame: *const Frame)>;
+
+/// Called with the `LoadViz` inside a shared one, see
+/// [`loadviz_shared_configure()`]. Pass `loadviz` to any of the settings
+/// functions, and return what they returned.
+pub type LoadVizConfigureCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, loadviz: *mut LoadViz) -> LoadVizStatus>;
+
+/// Whatever the C caller wants passed to its callback
+struct UserData(*mut c_void);
+
+// It's up to the C caller to make their user data usable from the rendering
+// thread, we only pass the pointer along
+unsafe impl Send for UserData {}
+
+type Failure = (LoadVizStatus, String);
+
+/// Run `action` on `loadviz`, and remember the error message if it fails
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+unsafe fn with_loadviz(
+    loadviz: *mut LoadViz,
+    action: impl FnOnce(&mut LoadViz) -> Result<(), Failure>,
+) -> LoadVizStatus {
+    let Ok(loadviz) = (unsafe { opaque_pointer::mut_object(loadviz) }) else {
+        return LoadVizStatus::NullPointer;
+    };
+
+    loadviz.last_error = None;
+    let Err((status, message)) = action(loadviz) else {
+        return LoadVizStatus::Ok;
+    };
+
+    set_last_error(loadviz, &message);
+    return status;
+}
+
+fn set_last_error(loadviz: &mut LoadViz, message: &str) {
+    // Interior NULs would make CString::new() fail, and the message is only
+    // for humans anyway
+    loadviz.last_error = Some(CString::new(message.replace('\0', "?")).unwrap());
+}
+
+/// # Safety
+///
+/// `string` must be `NULL` or point to a `\0` terminated string.
+unsafe fn to_str<'a>(string: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if string.is_null() {
+        return Err((LoadVizStatus::NullPointer, format!("{} is NULL", name)));
+    }
+    return unsafe { CStr::from_ptr(string) }.to_str().map_err(|_| {
+        (
+            LoadVizStatus::InvalidArgument,
+            format!("{} is not valid UTF-8", name),
+        )
+    });
+}
+
+/// Free the result with [`free_loadviz()`]
+///
+/// If the load source environment variables are broken, this shows the
+/// machine we're running on instead, and [`loadviz_last_error()`] says what
+/// was wrong.
+#[no_mangle]
+pub extern "C" fn new_loadviz() -> *mut LoadViz {
+    let loadviz = match crate::get_initial_load_source() {
+        Ok(source) => LoadViz::builder().load_source(source).build(),
+        Err(message) => {
+            let mut loadviz = LoadViz::builder().build();
+            set_last_error(&mut loadviz, &message);
+            loadviz
+        }
+    };
+    return opaque_pointer::raw(loadviz);
+}
+
+/// Passing `NULL` is fine and does nothing.
+///
+/// # Safety
+///
+/// `loadviz` must come from [`new_loadviz()`], and must not be used after
+/// this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_loadviz(loadviz: *mut LoadViz) {
+    // Fails for NULL only, and there's nothing to free then
+    let _ = unsafe { opaque_pointer::own_back(loadviz) };
+}
+
+/// Returns `width * height * 3` bytes, RGBRGBRGB..., top row first, or `NULL`
+/// if `loadviz` is `NULL`. The pixels are valid until the next call with the
+/// same `loadviz`. To render from several threads, or to hold on to frames,
+/// use [`loadviz_share()`] instead.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences the incoming `loadviz`
+/// pointer. But as long as you get that from [`new_loadviz()`](new_loadviz) you
+/// should be fine.
+#[no_mangle]
+pub unsafe extern "C" fn get_image(
+    loadviz: *mut LoadViz,
+    width: usize,
+    height: usize,
+) -> *const u8 {
+    let Ok(loadviz) = (unsafe { opaque_pointer::mut_object(loadviz) }) else {
+        return std::ptr::null();
+    };
+    return loadviz.get_image(width, height);
+}
+
+/// The library version, `"1.2.3"`. Never free this.
+#[no_mangle]
+pub extern "C" fn loadviz_version() -> *const c_char {
+    return VERSION.as_ptr() as *const c_char;
+}
+
+/// What went wrong in the latest call that didn't return
+/// `LoadVizStatus_Ok`, or `NULL` if the latest call succeeded. Right after
+/// [`new_loadviz()`], what was wrong with the load source environment
+/// variables, if anything.
+///
+/// The string is owned by `loadviz` and valid until the next call with the
+/// same `loadviz`.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_last_error(loadviz: *const LoadViz) -> *const c_char {
+    let Ok(loadviz) = (unsafe { opaque_pointer::object(loadviz) }) else {
+        return std::ptr::null();
+    };
+    return match &loadviz.last_error {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    };
+}
+
+/// How often to ask the load source for a new sample, 1000ms by default
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_sample_interval_ms(
+    loadviz: *mut LoadViz,
+    interval_ms: u32,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            if interval_ms == 0 {
+                return Err((
+                    LoadVizStatus::InvalidArgument,
+                    "Sample interval must be at least 1ms".to_string(),
+                ));
+            }
+            loadviz.set_sample_interval(Duration::from_millis(interval_ms as u64));
+            return Ok(());
+        })
+    };
+}
+
+/// How many seconds it takes to animate from no load to full load (`up`) and
+/// back (`down`). 5s up and 20s down by default.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_animation_seconds(
+    loadviz: *mut LoadViz,
+    up: f32,
+    down: f32,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            // NaN fails this as well
+            if !(up > 0.0 && down > 0.0 && up.is_finite() && down.is_finite()) {
+                return Err((
+                    LoadVizStatus::InvalidArgument,
+                    format!("Animation times must be positive: up={} down={}", up, down),
+                ));
+            }
+            loadviz.set_animation_speed(AnimationSpeed {
+                seconds_0_to_100_up: up,
+                seconds_0_to_100_down: down,
+            });
+            return Ok(());
+        })
+    };
+}
+
+/// Turn the disk / network / battery / power weather on or off
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_weather_enabled(
+    loadviz: *mut LoadViz,
+    enabled: bool,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            loadviz.set_weather_enabled(enabled);
+            return Ok(());
+        })
+    };
+}
+
+/// Draw no background, so that the picture can be put on top of something
+/// else. Use [`loadviz_render_to()`] with a pixel format with alpha to get the
+/// transparency, [`get_image()`] draws on black.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_transparent_background(
+    loadviz: *mut LoadViz,
+    transparent: bool,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            loadviz.set_transparent_background(transparent);
+            return Ok(());
+        })
+    };
+}
+
+/// Colors to draw in: `classic`, `dark-panel`, `light-panel`,
+/// `high-contrast`, `colorblind-safe`, or the path to a theme file. The theme
+/// file format is documented in `src/theme.rs`.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and
+/// `name_or_path` must be `NULL` or a `\0` terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_theme(
+    loadviz: *mut LoadViz,
+    name_or_path: *const c_char,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            let name_or_path = to_str(name_or_path, "Theme")?;
+            let theme = crate::load_theme(name_or_path)
+                .map_err(|message| (LoadVizStatus::InvalidArgument, message))?;
+            loadviz.set_theme(theme);
+            return Ok(());
+        })
+    };
+}
+
+/// What the picture looks like: `classic` or `bars`
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `name` must be
+/// `NULL` or a `\0` terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_visualization(
+    loadviz: *mut LoadViz,
+    name: *const c_char,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            let name = to_str(name, "Visualization")?;
+            loadviz
+                .set_visualization(name)
+                .map_err(|message| (LoadVizStatus::InvalidArgument, message))?;
+            return Ok(());
+        })
+    };
+}
+
+/// Where the samples come from, one of:
+///
+/// - `local`: The machine we're running on
+/// - `scenario:NAME`: A scenario preset, or the path to a scenario file
+/// - `replay:PATH`: A trace file
+/// - `remote:ADDRESS`: A `loadviz-agent`
+/// - `prometheus:URL`: A node_exporter URL or metrics file
+/// - `pipe:PATH`: A FIFO, or `-` for stdin
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `spec` must be
+/// `NULL` or a `\0` terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_load_source(
+    loadviz: *mut LoadViz,
+    spec: *const c_char,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            let spec = to_str(spec, "Load source")?;
+            let source = crate::parse_load_source(spec)?;
+            loadviz.set_load_source(source);
+            return Ok(());
+        })
+    };
+}
+
+/// Show `count` columns with these loads, until the next push. Loads must be
+/// between 0 and 1. If user + system load is more than 1, system load is
+/// reduced.
+///
+/// The first push replaces the current load source.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `loads` must
+/// point to `count` loads.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_push_loads(
+    loadviz: *mut LoadViz,
+    loads: *const CpuLoad,
+    count: usize,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            if loads.is_null() && count > 0 {
+                return Err((LoadVizStatus::NullPointer, "Loads are NULL".to_string()));
+            }
+            let loads = if count == 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(loads, count)
+            };
+
+            if loadviz.push_handle.is_none() {
+                let source = PushSource::new();
+                let handle = source.handle();
+                loadviz.set_load_source(Box::new(source));
+                loadviz.push_handle = Some(handle);
+            }
+            return loadviz
+                .push_handle
+                .as_ref()
+                .unwrap()
+                .push(loads)
+                .map_err(|message| (LoadVizStatus::InvalidArgument, message));
+        })
+    };
+}
+
+/// Replace the current load source with `callback`. It is called with
+/// `user_data` from inside [`get_image()`] whenever a new sample is needed.
+///
+/// Out of range loads make us skip that sample, and
+/// [`loadviz_load_callback_error()`] says why.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `callback`
+/// must be safe to call with `user_data` until the load source is changed
+/// again or `loadviz` is freed.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_set_load_callback(
+    loadviz: *mut LoadViz,
+    callback: LoadVizLoadCallback,
+    user_data: *mut c_void,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            let Some(callback) = callback else {
+                return Err((LoadVizStatus::NullPointer, "Callback is NULL".to_string()));
+            };
+
+            let user_data = UserData(user_data);
+            let mut loads = vec![
+                CpuLoad {
+                    user_0_to_1: 0.0,
+                    system_0_to_1: 0.0,
+                };
+                CALLBACK_CAPACITY
+            ];
+            let source = CallbackSource::new(move || {
+                // Capture all of user_data, not just its non-Send pointer
+                let user_data = &user_data;
+                let count = callback(user_data.0, loads.as_mut_ptr(), loads.len());
+                if count == 0 {
+                    return None;
+                }
+                return Some(loads[..count.min(loads.len())].to_vec());
+            });
+            let status = source.status();
+            loadviz.set_load_source(Box::new(source));
+            loadviz.callback_status = Some(status);
+            return Ok(());
+        })
+    };
+}
+
+/// Why the most recently skipped [`loadviz_set_load_callback()`] sample was
+/// skipped, or `NULL` if none has been, or the load source has changed since.
+///
+/// The string is owned by `loadviz` and valid until the next call with the
+/// same `loadviz`.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_load_callback_error(loadviz: *mut LoadViz) -> *const c_char {
+    let Ok(loadviz) = (unsafe { opaque_pointer::mut_object(loadviz) }) else {
+        return std::ptr::null();
+    };
+    let message = loadviz
+        .callback_status
+        .as_ref()
+        .and_then(|status| status.last_error());
+    loadviz.callback_error =
+        message.map(|message| CString::new(message.replace('\0', "?")).unwrap());
+    return match &loadviz.callback_error {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    };
+}
+
+/// Record all samples into a trace file, any previous recording is stopped
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `path` must be
+/// `NULL` or a `\0` terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_start_recording(
+    loadviz: *mut LoadViz,
+    path: *const c_char,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            let path = to_str(path, "Recording path")?;
+            return loadviz
+                .start_recording(Path::new(path))
+                .map_err(|error| (LoadVizStatus::IoError, format!("{}: {}", path, error)));
+        })
+    };
+}
+
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_stop_recording(loadviz: *mut LoadViz) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            loadviz.stop_recording();
+            return Ok(());
+        })
+    };
+}
+
+/// False if `loadviz` is `NULL`, or if writing the trace file failed
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_is_recording(loadviz: *const LoadViz) -> bool {
+    return unsafe { opaque_pointer::object(loadviz) }.is_ok_and(|loadviz| loadviz.is_recording());
+}
+
+/// False if `loadviz` is `NULL`, or while a remote load source can't be
+/// reached
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_is_connected(loadviz: *const LoadViz) -> bool {
+    return unsafe { opaque_pointer::object(loadviz) }.is_ok_and(|loadviz| loadviz.is_connected());
+}
+
+/// Like [`get_image()`], but into a buffer of your own. Rows are `stride`
+/// bytes apart. To draw into part of a bigger image, point `pixels` at the
+/// top left pixel of that part and pass the stride of the bigger image.
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`], and `pixels` must
+/// point to at least `stride * (height - 1) + width * bytes_per_pixel`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_render_to(
+    loadviz: *mut LoadViz,
+    pixels: *mut u8,
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+) -> LoadVizStatus {
+    return unsafe {
+        with_loadviz(loadviz, |loadviz| {
+            if pixels.is_null() {
+                return Err((LoadVizStatus::NullPointer, "Pixels are NULL".to_string()));
+            }
+
+            let length = get_minimum_length(width, height, stride, format)
+                .map_err(|error| (LoadVizStatus::InvalidArgument, error.to_string()))?;
+            let pixels = std::slice::from_raw_parts_mut(pixels, length);
+            let mut target = RenderTarget::new(pixels, width, height, stride, format)
+                .map_err(|error| (LoadVizStatus::InvalidArgument, error.to_string()))?;
+            loadviz.render_to(&mut target);
+            return Ok(());
+        })
+    };
+}
+
+/// Make `loadviz` usable from several threads at once. To change settings
+/// afterwards, use [`loadviz_shared_configure()`].
+///
+/// Returns `NULL` if `loadviz` is `NULL`. Free the result with
+/// [`free_shared_loadviz()`].
+///
+/// # Safety
+///
+/// `loadviz` must be `NULL` or come from [`new_loadviz()`]. It is consumed by
+/// this call and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_share(loadviz: *mut LoadViz) -> *mut SharedLoadViz {
+    let Ok(loadviz) = (unsafe { opaque_pointer::own_back(loadviz) }) else {
+        return std::ptr::null_mut();
+    };
+    return opaque_pointer::raw(SharedLoadViz::new(loadviz));
+}
+
+/// Change settings of a shared `LoadViz`, from any thread. Calls `callback`
+/// with `user_data` and the `LoadViz` inside `shared`, while no other thread
+/// can use it. Returns what `callback` returned.
+///
+/// Inside the callback, [`loadviz_last_error()`] works on the `LoadViz` as
+/// usual.
+///
+/// # Safety
+///
+/// `shared` must be `NULL` or come from [`loadviz_share()`]. The `LoadViz`
+/// pointer is only valid during the call. Don't free or share it, and don't
+/// render `shared` from inside the callback, that will deadlock.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_shared_configure(
+    shared: *const SharedLoadViz,
+    callback: LoadVizConfigureCallback,
+    user_data: *mut c_void,
+) -> LoadVizStatus {
+    let Ok(shared) = (unsafe { opaque_pointer::object(shared) }) else {
+        return LoadVizStatus::NullPointer;
+    };
+    let Some(callback) = callback else {
+        return LoadVizStatus::NullPointer;
+    };
+    return shared.with_loadviz(|loadviz| unsafe { callback(user_data, loadviz) });
+}
+
+/// Frames that haven't been released yet stay valid. Passing `NULL` is fine
+/// and does nothing.
+///
+/// # Safety
+///
+/// `shared` must come from [`loadviz_share()`], no other thread may be using
+/// it, and it must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn free_shared_loadviz(shared: *mut SharedLoadViz) {
+    // Fails for NULL only, and there's nothing to free then
+    let _ = unsafe { opaque_pointer::own_back(shared) };
+}
+
+/// Take one animation step, draw it at this size and publish it as the
+/// latest frame. Can be called from any thread.
+///
+/// Release the frame with [`loadviz_release_frame()`]. Returns `NULL` if
+/// `shared` is `NULL`.
+///
+/// # Safety
+///
+/// `shared` must be `NULL` or come from [`loadviz_share()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_render_frame(
+    shared: *const SharedLoadViz,
+    width: usize,
+    height: usize,
+) -> *const Frame {
+    let Ok(shared) = (unsafe { opaque_pointer::object(shared) }) else {
+        return std::ptr::null();
+    };
+    return opaque_pointer::raw(shared.render(width, height));
+}
+
+/// The most recently rendered frame, from any thread. Returns `NULL` if
+/// nothing has been rendered yet.
+///
+/// Release the frame with [`loadviz_release_frame()`].
+///
+/// # Safety
+///
+/// `shared` must be `NULL` or come from [`loadviz_share()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_latest_frame(shared: *const SharedLoadViz) -> *const Frame {
+    let Ok(shared) = (unsafe { opaque_pointer::object(shared) }) else {
+        return std::ptr::null();
+    };
+    return match shared.latest_frame() {
+        Some(frame) => opaque_pointer::raw(frame),
+        None => std::ptr::null(),
+    };
+}
+
+/// After this, the frame's pixels may be reused for another frame. Passing
+/// `NULL` is fine and does nothing.
+///
+/// # Safety
+///
+/// `frame` must be `NULL` or come from [`loadviz_render_frame()`] or
+/// [`loadviz_latest_frame()`], and must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_release_frame(frame: *const Frame) {
+    // Fails for NULL only, and there's nothing to release then
+    let _ = unsafe { opaque_pointer::own_back(frame as *mut Frame) };
+}
+
+/// `width * height * 3` bytes, RGBRGBRGB..., top row first. Valid and
+/// unchanged until the frame is released.
+///
+/// # Safety
+///
+/// `frame` must be `NULL` or an unreleased frame.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_frame_pixels(frame: *const Frame) -> *const u8 {
+    return unsafe { opaque_pointer::object(frame) }
+        .map_or(std::ptr::null(), |frame| frame.pixels().as_ptr());
+}
+
+/// # Safety
+///
+/// `frame` must be `NULL` or an unreleased frame.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_frame_width(frame: *const Frame) -> usize {
+    return unsafe { opaque_pointer::object(frame) }.map_or(0, |frame| frame.width());
+}
+
+/// # Safety
+///
+/// `frame` must be `NULL` or an unreleased frame.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_frame_height(frame: *const Frame) -> usize {
+    return unsafe { opaque_pointer::object(frame) }.map_or(0, |frame| frame.height());
+}
+
+/// Increases by one for each frame rendered from the same shared `LoadViz`.
+/// Tells you whether [`loadviz_latest_frame()`] has anything new.
+///
+/// # Safety
+///
+/// `frame` must be `NULL` or an unreleased frame.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_frame_sequence(frame: *const Frame) -> u64 {
+    return unsafe { opaque_pointer::object(frame) }.map_or(0, |frame| frame.sequence());
+}
+
+/// Start rendering `shared` on a thread of its own, at `width` x `height`
+/// pixels and `fps` frames per second. Get the frames with
+/// [`loadviz_producer_latest_frame()`] or [`loadviz_producer_set_callback()`].
+///
+/// Without a callback, rendering pauses when nobody has asked for a frame for
+/// a second, and resumes on the next request.
+///
+/// Returns `NULL` if `shared` is `NULL`. Stop rendering with
+/// [`free_frame_producer()`].
+///
+/// # Safety
+///
+/// `shared` must be `NULL` or come from [`loadviz_share()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_start_producer(
+    shared: *const SharedLoadViz,
+    width: usize,
+    height: usize,
+    fps: f32,
+) -> *mut FrameProducer {
+    let Ok(shared) = (unsafe { opaque_pointer::object(shared) }) else {
+        return std::ptr::null_mut();
+    };
+    return opaque_pointer::raw(FrameProducer::new(shared.clone(), width, height, fps));
+}
+
+/// Stop rendering. Passing `NULL` is fine and does nothing.
+///
+/// # Safety
+///
+/// `producer` must come from [`loadviz_start_producer()`], and must not be
+/// used after this call. Don't call this from inside the frame callback.
+#[no_mangle]
+pub unsafe extern "C" fn free_frame_producer(producer: *mut FrameProducer) {
+    // Fails for NULL only, and there's nothing to free then
+    let _ = unsafe { opaque_pointer::own_back(producer) };
+}
+
+/// Takes effect from the next frame on. A zero width or height pauses
+/// rendering.
+///
+/// # Safety
+///
+/// `producer` must be `NULL` or come from [`loadviz_start_producer()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_producer_set_size(
+    producer: *const FrameProducer,
+    width: usize,
+    height: usize,
+) -> LoadVizStatus {
+    let Ok(producer) = (unsafe { opaque_pointer::object(producer) }) else {
+        return LoadVizStatus::NullPointer;
+    };
+    producer.set_size(width, height);
+    return LoadVizStatus::Ok;
+}
+
+/// Zero pauses rendering
+///
+/// # Safety
+///
+/// `producer` must be `NULL` or come from [`loadviz_start_producer()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_producer_set_fps(
+    producer: *const FrameProducer,
+    fps: f32,
+) -> LoadVizStatus {
+    let Ok(producer) = (unsafe { opaque_pointer::object(producer) }) else {
+        return LoadVizStatus::NullPointer;
+    };
+    if !(fps >= 0.0 && fps.is_finite()) {
+        return LoadVizStatus::InvalidArgument;
+    }
+    producer.set_fps(fps);
+    return LoadVizStatus::Ok;
+}
+
+/// Call `callback` with `user_data` on the rendering thread for each new
+/// frame. A `NULL` callback stops the calls.
+///
+/// # Safety
+///
+/// `producer` must be `NULL` or come from [`loadviz_start_producer()`], and
+/// `callback` must be safe to call with `user_data` from another thread
+/// until the callback is changed or `producer` is freed.
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_producer_set_callback(
+    producer: *const FrameProducer,
+    callback: LoadVizFrameCallback,
+    user_data: *mut c_void,
+) -> LoadVizStatus {
+    let Ok(producer) = (unsafe { opaque_pointer::object(producer) }) else {
+        return LoadVizStatus::NullPointer;
+    };
+    let Some(callback) = callback else {
+        producer.clear_callback();
+        return LoadVizStatus::Ok;
+    };
+
+    let user_data = UserData(user_data);
+    producer.set_callback(move |frame| {
+        // Capture all of user_data, not just its non-Send pointer
+        let user_data = &user_data;
+        unsafe { callback(user_data.0, frame) };
+    });
+    return LoadVizStatus::Ok;
+}
+
+/// The most recently rendered frame, or `NULL` if nothing has been rendered
+/// yet. Also resumes rendering if it was paused for lack of requests.
+///
+/// Release the frame with [`loadviz_release_frame()`].
+///
+/// # Safety
+///
+/// `producer` must be `NULL` or come from [`loadviz_start_producer()`].
+#[no_mangle]
+pub unsafe extern "C" fn loadviz_producer_latest_frame(
+    producer: *const FrameProducer,
+) -> *const Frame {
+    let Ok(producer) = (unsafe { opaque_pointer::object(producer) }) else {
+        return std::ptr::null();
+    };
+    return match producer.latest_frame() {
+        Some(frame) => opaque_pointer::raw(frame),
+        None => std::ptr::null(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors() {
+        let loadviz = opaque_pointer::raw(LoadViz::builder().size(4, 4).build());
+        unsafe {
+            assert!(loadviz_last_error(loadviz).is_null());
+
+            let status = loadviz_set_animation_seconds(loadviz, 0.0, 1.0);
+            assert_eq!(LoadVizStatus::InvalidArgument, status);
+            assert!(!loadviz_last_error(loadviz).is_null());
+
+            let spec = CString::new("scenario:no-such-preset").unwrap();
+            let status = loadviz_set_load_source(loadviz, spec.as_ptr());
+            assert_eq!(LoadVizStatus::InvalidArgument, status);
+
+            let spec = CString::new("scenario:demo").unwrap();
+            let status = loadviz_set_load_source(loadviz, spec.as_ptr());
+            assert_eq!(LoadVizStatus::Ok, status);
+            assert!(loadviz_last_error(loadviz).is_null());
+
+            let status = loadviz_set_load_source(loadviz, std::ptr::null());
+            assert_eq!(LoadVizStatus::NullPointer, status);
+
+            let theme = CString::new("no-such-theme").unwrap();
+            let status = loadviz_set_theme(loadviz, theme.as_ptr());
+            assert_eq!(LoadVizStatus::InvalidArgument, status);
+
+            let theme = CString::new("high-contrast").unwrap();
+            let status = loadviz_set_theme(loadviz, theme.as_ptr());
+            assert_eq!(LoadVizStatus::Ok, status);
+
+            let name = CString::new("sparkles").unwrap();
+            let status = loadviz_set_visualization(loadviz, name.as_ptr());
+            assert_eq!(LoadVizStatus::InvalidArgument, status);
+
+            let name = CString::new("bars").unwrap();
+            let status = loadviz_set_visualization(loadviz, name.as_ptr());
+            assert_eq!(LoadVizStatus::Ok, status);
+
+            free_loadviz(loadviz);
+        }
+
+        assert_eq!(LoadVizStatus::NullPointer, unsafe {
+            loadviz_set_weather_enabled(std::ptr::null_mut(), false)
+        });
+        assert!(unsafe { get_image(std::ptr::null_mut(), 1, 1) }.is_null());
+    }
+}
@@ -1,5 +1,10 @@
 use std::cmp::Ordering;
 
+/// How many counter ticks each synthesized sample is worth. Only the ratios
+/// matter.
+static SYNTHETIC_TICKS_PER_SAMPLE: f32 = 10_000.0;
+
+#[repr(C)]
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub struct CpuLoad {
     // NOTE: Maybe we should do "nice" here as well?
@@ -7,7 +12,7 @@ pub struct CpuLoad {
     pub system_0_to_1: f32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoadCounters {
     pub user: usize,
     pub system: usize,
@@ -18,6 +23,24 @@ pub struct LoadCounters {
     // NOTE: Maybe track nice in here as well?
 }
 
+/// Turns a series of loads into counters, for sources that only know the
+/// current load
+#[derive(Default)]
+pub(crate) struct SyntheticCounters {
+    /// Accumulated load, one entry per core
+    counters: Vec<LoadCounters>,
+}
+
+impl CpuLoad {
+    /// Unchecked, see [`make_load()`] for loads from outside
+    pub const fn new(user_0_to_1: f32, system_0_to_1: f32) -> CpuLoad {
+        return CpuLoad {
+            user_0_to_1,
+            system_0_to_1,
+        };
+    }
+}
+
 impl PartialOrd for CpuLoad {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         return (self.user_0_to_1 + self.system_0_to_1)
@@ -25,11 +48,51 @@ impl PartialOrd for CpuLoad {
     }
 }
 
+impl SyntheticCounters {
+    /// Add one sample's worth of load to the counters, and return them
+    pub(crate) fn add(&mut self, loads: &[CpuLoad]) -> Vec<LoadCounters> {
+        if self.counters.len() != loads.len() {
+            // Core count changed, start over
+            self.counters = vec![
+                LoadCounters {
+                    user: 0,
+                    system: 0,
+                    idle: 0,
+                };
+                loads.len()
+            ];
+        }
+
+        for (counters, load) in self.counters.iter_mut().zip(loads.iter()) {
+            let idle_0_to_1 = 1.0 - load.user_0_to_1 - load.system_0_to_1;
+            counters.user += (load.user_0_to_1 * SYNTHETIC_TICKS_PER_SAMPLE).round() as usize;
+            counters.system += (load.system_0_to_1 * SYNTHETIC_TICKS_PER_SAMPLE).round() as usize;
+            counters.idle += (idle_0_to_1 * SYNTHETIC_TICKS_PER_SAMPLE).round() as usize;
+        }
+
+        return self.counters.clone();
+    }
+}
+
+/// Loads from outside need checking. If user + system load is more than 1,
+/// system load is reduced.
+pub(crate) fn make_load(user_0_to_1: f32, system_0_to_1: f32) -> Result<CpuLoad, String> {
+    for value in [user_0_to_1, system_0_to_1] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(format!("Loads must be between 0 and 1, got {}", value));
+        }
+    }
+
+    return Ok(CpuLoad {
+        user_0_to_1,
+        system_0_to_1: system_0_to_1.min(1.0 - user_0_to_1),
+    });
+}
+
 /// Based on two CPU counter snapshots, compute the load for each CPU.
 ///
 /// If the number of cores have changed, return the right number of cores, but
 /// all with a load of 0. This usually happens on startup.
-#[cfg(any(not(debug_assertions), test))]
 pub fn diff(older: &[LoadCounters], newer: &[LoadCounters]) -> Vec<CpuLoad> {
     let mut result: Vec<CpuLoad> = vec![];
     if older.len() != newer.len() {
@@ -49,6 +112,14 @@ pub fn diff(older: &[LoadCounters], newer: &[LoadCounters]) -> Vec<CpuLoad> {
         let system = newer.system.wrapping_sub(older.system);
         let idle = newer.idle.wrapping_sub(older.idle);
         let total = user + system + idle;
+        if total == 0 {
+            // No time has passed, at least not that we can tell
+            result.push(CpuLoad {
+                user_0_to_1: 0.0,
+                system_0_to_1: 0.0,
+            });
+            continue;
+        }
         result.push(CpuLoad {
             user_0_to_1: user as f32 / total as f32,
             system_0_to_1: system as f32 / total as f32,
@@ -57,57 +128,9 @@ pub fn diff(older: &[LoadCounters], newer: &[LoadCounters]) -> Vec<CpuLoad> {
     return result;
 }
 
-/// Hard code load in debug builds to simplify testing the visualization
-#[cfg(all(debug_assertions, not(test)))]
-pub fn diff(_: &[LoadCounters], _: &[LoadCounters]) -> Vec<CpuLoad> {
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    let secs = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    if secs % 20 < 7 {
-        // High user load
-        return vec![
-            CpuLoad {
-                user_0_to_1: 0.7,
-                system_0_to_1: 0.2,
-            },
-            CpuLoad {
-                user_0_to_1: 0.0,
-                system_0_to_1: 0.1,
-            },
-        ];
-    } else if secs % 20 < 14 {
-        // High system load
-        return vec![
-            CpuLoad {
-                user_0_to_1: 0.2,
-                system_0_to_1: 0.7,
-            },
-            CpuLoad {
-                user_0_to_1: 0.1,
-                system_0_to_1: 0.0,
-            },
-        ];
-    }
-
-    // Idle
-    return vec![
-        CpuLoad {
-            user_0_to_1: 0.0,
-            system_0_to_1: 0.0,
-        },
-        CpuLoad {
-            user_0_to_1: 0.0,
-            system_0_to_1: 0.0,
-        },
-    ];
-}
-
 #[cfg(test)]
 mod tests {
-    use crate::cpuload::LoadCounters;
+    use crate::cpuload::{CpuLoad, LoadCounters, SyntheticCounters};
 
     /// Test that diff() can handle one counter wrapping around to zero
     #[test]
@@ -127,4 +150,28 @@ mod tests {
         assert_eq!(1.0 / (1.0 + 2.0 + 3.0), result[0].user_0_to_1);
         assert_eq!(2.0 / (1.0 + 2.0 + 3.0), result[0].system_0_to_1);
     }
+
+    #[test]
+    fn test_diff_unchanged() {
+        let counters = vec![LoadCounters {
+            user: 0,
+            system: 0,
+            idle: 0,
+        }];
+        let result = super::diff(&counters, &counters);
+        assert_eq!(0.0, result[0].user_0_to_1);
+        assert_eq!(0.0, result[0].system_0_to_1);
+    }
+
+    #[test]
+    fn test_synthetic_counters_diff_back_to_loads() {
+        let load = |user_0_to_1: f32, system_0_to_1: f32| CpuLoad {
+            user_0_to_1,
+            system_0_to_1,
+        };
+        let mut counters: SyntheticCounters = Default::default();
+        let older = counters.add(&[load(0.5, 0.25)]);
+        let newer = counters.add(&[load(0.25, 0.5)]);
+        assert_eq!(vec![load(0.25, 0.5)], super::diff(&older, &newer));
+    }
 }
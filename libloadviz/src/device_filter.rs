@@ -0,0 +1,75 @@
+/// Decides which devices (disks, network interfaces, ...) we should care about.
+///
+/// Patterns are either exact device names (`"lo"`), or prefixes ending in a
+/// `*` (`"loop*"`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceFilter {
+    /// If non-empty, only devices matching one of these patterns are included
+    pub include: Vec<String>,
+
+    /// Devices matching any of these patterns are excluded, even if they are
+    /// also included
+    pub exclude: Vec<String>,
+}
+
+impl DeviceFilter {
+    pub fn excluding(patterns: &[&str]) -> DeviceFilter {
+        return DeviceFilter {
+            include: vec![],
+            exclude: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+        };
+    }
+
+    pub fn matches(&self, device: &str) -> bool {
+        if self
+            .exclude
+            .iter()
+            .any(|pattern| pattern_matches(pattern, device))
+        {
+            return false;
+        }
+
+        if self.include.is_empty() {
+            return true;
+        }
+
+        return self
+            .include
+            .iter()
+            .any(|pattern| pattern_matches(pattern, device));
+    }
+}
+
+fn pattern_matches(pattern: &str, device: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        return device.starts_with(prefix);
+    }
+
+    return pattern == device;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclude() {
+        let filter = DeviceFilter::excluding(&["loop*", "lo"]);
+        assert!(filter.matches("sda"));
+        assert!(filter.matches("lowpan0"));
+        assert!(!filter.matches("loop0"));
+        assert!(!filter.matches("lo"));
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let filter = DeviceFilter {
+            include: vec!["nvme*".to_string(), "sda".to_string()],
+            exclude: vec!["nvme1*".to_string()],
+        };
+        assert!(filter.matches("sda"));
+        assert!(filter.matches("nvme0n1"));
+        assert!(!filter.matches("sdb"));
+        assert!(!filter.matches("nvme1n1"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use crate::device_filter::DeviceFilter;
+
+/// Below this, disk I/O is considered idle
+static QUIET_BYTES_PER_SECOND: f32 = 100_000.0;
+
+/// At this point and above, disk I/O is considered maxed out
+static LOUD_BYTES_PER_SECOND: f32 = 1_000_000_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskCounters {
+    pub device: String,
+
+    pub bytes_read: usize,
+    pub bytes_written: usize,
+
+    /// Milliseconds spent doing I/O, weighted by the number of I/O requests in
+    /// flight. Diffing this and dividing by the elapsed time gives you the
+    /// average queue length.
+    pub time_in_queue_ms: usize,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct DiskLoad {
+    pub read_bytes_per_second: f32,
+    pub write_bytes_per_second: f32,
+
+    /// Average number of I/O requests in flight
+    pub average_queue_length: f32,
+}
+
+impl DiskLoad {
+    /// 0 means idle, 1 means lots of I/O.
+    ///
+    /// Logarithmic, since disk throughput varies by orders of magnitude
+    /// depending on both workload and hardware.
+    pub fn activity_0_to_1(&self) -> f32 {
+        let bytes_per_second = self.read_bytes_per_second + self.write_bytes_per_second;
+        let activity = (bytes_per_second / QUIET_BYTES_PER_SECOND).log10()
+            / (LOUD_BYTES_PER_SECOND / QUIET_BYTES_PER_SECOND).log10();
+
+        // Note that this clamps the -inf we get from zero throughput as well
+        return activity.clamp(0.0, 1.0);
+    }
+}
+
+/// Based on two disk counter snapshots taken `dt` apart, compute the total
+/// disk load for all devices matching the filter.
+///
+/// Devices that are only present in one of the snapshots are ignored.
+pub fn diff(
+    older: &[DiskCounters],
+    newer: &[DiskCounters],
+    dt: Duration,
+    filter: &DeviceFilter,
+) -> DiskLoad {
+    let seconds = dt.as_secs_f32();
+    if seconds <= 0.0 {
+        return DiskLoad::default();
+    }
+
+    let mut bytes_read = 0;
+    let mut bytes_written = 0;
+    let mut time_in_queue_ms = 0;
+    for newer in newer.iter() {
+        if !filter.matches(&newer.device) {
+            continue;
+        }
+
+        let older = older.iter().find(|older| older.device == newer.device);
+        let Some(older) = older else {
+            // New device, no diff possible
+            continue;
+        };
+
+        if newer.bytes_read < older.bytes_read
+            || newer.bytes_written < older.bytes_written
+            || newer.time_in_queue_ms < older.time_in_queue_ms
+        {
+            // Counters reset, for example by the device being re-attached
+            continue;
+        }
+
+        bytes_read += newer.bytes_read - older.bytes_read;
+        bytes_written += newer.bytes_written - older.bytes_written;
+        time_in_queue_ms += newer.time_in_queue_ms - older.time_in_queue_ms;
+    }
+
+    return DiskLoad {
+        read_bytes_per_second: bytes_read as f32 / seconds,
+        write_bytes_per_second: bytes_written as f32 / seconds,
+        average_queue_length: time_in_queue_ms as f32 / 1000.0 / seconds,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(device: &str, bytes_read: usize, bytes_written: usize) -> DiskCounters {
+        return DiskCounters {
+            device: device.to_string(),
+            bytes_read,
+            bytes_written,
+            time_in_queue_ms: 0,
+        };
+    }
+
+    #[test]
+    fn test_diff_filter() {
+        let older = vec![counters("sda", 0, 0), counters("loop0", 0, 0)];
+        let newer = vec![counters("sda", 1000, 2000), counters("loop0", 5000, 5000)];
+
+        let result = diff(
+            &older,
+            &newer,
+            Duration::from_secs(2),
+            &DeviceFilter::excluding(&["loop*"]),
+        );
+        assert_eq!(500.0, result.read_bytes_per_second);
+        assert_eq!(1000.0, result.write_bytes_per_second);
+    }
+
+    /// Test that diff() skips devices with counters that went backwards,
+    /// rather than adding up huge wrapped around differences
+    #[test]
+    fn test_diff_reset() {
+        let older = vec![counters("sda", usize::MAX, 0), counters("sdb", 100, 0)];
+        let newer = vec![counters("sda", 9, 0), counters("sdb", 110, 0)];
+
+        let result = diff(&older, &newer, Duration::from_secs(1), &Default::default());
+        assert_eq!(10.0, result.read_bytes_per_second);
+    }
+
+    #[test]
+    fn test_activity() {
+        assert_eq!(0.0, DiskLoad::default().activity_0_to_1());
+
+        let loud = DiskLoad {
+            read_bytes_per_second: LOUD_BYTES_PER_SECOND,
+            write_bytes_per_second: LOUD_BYTES_PER_SECOND,
+            average_queue_length: 0.0,
+        };
+        assert_eq!(1.0, loud.activity_0_to_1());
+    }
+}
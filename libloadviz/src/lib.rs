@@ -1,15 +1,47 @@
 #![allow(clippy::needless_return)]
 
-use std::time::Instant;
+use std::{ffi::CString, fs::File, io::BufWriter, path::Path, time::Duration};
 
-use physics::update_currently_displayed_loads;
-use system_load::get_load_counters;
+use load_source::LocalLoadSource;
 
+pub use builder::LoadVizBuilder;
+pub use cpuload::CpuLoad;
+pub use device_filter::DeviceFilter;
+pub use load_source::LoadSource;
+pub use physics::AnimationSpeed;
+pub use render_target::{PixelFormat, RenderTarget};
+pub use system_load::Sample;
+pub use theme::Theme;
+
+pub mod battery;
+mod builder;
+mod c_api;
 pub mod cpuload;
+pub mod device_filter;
+pub mod diskload;
 mod load_reader;
+pub mod load_source;
+pub mod netload;
 mod physics;
+pub mod pipe;
+pub mod power;
+pub mod producer;
+pub mod prometheus;
+pub mod push;
+pub mod remote;
+pub mod render_target;
+pub mod replay;
+pub mod sar;
+pub mod scenario;
+pub mod shared;
 
 pub mod system_load;
+pub mod theme;
+pub mod trace;
+pub mod wall;
+
+#[cfg(test)]
+mod test_util;
 
 // Public for benchmarking purposes only
 pub mod renderer;
@@ -23,8 +55,7 @@ pub struct LoadViz {
 
     /// What we're currently displaying. This will constantly be animated
     /// towards the current system load.
-    currently_displayed_loads: Vec<cpuload::CpuLoad>,
-    currently_displayed_loads_updated: std::time::Instant,
+    displayed: physics::DisplayedState,
 
     /// When this object was created
     t0: std::time::Instant,
@@ -32,66 +63,395 @@ pub struct LoadViz {
     load_reader: load_reader::LoadReader,
 
     renderer: renderer::Renderer,
+
+    /// If false, only the CPU load is drawn, no rain, wind, sky or glow
+    weather_enabled: bool,
+
+    /// Where [`c_api::loadviz_push_loads()`] pushes to, if it's been called
+    /// since the load source was last changed
+    push_handle: Option<push::PushHandle>,
+
+    /// Set by [`c_api::loadviz_set_load_callback()`], and cleared when the
+    /// load source is changed
+    callback_status: Option<push::CallbackStatus>,
+
+    /// Owns the string returned by [`c_api::loadviz_load_callback_error()`]
+    callback_error: Option<CString>,
+
+    /// Set when a C API call fails, see [`c_api::loadviz_last_error()`]
+    last_error: Option<CString>,
 }
 
 impl LoadViz {
-    pub(crate) fn get_image(&mut self, width: usize, height: usize) -> *const u8 {
+    /// Replace the local machine with some other source of samples. A
+    /// [`replay::ReplaySource`] for example.
+    pub fn set_load_source(&mut self, source: Box<dyn LoadSource>) {
+        self.load_reader.set_source(source);
+        self.push_handle = None;
+        self.callback_status = None;
+    }
+
+    /// Decide which disks should make it rain. By default, loop and RAM
+    /// devices are excluded.
+    pub fn set_disk_filter(&mut self, filter: device_filter::DeviceFilter) {
+        self.load_reader.disk_filter = filter;
+    }
+
+    /// Decide which network interfaces should make the wind blow. By default,
+    /// loopback and virtual bridge interfaces are excluded.
+    pub fn set_network_filter(&mut self, filter: device_filter::DeviceFilter) {
+        self.load_reader.network_filter = filter;
+    }
+
+    /// Record all samples into a trace file, see the [`trace`] module for the
+    /// format. Any previous recording is stopped.
+    pub fn start_recording(&mut self, path: &Path) -> std::io::Result<()> {
+        let file: Box<dyn std::io::Write + Send> = Box::new(BufWriter::new(File::create(path)?));
+        self.load_reader.trace_writer = Some(trace::TraceWriter::new(file)?);
+        return Ok(());
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.load_reader.trace_writer = None;
+    }
+
+    /// Recording stops by itself if writing the trace file fails
+    pub fn is_recording(&self) -> bool {
+        return self.load_reader.trace_writer.is_some();
+    }
+
+    /// How often to ask the load source for a new sample, once per second by
+    /// default
+    pub fn set_sample_interval(&mut self, interval: Duration) {
+        self.load_reader.sample_interval = interval;
+    }
+
+    /// How fast the picture follows the load
+    pub fn set_animation_speed(&mut self, speed: AnimationSpeed) {
+        self.displayed.speed = speed;
+    }
+
+    /// Turn the disk / network / battery / power weather on or off. On by
+    /// default.
+    pub fn set_weather_enabled(&mut self, enabled: bool) {
+        self.weather_enabled = enabled;
+    }
+
+    /// Draw no background, for putting the picture on top of something else.
+    /// Flames and clouds fade to transparent instead of to the background
+    /// color of the theme. Render with
+    /// [`render_to()`](LoadViz::render_to) into a [`PixelFormat`] with alpha to
+    /// get the transparency, [`render()`](LoadViz::render) draws on black.
+    pub fn set_transparent_background(&mut self, transparent: bool) {
+        self.renderer.set_transparent_background(transparent);
+    }
+
+    /// Colors to draw in. [`Theme::preset()`] has some ready made ones.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.renderer.set_theme(theme);
+    }
+
+    /// Choose what the picture looks like: `classic`, `bars`, or anything
+    /// added with [`add_visualization()`](LoadViz::add_visualization)
+    pub fn set_visualization(&mut self, name: &str) -> Result<(), String> {
+        return self.renderer.select_visualization(name);
+    }
+
+    /// Make a look of your own available to
+    /// [`set_visualization()`](LoadViz::set_visualization)
+    pub fn add_visualization(&mut self, visualization: Box<dyn renderer::Visualization>) {
+        self.renderer.add_visualization(visualization);
+    }
+
+    pub fn visualization_names(&self) -> Vec<&str> {
+        return self.renderer.visualization_names();
+    }
+
+    /// False while a remote load source can't be reached
+    pub fn is_connected(&self) -> bool {
+        return self.load_reader.is_source_connected();
+    }
+
+    pub fn builder() -> LoadVizBuilder {
+        return Default::default();
+    }
+
+    /// Takes effect on the next [`render()`](LoadViz::render)
+    pub fn set_size(&mut self, width: usize, height: usize) {
         if width != self.width || height != self.height {
             self.width = width;
             self.height = height;
             self.pixels = vec![0; width * height * 3];
         }
+    }
+
+    /// Returns `(width, height)`
+    pub fn size(&self) -> (usize, usize) {
+        return (self.width, self.height);
+    }
+
+    /// Take one animation step and draw it. Call this once per frame.
+    pub fn render(&mut self) -> Image<'_> {
+        let mut pixels = std::mem::take(&mut self.pixels);
+        let mut target =
+            RenderTarget::packed(&mut pixels, self.width, self.height, PixelFormat::Rgb888)
+                .unwrap();
+        self.render_to(&mut target);
+        self.pixels = pixels;
+
+        return Image {
+            width: self.width,
+            height: self.height,
+            pixels: &self.pixels,
+        };
+    }
 
+    /// Like [`render()`](LoadViz::render), but into a buffer of your own, in
+    /// the pixel format of your choice. The size comes from the target, not
+    /// from [`set_size()`](LoadViz::set_size).
+    pub fn render_to(&mut self, target: &mut RenderTarget) {
         if self.load_reader.get_loads().is_empty() {
+            if !self.is_connected() {
+                // Never got through to the source, show an idle machine
+                // grayed out, rather than nothing
+                let idle = CpuLoad {
+                    user_0_to_1: 0.0,
+                    system_0_to_1: 0.0,
+                };
+                let disconnected = renderer::Weather {
+                    disconnected: true,
+                    ..Default::default()
+                };
+                self.renderer.render_to(
+                    &[idle],
+                    &disconnected,
+                    self.t0.elapsed().as_secs_f32(),
+                    target,
+                );
+            }
+
             // FIXME: Draw something nice?
-            return &self.pixels[0];
+            return;
         }
 
-        update_currently_displayed_loads(
-            &mut self.currently_displayed_loads,
-            &self.load_reader.get_loads(),
-            Instant::now().duration_since(self.currently_displayed_loads_updated),
-        );
-        self.currently_displayed_loads_updated = Instant::now();
+        self.displayed.update(&mut self.load_reader);
 
-        self.renderer.render_image(
-            &self.currently_displayed_loads,
-            self.width,
-            self.height,
+        let no_weather = renderer::Weather {
+            disconnected: self.displayed.weather.disconnected,
+            ..Default::default()
+        };
+        self.renderer.render_to(
+            &self.displayed.loads,
+            if self.weather_enabled {
+                &self.displayed.weather
+            } else {
+                &no_weather
+            },
             self.t0.elapsed().as_secs_f32(),
-            &mut self.pixels,
+            target,
         );
+    }
+
+    fn get_image(&mut self, width: usize, height: usize) -> *const u8 {
+        self.set_size(width, height);
+        return self.render().pixels.as_ptr();
+    }
+}
 
-        return &self.pixels[0];
+impl Default for LoadViz {
+    /// Shows the machine we're running on
+    fn default() -> Self {
+        return LoadViz::builder().build();
     }
 }
 
-#[no_mangle]
-pub extern "C" fn new_loadviz() -> *mut LoadViz {
-    return opaque_pointer::raw(LoadViz {
-        width: 0,
-        height: 0,
-        pixels: vec![0],
-        currently_displayed_loads: Vec::new(),
-        currently_displayed_loads_updated: std::time::Instant::now(),
-        t0: Instant::now(),
-        load_reader: load_reader::LoadReader::new(get_load_counters),
-        renderer: Default::default(),
-    });
+/// One frame, borrowed from the [`LoadViz`] that rendered it
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pub width: usize,
+    pub height: usize,
+
+    /// Size: 3 * width * height. Format: RGBRGBRGB..., top row first.
+    pub pixels: &'a [u8],
 }
 
-/// # Safety
+/// Set `LOADVIZ_REMOTE` to an agent address to watch another machine, see the
+/// [`remote`] module. Set `LOADVIZ_PROMETHEUS` to a node_exporter URL or file
+/// to watch a machine scraped by Prometheus. Set `LOADVIZ_PIPE` to a FIFO path,
+/// or to `-` for stdin, to show load from some other program, see the [`pipe`]
+/// module.
 ///
-/// This function is unsafe because it dereferences the incoming `loadviz`
-/// pointer. But as long as you get that from [`new_loadviz()`](new_loadviz) you
-/// should be fine.
-#[no_mangle]
-pub unsafe extern "C" fn get_image(
-    loadviz: *mut LoadViz,
-    width: usize,
-    height: usize,
-) -> *const u8 {
-    let loadviz = unsafe { opaque_pointer::mut_object(loadviz) };
-    let loadviz = loadviz.unwrap();
-    return loadviz.get_image(width, height);
+/// Set the `LOADVIZ_SCENARIO` environment variable to a preset name or a
+/// scenario file path to show synthetic load instead of the real one. See the
+/// [`scenario`] module for details.
+///
+/// Without any of these, this is the machine we're running on.
+fn get_initial_load_source() -> Result<Box<dyn LoadSource>, String> {
+    return load_source_from_environment(|name| std::env::var(name).ok());
+}
+
+/// Does [`get_initial_load_source()`], looking up environment variables using
+/// `getenv`
+fn load_source_from_environment(
+    getenv: impl Fn(&str) -> Option<String>,
+) -> Result<Box<dyn LoadSource>, String> {
+    if let Some(address) = getenv("LOADVIZ_REMOTE") {
+        let address = remote::RemoteAddress::parse(&address);
+        return Ok(Box::new(remote::RemoteSource::new(address)));
+    }
+
+    if let Some(pipe) = getenv("LOADVIZ_PIPE") {
+        if pipe == "-" {
+            return Ok(Box::new(pipe::PipeSource::stdin()));
+        }
+        return Ok(Box::new(pipe::PipeSource::open(std::path::Path::new(
+            &pipe,
+        ))));
+    }
+
+    if let Some(location) = getenv("LOADVIZ_PROMETHEUS") {
+        let location = prometheus::MetricsLocation::parse(&location)
+            .map_err(|error| format!("LOADVIZ_PROMETHEUS: {}", error))?;
+        return Ok(Box::new(prometheus::PrometheusSource::new(location)));
+    }
+
+    let Some(scenario) = getenv("LOADVIZ_SCENARIO") else {
+        return Ok(Box::new(LocalLoadSource));
+    };
+    let scenario =
+        load_scenario(&scenario).map_err(|error| format!("LOADVIZ_SCENARIO: {}", error))?;
+    return Ok(Box::new(scenario::ScenarioSource::new(scenario)));
+}
+
+/// `name_or_path` is either a preset name or a scenario file path
+fn load_scenario(name_or_path: &str) -> Result<scenario::Scenario, String> {
+    return load_preset_or_file(
+        name_or_path,
+        scenario::Scenario::preset,
+        &scenario::Scenario::preset_names(),
+        scenario::Scenario::parse,
+    );
+}
+
+/// `name_or_path` is either a preset name or a theme file path
+fn load_theme(name_or_path: &str) -> Result<theme::Theme, String> {
+    return load_preset_or_file(
+        name_or_path,
+        theme::Theme::preset,
+        &theme::Theme::preset_names(),
+        theme::Theme::parse,
+    );
+}
+
+/// Look `name_or_path` up using `preset`, and if that finds nothing, read it
+/// as a file and `parse` the contents
+fn load_preset_or_file<T, E: std::fmt::Display>(
+    name_or_path: &str,
+    preset: impl Fn(&str) -> Option<T>,
+    preset_names: &[&str],
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<T, String> {
+    if let Some(preset) = preset(name_or_path) {
+        return Ok(preset);
+    }
+
+    let text = std::fs::read_to_string(name_or_path).map_err(|error| {
+        format!(
+            "Neither a preset ({}) nor a readable file: {}: {}",
+            preset_names.join(", "),
+            name_or_path,
+            error
+        )
+    })?;
+    return parse(&text).map_err(|error| format!("{}: {}", name_or_path, error));
+}
+
+/// Like the environment variables in [`get_initial_load_source()`], but all in
+/// one string: `local`, `scenario:NAME`, `replay:PATH`, `remote:ADDRESS`,
+/// `prometheus:URL` or `pipe:PATH`.
+fn parse_load_source(spec: &str) -> Result<Box<dyn LoadSource>, (c_api::LoadVizStatus, String)> {
+    let invalid = |message: String| (c_api::LoadVizStatus::InvalidArgument, message);
+
+    if spec == "local" {
+        return Ok(Box::new(LocalLoadSource));
+    }
+    let Some((kind, argument)) = spec.split_once(':') else {
+        return Err(invalid(format!("Unknown load source: {}", spec)));
+    };
+
+    return match kind {
+        "scenario" => {
+            let scenario = load_scenario(argument).map_err(invalid)?;
+            Ok(Box::new(scenario::ScenarioSource::new(scenario)))
+        }
+        "replay" => {
+            let file = File::open(argument).map_err(|error| {
+                (
+                    c_api::LoadVizStatus::IoError,
+                    format!("{}: {}", argument, error),
+                )
+            })?;
+            let reader = trace::TraceReader::new(std::io::BufReader::new(file))
+                .map_err(|error| invalid(format!("{}: {}", argument, error)))?;
+            let replay = replay::ReplaySource::from_trace(reader)
+                .map_err(|error| invalid(format!("{}: {}", argument, error)))?;
+            Ok(Box::new(replay))
+        }
+        "remote" => Ok(Box::new(remote::RemoteSource::new(
+            remote::RemoteAddress::parse(argument),
+        ))),
+        "prometheus" => {
+            let location = prometheus::MetricsLocation::parse(argument).map_err(invalid)?;
+            Ok(Box::new(prometheus::PrometheusSource::new(location)))
+        }
+        "pipe" if argument == "-" => Ok(Box::new(pipe::PipeSource::stdin())),
+        "pipe" => Ok(Box::new(pipe::PipeSource::open(Path::new(argument)))),
+        _ => Err(invalid(format!("Unknown load source: {}", spec))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bad_environment() {
+        let getenv = |variable: &'static str, value: &'static str| {
+            return move |name: &str| {
+                if name == variable {
+                    return Some(value.to_string());
+                }
+                return None;
+            };
+        };
+
+        assert!(load_source_from_environment(getenv("LOADVIZ_SCENARIO", "demo")).is_ok());
+        let error = load_source_from_environment(getenv("LOADVIZ_SCENARIO", "no-such-scenario"))
+            .err()
+            .unwrap();
+        assert!(error.starts_with("LOADVIZ_SCENARIO: "), "{}", error);
+
+        let error = load_source_from_environment(getenv("LOADVIZ_PROMETHEUS", "https://x/"))
+            .err()
+            .unwrap();
+        assert!(error.starts_with("LOADVIZ_PROMETHEUS: "), "{}", error);
+    }
+
+    #[test]
+    fn test_never_connected() {
+        let mut loadviz = LoadViz::builder()
+            .load_source(Box::new(remote::RemoteSource::new(
+                remote::RemoteAddress::parse("127.0.0.1:1"),
+            )))
+            .build();
+        loadviz.set_size(8, 6);
+        let image = loadviz.render();
+
+        // The sky, in gray
+        assert!(image.pixels.iter().any(|&byte| byte != 0));
+        for pixel in image.pixels.chunks(3) {
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[0], pixel[2]);
+        }
+    }
 }
@@ -1,51 +1,184 @@
-use std::time::Instant;
+use std::io::Write;
+use std::time::{Duration, Instant};
 
-use crate::cpuload::{diff, CpuLoad, LoadCounters};
+use crate::battery::BatteryState;
+use crate::cpuload::{diff, CpuLoad};
+use crate::device_filter::DeviceFilter;
+use crate::diskload::{self, DiskLoad};
+use crate::load_source::LoadSource;
+use crate::netload::{self, NetworkLoad};
+use crate::power::{self, PowerDraw};
+use crate::system_load::Sample;
+use crate::trace::TraceWriter;
 
-static SECONDS_BETWEEN_MEASUREMENTS: u64 = 1;
+static DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
 
 struct LoadState {
     last_update_done: Instant,
-    older_sample: Vec<LoadCounters>,
-    newer_sample: Vec<LoadCounters>,
+    older_sample: Option<Sample>,
+    newer_sample: Option<Sample>,
 }
 
 pub(crate) struct LoadReader {
     last_result: Vec<CpuLoad>,
+    last_disk_result: DiskLoad,
+    last_network_result: NetworkLoad,
+    last_battery_result: Option<BatteryState>,
+    last_power_result: PowerDraw,
     state: LoadState,
-    get_load_counters: fn() -> Vec<LoadCounters>,
+    source: Box<dyn LoadSource>,
+    pub(crate) disk_filter: DeviceFilter,
+    pub(crate) network_filter: DeviceFilter,
+
+    /// How often to ask the source for a new sample
+    pub(crate) sample_interval: Duration,
+
+    /// If set, all samples get recorded into this
+    pub(crate) trace_writer: Option<TraceWriter<Box<dyn Write + Send>>>,
 }
 
 impl LoadReader {
-    pub(crate) fn new(get_load_counters: fn() -> Vec<LoadCounters>) -> LoadReader {
+    pub(crate) fn new(source: Box<dyn LoadSource>) -> LoadReader {
         let mut return_me = LoadReader {
             last_result: vec![],
+            last_disk_result: Default::default(),
+            last_network_result: Default::default(),
+            last_battery_result: None,
+            last_power_result: Default::default(),
             state: LoadState {
                 last_update_done: Instant::now(),
-                older_sample: vec![],
-                newer_sample: vec![],
+                older_sample: None,
+                newer_sample: None,
             },
-            get_load_counters,
+            source,
+
+            // Loop devices are backed by files on other disks, and RAM disks
+            // aren't disks
+            disk_filter: DeviceFilter::excluding(&["loop*", "ram*", "zram*"]),
+
+            // Loopback traffic never leaves the machine, and traffic on
+            // container / VM bridges shows up on the physical interfaces as
+            // well
+            network_filter: DeviceFilter::excluding(&["lo", "veth*", "docker*", "br-*", "virbr*"]),
+
+            trace_writer: None,
+
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
         };
 
-        return_me.measure_cpu_loads();
+        return_me.measure_loads();
 
         return return_me;
     }
 
+    /// Start reading from another source, forgetting everything we got from
+    /// the previous one
+    pub(crate) fn set_source(&mut self, source: Box<dyn LoadSource>) {
+        self.source = source;
+        self.state.older_sample = None;
+        self.state.newer_sample = None;
+        self.last_result = vec![];
+        self.last_disk_result = Default::default();
+        self.last_network_result = Default::default();
+        self.last_battery_result = None;
+        self.last_power_result = Default::default();
+
+        self.measure_loads();
+    }
+
     pub(crate) fn get_loads(&mut self) -> Vec<CpuLoad> {
-        if self.state.last_update_done.elapsed().as_secs() > SECONDS_BETWEEN_MEASUREMENTS {
-            self.measure_cpu_loads();
-        }
+        self.measure_if_needed();
 
         return self.last_result.clone();
     }
 
-    fn measure_cpu_loads(&mut self) {
-        self.state.older_sample = self.state.newer_sample.clone();
-        self.state.newer_sample = (self.get_load_counters)();
+    pub(crate) fn get_disk_load(&mut self) -> DiskLoad {
+        self.measure_if_needed();
+
+        return self.last_disk_result;
+    }
+
+    pub(crate) fn get_network_load(&mut self) -> NetworkLoad {
+        self.measure_if_needed();
+
+        return self.last_network_result;
+    }
+
+    pub(crate) fn get_battery_state(&mut self) -> Option<BatteryState> {
+        self.measure_if_needed();
+
+        return self.last_battery_result;
+    }
+
+    pub(crate) fn get_power_draw(&mut self) -> PowerDraw {
+        self.measure_if_needed();
 
-        self.last_result = diff(&self.state.older_sample, &self.state.newer_sample);
+        return self.last_power_result;
+    }
+
+    pub(crate) fn is_source_connected(&self) -> bool {
+        return self.source.is_connected();
+    }
+
+    fn measure_if_needed(&mut self) {
+        if self.state.last_update_done.elapsed() > self.sample_interval {
+            self.measure_loads();
+        }
+    }
+
+    fn measure_loads(&mut self) {
         self.state.last_update_done = Instant::now();
+
+        let Some(sample) = self.source.get_sample() else {
+            return;
+        };
+
+        if let Some(newer) = &self.state.newer_sample {
+            if sample.timestamp == newer.timestamp {
+                // Nothing new, a paused replay for example. Keep showing what
+                // we're showing.
+                return;
+            }
+            if sample.timestamp < newer.timestamp {
+                // We jumped backwards in time, a replay seek or loop for
+                // example. Diffing across the jump would give us garbage, so
+                // start over.
+                self.state.newer_sample = None;
+            }
+        }
+
+        if let Some(trace_writer) = &mut self.trace_writer {
+            if trace_writer.write_sample(&sample).is_err() {
+                // Disk full or similar, stop recording. Users can find out
+                // about this through LoadViz::is_recording().
+                self.trace_writer = None;
+            }
+        }
+
+        self.state.older_sample = self.state.newer_sample.take();
+        self.state.newer_sample = Some(sample);
+
+        let newer = self.state.newer_sample.as_ref().unwrap();
+        let Some(older) = &self.state.older_sample else {
+            // First sample, diffing will give us the correct number of cores
+            // but with zero load
+            self.last_result = diff(&[], &newer.cpus);
+            self.last_battery_result = newer.battery;
+            return;
+        };
+
+        // Use the sample timestamps rather than our own clock, so that we get
+        // this right when replaying recorded samples as well
+        let dt = newer
+            .timestamp
+            .duration_since(older.timestamp)
+            .unwrap_or(Duration::ZERO);
+
+        self.last_result = diff(&older.cpus, &newer.cpus);
+        self.last_disk_result = diskload::diff(&older.disks, &newer.disks, dt, &self.disk_filter);
+        self.last_network_result =
+            netload::diff(&older.networks, &newer.networks, dt, &self.network_filter);
+        self.last_battery_result = newer.battery;
+        self.last_power_result = power::diff(&older.energy, &newer.energy, dt);
     }
 }
@@ -0,0 +1,26 @@
+use crate::system_load::{self, Sample};
+
+/// Where `LoadViz` gets its samples from
+pub trait LoadSource: Send {
+    /// Called about once per second.
+    ///
+    /// Returning the same sample as last time means nothing has changed, and
+    /// returning a sample that is older than the previous one means the
+    /// source jumped backwards in time. `None` means no sample is available.
+    fn get_sample(&mut self) -> Option<Sample>;
+
+    /// Sources watching other machines return `false` while they can't reach
+    /// them, and the picture is drawn as disconnected.
+    fn is_connected(&self) -> bool {
+        return true;
+    }
+}
+
+/// Samples the machine we're running on
+pub struct LocalLoadSource;
+
+impl LoadSource for LocalLoadSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return Some(system_load::get_sample());
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use crate::device_filter::DeviceFilter;
+
+/// For interfaces with unknown link speed, below this is considered idle
+static QUIET_BYTES_PER_SECOND: f32 = 10_000.0;
+
+/// For interfaces with unknown link speed, at this point and above the network
+/// is considered maxed out
+static LOUD_BYTES_PER_SECOND: f32 = 100_000_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkCounters {
+    pub interface: String,
+
+    pub bytes_received: usize,
+    pub bytes_sent: usize,
+
+    /// `None` if unknown, for example for wireless or virtual interfaces
+    pub link_speed_bytes_per_second: Option<usize>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct NetworkLoad {
+    pub received_bytes_per_second: f32,
+    pub sent_bytes_per_second: f32,
+
+    /// How close the busiest interface is to its link speed, 0 is idle, 1 is
+    /// maxed out
+    pub received_0_to_1: f32,
+
+    /// How close the busiest interface is to its link speed, 0 is idle, 1 is
+    /// maxed out
+    pub sent_0_to_1: f32,
+}
+
+impl NetworkLoad {
+    /// Sending makes the wind blow right (positive), receiving makes it blow
+    /// left (negative). The strength is decided by whichever of the two
+    /// directions is busier.
+    pub fn wind_m1_to_1(&self) -> f32 {
+        if self.sent_0_to_1 >= self.received_0_to_1 {
+            return self.sent_0_to_1;
+        }
+        return -self.received_0_to_1;
+    }
+}
+
+/// Based on two network counter snapshots taken `dt` apart, compute the total
+/// network load for all interfaces matching the filter.
+///
+/// Interfaces that are only present in one of the snapshots are ignored.
+pub fn diff(
+    older: &[NetworkCounters],
+    newer: &[NetworkCounters],
+    dt: Duration,
+    filter: &DeviceFilter,
+) -> NetworkLoad {
+    let seconds = dt.as_secs_f32();
+    if seconds <= 0.0 {
+        return NetworkLoad::default();
+    }
+
+    let mut result = NetworkLoad::default();
+    for newer in newer.iter() {
+        if !filter.matches(&newer.interface) {
+            continue;
+        }
+
+        let older = older
+            .iter()
+            .find(|older| older.interface == newer.interface);
+        let Some(older) = older else {
+            // New interface, no diff possible
+            continue;
+        };
+
+        let (Some(bytes_received), Some(bytes_sent)) = (
+            newer.bytes_received.checked_sub(older.bytes_received),
+            newer.bytes_sent.checked_sub(older.bytes_sent),
+        ) else {
+            // Counters reset, for example by the interface being re-created
+            continue;
+        };
+        let received_bytes_per_second = bytes_received as f32 / seconds;
+        let sent_bytes_per_second = bytes_sent as f32 / seconds;
+
+        result.received_bytes_per_second += received_bytes_per_second;
+        result.sent_bytes_per_second += sent_bytes_per_second;
+
+        let link_speed = newer.link_speed_bytes_per_second;
+        result.received_0_to_1 = result
+            .received_0_to_1
+            .max(utilization_0_to_1(received_bytes_per_second, link_speed));
+        result.sent_0_to_1 = result
+            .sent_0_to_1
+            .max(utilization_0_to_1(sent_bytes_per_second, link_speed));
+    }
+
+    return result;
+}
+
+fn utilization_0_to_1(bytes_per_second: f32, link_speed_bytes_per_second: Option<usize>) -> f32 {
+    if let Some(link_speed) = link_speed_bytes_per_second {
+        if link_speed > 0 {
+            return (bytes_per_second / link_speed as f32).clamp(0.0, 1.0);
+        }
+    }
+
+    // Unknown link speed, go logarithmic since network throughput varies by
+    // orders of magnitude.
+    let utilization = (bytes_per_second / QUIET_BYTES_PER_SECOND).log10()
+        / (LOUD_BYTES_PER_SECOND / QUIET_BYTES_PER_SECOND).log10();
+
+    // Note that this clamps the -inf we get from zero throughput as well
+    return utilization.clamp(0.0, 1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(
+        interface: &str,
+        bytes_received: usize,
+        bytes_sent: usize,
+        link_speed_bytes_per_second: Option<usize>,
+    ) -> NetworkCounters {
+        return NetworkCounters {
+            interface: interface.to_string(),
+            bytes_received,
+            bytes_sent,
+            link_speed_bytes_per_second,
+        };
+    }
+
+    #[test]
+    fn test_diff_link_speed() {
+        let older = vec![
+            counters("eth0", 0, 0, Some(1000)),
+            counters("lo", 0, 0, None),
+        ];
+        let newer = vec![
+            counters("eth0", 1000, 2000, Some(1000)),
+            counters("lo", 9000, 9000, None),
+        ];
+
+        let result = diff(
+            &older,
+            &newer,
+            Duration::from_secs(4),
+            &DeviceFilter::excluding(&["lo"]),
+        );
+        assert_eq!(250.0, result.received_bytes_per_second);
+        assert_eq!(500.0, result.sent_bytes_per_second);
+        assert_eq!(0.25, result.received_0_to_1);
+        assert_eq!(0.5, result.sent_0_to_1);
+        assert_eq!(0.5, result.wind_m1_to_1());
+    }
+
+    /// Test that an interface whose counters went back to zero is skipped
+    #[test]
+    fn test_diff_reset() {
+        let older = vec![
+            counters("tun0", usize::MAX, 0, Some(100)),
+            counters("eth0", 0, 0, Some(100)),
+        ];
+        let newer = vec![
+            counters("tun0", 9, 0, Some(100)),
+            counters("eth0", 0, 10, Some(100)),
+        ];
+
+        let result = diff(&older, &newer, Duration::from_secs(1), &Default::default());
+        assert_eq!(0.0, result.received_bytes_per_second);
+        assert_eq!(10.0, result.sent_bytes_per_second);
+        assert_eq!(0.1, result.wind_m1_to_1());
+    }
+
+    #[test]
+    fn test_unknown_link_speed() {
+        assert_eq!(0.0, utilization_0_to_1(0.0, None));
+        assert_eq!(0.0, utilization_0_to_1(QUIET_BYTES_PER_SECOND, None));
+        assert_eq!(1.0, utilization_0_to_1(LOUD_BYTES_PER_SECOND, None));
+    }
+}
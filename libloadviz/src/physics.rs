@@ -1,8 +1,10 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::cpuload::CpuLoad;
+use crate::load_reader::LoadReader;
+use crate::renderer::Weather;
 
-// Maybe keep these higher than SECONDS_BETWEEN_MEASUREMENTS in load_reader.rs?
+// Maybe keep these higher than DEFAULT_SAMPLE_INTERVAL in load_reader.rs?
 // By moving the values around until we're happy!
 //
 // The idea with moving up fast is that we want to react when something happens.
@@ -11,10 +13,98 @@ use crate::cpuload::CpuLoad;
 static SECONDS_0_TO_100_UP: f32 = 5.0;
 static SECONDS_0_TO_100_DOWN: f32 = 20.0;
 
+/// How fast the picture follows the load
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationSpeed {
+    /// How long it takes to animate from no load to full load
+    pub seconds_0_to_100_up: f32,
+
+    /// How long it takes to animate from full load to no load
+    pub seconds_0_to_100_down: f32,
+}
+
+impl Default for AnimationSpeed {
+    fn default() -> Self {
+        return Self {
+            seconds_0_to_100_up: SECONDS_0_TO_100_UP,
+            seconds_0_to_100_down: SECONDS_0_TO_100_DOWN,
+        };
+    }
+}
+
+/// What we're currently displaying, constantly animated towards what the
+/// [`LoadReader`] says.
+pub(crate) struct DisplayedState {
+    pub(crate) loads: Vec<CpuLoad>,
+
+    /// Animated towards the current disk / network / ... load, just like
+    /// `loads`.
+    pub(crate) weather: Weather,
+
+    pub(crate) speed: AnimationSpeed,
+
+    updated: Instant,
+}
+
+impl DisplayedState {
+    pub(crate) fn new() -> DisplayedState {
+        return DisplayedState {
+            loads: Vec::new(),
+            weather: Default::default(),
+            speed: Default::default(),
+            updated: Instant::now(),
+        };
+    }
+
+    /// Take one animation step towards the latest measurements
+    pub(crate) fn update(&mut self, load_reader: &mut LoadReader) {
+        let dt = Instant::now().duration_since(self.updated);
+        let speed = &self.speed;
+        update_currently_displayed_loads(&mut self.loads, &load_reader.get_loads(), dt, speed);
+        update_currently_displayed_value(
+            &mut self.weather.rain_0_to_1,
+            load_reader.get_disk_load().activity_0_to_1(),
+            dt,
+            speed,
+        );
+        update_currently_displayed_value(
+            &mut self.weather.wind_m1_to_1,
+            load_reader.get_network_load().wind_m1_to_1(),
+            dt,
+            speed,
+        );
+        self.weather.wind_distance += self.weather.wind_m1_to_1 * dt.as_secs_f32();
+        match (&mut self.weather.battery, load_reader.get_battery_state()) {
+            (Some(current), Some(target)) => {
+                update_currently_displayed_value(
+                    &mut current.charge_0_to_1,
+                    target.charge_0_to_1,
+                    dt,
+                    speed,
+                );
+                current.charging = target.charging;
+            }
+            (current, target) => {
+                // Battery appeared or disappeared
+                *current = target;
+            }
+        }
+        update_currently_displayed_value(
+            &mut self.weather.glow_0_to_1,
+            load_reader.get_power_draw().package_load_0_to_1(),
+            dt,
+            speed,
+        );
+        self.weather.disconnected = !load_reader.is_source_connected();
+        self.updated = Instant::now();
+    }
+}
+
 pub(crate) fn update_currently_displayed_loads(
     current: &mut Vec<CpuLoad>,
     target: &Vec<CpuLoad>,
     dt: Duration,
+    speed: &AnimationSpeed,
 ) {
     if current.len() != target.len() {
         // current = target;
@@ -30,8 +120,9 @@ pub(crate) fn update_currently_displayed_loads(
     cpu_loads.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
     for (current, actual) in current.iter_mut().zip(cpu_loads.iter_mut()) {
-        current.user_0_to_1 += compute_step(dt, current.user_0_to_1, actual.user_0_to_1);
-        current.system_0_to_1 += compute_step(dt, current.system_0_to_1, actual.system_0_to_1);
+        current.user_0_to_1 += compute_step(dt, current.user_0_to_1, actual.user_0_to_1, speed);
+        current.system_0_to_1 +=
+            compute_step(dt, current.system_0_to_1, actual.system_0_to_1, speed);
 
         if current.user_0_to_1 + current.system_0_to_1 <= 1.0 {
             continue;
@@ -48,17 +139,28 @@ pub(crate) fn update_currently_displayed_loads(
     }
 }
 
+/// Animate a single value towards its target, with the same speed limits as
+/// the CPU loads.
+pub(crate) fn update_currently_displayed_value(
+    current: &mut f32,
+    target: f32,
+    dt: Duration,
+    speed: &AnimationSpeed,
+) {
+    *current += compute_step(dt, *current, target, speed);
+}
+
 /// How far should we step towards the goal value?
 ///
 /// `dt` is the time since the last update
-fn compute_step(dt: Duration, current: f32, goal: f32) -> f32 {
+fn compute_step(dt: Duration, current: f32, goal: f32, speed: &AnimationSpeed) -> f32 {
     let direction = if goal > current { 1.0 } else { -1.0 };
 
     let how_far_we_can_go = dt.as_secs_f32()
         / (if direction > 0.0 {
-            SECONDS_0_TO_100_UP
+            speed.seconds_0_to_100_up
         } else {
-            SECONDS_0_TO_100_DOWN
+            speed.seconds_0_to_100_down
         });
 
     let how_far_we_are_allowed_to_go = (goal - current).abs();
@@ -73,12 +175,12 @@ mod tests {
     #[test]
     fn test_compute_step() {
         let dt = Duration::from_secs_f32(SECONDS_0_TO_100_UP / 2.0);
-        assert_eq!(compute_step(dt, 0.0, 1.0), 0.5);
-        assert_eq!(compute_step(dt, 0.0, 0.1), 0.1);
+        assert_eq!(compute_step(dt, 0.0, 1.0, &Default::default()), 0.5);
+        assert_eq!(compute_step(dt, 0.0, 0.1, &Default::default()), 0.1);
 
         let dt = Duration::from_secs_f32(SECONDS_0_TO_100_DOWN / 2.0);
-        assert_eq!(compute_step(dt, 1.0, 0.0), -0.5);
-        assert_eq!(compute_step(dt, 1.0, 0.7), -0.3);
+        assert_eq!(compute_step(dt, 1.0, 0.0, &Default::default()), -0.5);
+        assert_eq!(compute_step(dt, 1.0, 0.7, &Default::default()), -0.3);
     }
 
     #[test]
@@ -97,6 +199,7 @@ mod tests {
             &mut current,
             &target,
             Duration::from_secs(SECONDS_0_TO_100_UP as u64),
+            &Default::default(),
         );
 
         assert_eq!(
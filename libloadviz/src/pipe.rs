@@ -0,0 +1,449 @@
+//! Load from any program that can print lines, through stdin or a FIFO.
+//!
+//! Each line is the current load of each core, as fractions between 0 and 1.
+//! Lines are either JSON:
+//!
+//! ```text
+//! {"cpus": [{"user": 0.5, "system": 0.1}, {"user": 0.2, "system": 0.0}], "label": "build-01"}
+//! {"cpus": [[0.5, 0.1], [0.2, 0.0]]}
+//! ```
+//!
+//! Or CSV, with user and system columns for each core:
+//!
+//! ```text
+//! 0.5,0.1,0.2,0.0
+//! ```
+//!
+//! CSV input can start with a header line naming the columns. A column named
+//! `label` holds the label, the other columns are user and system loads:
+//!
+//! ```text
+//! label,cpu0_user,cpu0_system,cpu1_user,cpu1_system
+//! build-01,0.5,0.1,0.2,0.0
+//! ```
+//!
+//! Labels are optional, and are available through [`PipeStatus::label()`].
+//! Missing loads count as zero. If user + system load for a core is more than
+//! 1, system load is reduced.
+//!
+//! Lines that can't be parsed, and lines longer than 64 kB, are skipped.
+//! [`PipeStatus`] counts them and says why the latest one was skipped.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::cpuload::{make_load, CpuLoad, SyntheticCounters};
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+
+mod json;
+
+/// Longer lines are skipped, rather than buffered until we run out of memory
+static MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+/// Reads samples from a pipe on a background thread
+pub struct PipeSource {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Tells you how reading the pipe is going. Clones share the same status.
+#[derive(Clone)]
+pub struct PipeStatus {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Option<Sample>,
+    label: Option<String>,
+    skipped_line_count: usize,
+    last_error: Option<String>,
+}
+
+/// One parsed line
+#[derive(Debug, PartialEq)]
+struct PipeLine {
+    loads: Vec<CpuLoad>,
+    label: Option<String>,
+}
+
+/// Parsing state that carries over between lines
+#[derive(Default)]
+struct PipeParser {
+    /// Column names from the CSV header, if we got one
+    csv_header: Option<Vec<String>>,
+
+    /// Accumulated load, one entry per core
+    counters: SyntheticCounters,
+}
+
+impl PipeSource {
+    pub fn new<R: BufRead + Send + 'static>(reader: R) -> PipeSource {
+        let shared: Arc<Mutex<Shared>> = Default::default();
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            let mut parser: PipeParser = Default::default();
+            read_lines(reader, &mut parser, &thread_shared);
+        });
+
+        return PipeSource { shared };
+    }
+
+    pub fn stdin() -> PipeSource {
+        return PipeSource::new(BufReader::new(io::stdin()));
+    }
+
+    /// Read from a FIFO or a file. FIFOs are reopened whenever the writer goes
+    /// away, so that the producer can be restarted.
+    pub fn open(path: &Path) -> PipeSource {
+        let shared: Arc<Mutex<Shared>> = Default::default();
+        let thread_shared = shared.clone();
+        let path = PathBuf::from(path);
+        std::thread::spawn(move || {
+            let mut parser: PipeParser = Default::default();
+            loop {
+                // Opening a FIFO blocks until somebody opens it for writing
+                let file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(error) => {
+                        report(&thread_shared, format!("{}: {}", path.display(), error));
+                        return;
+                    }
+                };
+                read_lines(BufReader::new(file), &mut parser, &thread_shared);
+
+                if !is_fifo(&path) {
+                    return;
+                }
+            }
+        });
+
+        return PipeSource { shared };
+    }
+
+    pub fn status(&self) -> PipeStatus {
+        return PipeStatus {
+            shared: self.shared.clone(),
+        };
+    }
+}
+
+impl LoadSource for PipeSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return self.shared.lock().unwrap().latest.clone();
+    }
+}
+
+impl PipeStatus {
+    /// The most recent label we got, if any
+    pub fn label(&self) -> Option<String> {
+        return self.shared.lock().unwrap().label.clone();
+    }
+
+    pub fn skipped_line_count(&self) -> usize {
+        return self.shared.lock().unwrap().skipped_line_count;
+    }
+
+    /// Why the most recently skipped line was skipped
+    pub fn last_error(&self) -> Option<String> {
+        return self.shared.lock().unwrap().last_error.clone();
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    return std::fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_fifo())
+        .unwrap_or(false);
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &Path) -> bool {
+    return false;
+}
+
+fn read_lines(mut reader: impl BufRead, parser: &mut PipeParser, shared: &Mutex<Shared>) {
+    for index in 0.. {
+        let mut bytes: Vec<u8> = vec![];
+        let length = match (&mut reader)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut bytes)
+        {
+            Ok(0) => return,
+            Ok(length) => length,
+            Err(error) => {
+                report(shared, format!("Line {}: {}", index + 1, error));
+                return;
+            }
+        };
+        if length as u64 == MAX_LINE_LENGTH && !bytes.ends_with(b"\n") {
+            report(
+                shared,
+                format!("Line {}: Longer than {} bytes", index + 1, MAX_LINE_LENGTH),
+            );
+            if let Err(error) = skip_rest_of_line(&mut reader) {
+                report(shared, format!("Line {}: {}", index + 1, error));
+                return;
+            }
+            continue;
+        }
+        let Ok(line) = String::from_utf8(bytes) else {
+            // The next line may be fine
+            report(shared, format!("Line {}: Not UTF-8", index + 1));
+            continue;
+        };
+
+        match parser.parse_line(&line) {
+            Ok(Some(parsed)) => {
+                let counters = parser.counters.add(&parsed.loads);
+                let mut shared = shared.lock().unwrap();
+                shared.latest = Some(Sample::cpus_only(SystemTime::now(), counters));
+                if parsed.label.is_some() {
+                    shared.label = parsed.label;
+                }
+            }
+            Ok(None) => {
+                // Header or empty line
+            }
+            Err(message) => report(shared, format!("Line {}: {}", index + 1, message)),
+        }
+    }
+}
+
+fn skip_rest_of_line(reader: &mut impl BufRead) -> io::Result<()> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        if let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+            reader.consume(newline + 1);
+            return Ok(());
+        }
+        let length = buffer.len();
+        reader.consume(length);
+    }
+}
+
+/// Note that a line was skipped
+fn report(shared: &Mutex<Shared>, message: String) {
+    let mut shared = shared.lock().unwrap();
+    shared.skipped_line_count += 1;
+    shared.last_error = Some(message);
+}
+
+impl PipeParser {
+    /// Returns `None` for lines without samples
+    fn parse_line(&mut self, line: &str) -> Result<Option<PipeLine>, String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if line.starts_with('{') {
+            return parse_json_line(line).map(Some);
+        }
+        return self.parse_csv_line(line);
+    }
+
+    fn parse_csv_line(&mut self, line: &str) -> Result<Option<PipeLine>, String> {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.iter().all(|field| field.parse::<f32>().is_err()) {
+            let header: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+            let load_columns = header.iter().filter(|name| *name != "label").count();
+            if !load_columns.is_multiple_of(2) {
+                return Err("Expected user and system columns for each core".to_string());
+            }
+            self.csv_header = Some(header);
+            return Ok(None);
+        }
+
+        let mut label: Option<String> = None;
+        let mut values: Vec<f32> = vec![];
+        for (index, field) in fields.iter().enumerate() {
+            if let Some(header) = &self.csv_header {
+                match header.get(index) {
+                    None => {
+                        return Err(format!(
+                            "Expected {} columns, got {}",
+                            header.len(),
+                            fields.len()
+                        ))
+                    }
+                    Some(name) if name == "label" => {
+                        label = Some(field.to_string());
+                        continue;
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            let value = field
+                .parse::<f32>()
+                .map_err(|_| format!("Not a number: {}", field))?;
+            values.push(value);
+        }
+        if let Some(header) = &self.csv_header {
+            if fields.len() != header.len() {
+                return Err(format!(
+                    "Expected {} columns, got {}",
+                    header.len(),
+                    fields.len()
+                ));
+            }
+        }
+        if !values.len().is_multiple_of(2) {
+            return Err("Expected user and system columns for each core".to_string());
+        }
+
+        let mut loads: Vec<CpuLoad> = vec![];
+        for pair in values.chunks(2) {
+            loads.push(make_load(pair[0], pair[1])?);
+        }
+        return Ok(Some(PipeLine { loads, label }));
+    }
+}
+
+fn parse_json_line(line: &str) -> Result<PipeLine, String> {
+    let parsed = json::parse(line)?;
+    let Some(json::Json::Array(cpus)) = parsed.get("cpus") else {
+        return Err("Expected a \"cpus\" array".to_string());
+    };
+
+    let mut loads: Vec<CpuLoad> = vec![];
+    for cpu in cpus {
+        let (user, system) = match cpu {
+            json::Json::Array(pair) => (pair.first(), pair.get(1)),
+            json::Json::Object(_) => (cpu.get("user"), cpu.get("system")),
+            _ => return Err("Expected each CPU to be an object or an array".to_string()),
+        };
+        loads.push(make_load(json_fraction(user)?, json_fraction(system)?)?);
+    }
+
+    let label = match parsed.get("label") {
+        Some(json::Json::String(label)) => Some(label.clone()),
+        None | Some(json::Json::Null) => None,
+        Some(_) => return Err("Expected \"label\" to be a string".to_string()),
+    };
+
+    return Ok(PipeLine { loads, label });
+}
+
+/// Missing values count as zero
+fn json_fraction(value: Option<&json::Json>) -> Result<f32, String> {
+    return match value {
+        None | Some(json::Json::Null) => Ok(0.0),
+        Some(json::Json::Number(number)) => Ok(*number as f32),
+        Some(other) => Err(format!("Expected a number, got {:?}", other)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let mut parser: PipeParser = Default::default();
+        assert_eq!(
+            parser.parse_line(
+                r#"{"cpus": [{"user": 0.5, "system": 0.25}, {"user": 0.25}], "label": "build-01"}"#
+            ),
+            Ok(Some(PipeLine {
+                loads: vec![CpuLoad::new(0.5, 0.25), CpuLoad::new(0.25, 0.0)],
+                label: Some("build-01".to_string()),
+            }))
+        );
+        assert_eq!(
+            parser.parse_line(r#"{"cpus": [[0.5, 0.75]]}"#),
+            Ok(Some(PipeLine {
+                // System load reduced to fit
+                loads: vec![CpuLoad::new(0.5, 0.5)],
+                label: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut parser: PipeParser = Default::default();
+        assert_eq!(
+            parser.parse_line("0.5,0.25,0,1"),
+            Ok(Some(PipeLine {
+                loads: vec![CpuLoad::new(0.5, 0.25), CpuLoad::new(0.0, 1.0)],
+                label: None,
+            }))
+        );
+
+        assert_eq!(parser.parse_line("label, cpu0_user, cpu0_system"), Ok(None));
+        assert_eq!(
+            parser.parse_line("build-01, 0.5, 0.25"),
+            Ok(Some(PipeLine {
+                loads: vec![CpuLoad::new(0.5, 0.25)],
+                label: Some("build-01".to_string()),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut parser: PipeParser = Default::default();
+        assert!(parser.parse_line("0.5").is_err());
+        assert!(parser.parse_line("0.5,banana").is_err());
+        assert!(parser.parse_line("1.5,0").is_err());
+        assert!(parser.parse_line("{\"cpus\": 5}").is_err());
+        assert!(parser.parse_line("{\"cpus\": [[\"x\", 0]]}").is_err());
+        assert!(parser.parse_line("{broken").is_err());
+        assert!(parser.parse_line("user,system,extra").is_err());
+
+        parser.parse_line("label,user,system").unwrap();
+        assert!(parser.parse_line("host,0.5,0.1,0.2").is_err());
+
+        // Still usable after errors
+        assert!(parser.parse_line("host,0.5,0.1").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_source() {
+        let input = "\
+0.5,0.25
+this is not a load
+{\"cpus\": [[0.25, 0.5]], \"label\": \"ci\"}
+";
+        let mut source = PipeSource::new(input.as_bytes());
+        let status = source.status();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while status.label().is_none() {
+            assert!(Instant::now() < deadline);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(Some("ci".to_string()), status.label());
+        assert_eq!(1, status.skipped_line_count());
+        assert!(status.last_error().unwrap().starts_with("Line 2:"));
+
+        let sample = source.get_sample().unwrap();
+        assert_eq!(1, sample.cpus.len());
+        assert_eq!(7500, sample.cpus[0].user);
+    }
+
+    #[test]
+    fn test_long_line() {
+        let mut input = "0,".repeat(MAX_LINE_LENGTH as usize).into_bytes();
+        input.extend_from_slice(b"\n\xff\n0.5,0.25\n");
+        let shared: Mutex<Shared> = Default::default();
+        read_lines(input.as_slice(), &mut Default::default(), &shared);
+
+        // The long line, and the one that isn't UTF-8
+        let shared = shared.lock().unwrap();
+        assert_eq!(2, shared.skipped_line_count);
+        assert_eq!(Some("Line 2: Not UTF-8".to_string()), shared.last_error);
+        assert_eq!(5000, shared.latest.as_ref().unwrap().cpus[0].user);
+    }
+}
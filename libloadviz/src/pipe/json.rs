@@ -0,0 +1,280 @@
+/// Arrays and objects nested deeper than this are an error. A sample is three
+/// levels deep, and without a limit, a long enough line of `[` would overflow
+/// the stack.
+static MAX_NESTING: usize = 8;
+
+/// Just enough JSON for reading one sample per line
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Look up a key in an object
+    pub(super) fn get(&self, key: &str) -> Option<&Json> {
+        let Json::Object(members) = self else {
+            return None;
+        };
+        return members
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value);
+    }
+}
+
+pub(super) fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        position: 0,
+        nesting: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(format!("Unexpected data at column {}", parser.position + 1));
+    }
+    return Ok(value);
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+
+    /// How many arrays and objects we're inside of
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        return match self.peek() {
+            Some(b'{' | b'[') if self.nesting >= MAX_NESTING => {
+                Err(format!("Nested too deep at column {}", self.position + 1))
+            }
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(Json::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", Json::Bool(true)),
+            Some(b'f') => self.parse_literal("false", Json::Bool(false)),
+            Some(b'n') => self.parse_literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(format!(
+                "Unexpected character at column {}",
+                self.position + 1
+            )),
+            None => Err("Unexpected end of line".to_string()),
+        };
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.nesting += 1;
+        let object = self.parse_object_members();
+        self.nesting -= 1;
+        return object;
+    }
+
+    fn parse_object_members(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut members: Vec<(String, Json)> = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            let value = self.parse_value()?;
+            members.push((name, value));
+
+            self.skip_whitespace();
+            if self.peek() == Some(b',') {
+                self.position += 1;
+                continue;
+            }
+            self.expect(b'}')?;
+            return Ok(Json::Object(members));
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.nesting += 1;
+        let array = self.parse_array_elements();
+        self.nesting -= 1;
+        return array;
+    }
+
+    fn parse_array_elements(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut elements: Vec<Json> = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(elements));
+        }
+
+        loop {
+            elements.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            if self.peek() == Some(b',') {
+                self.position += 1;
+                continue;
+            }
+            self.expect(b']')?;
+            return Ok(Json::Array(elements));
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut result: Vec<u8> = vec![];
+        loop {
+            let Some(byte) = self.peek() else {
+                return Err("Unterminated string".to_string());
+            };
+            self.position += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return Err("Unterminated string".to_string());
+                    };
+                    self.position += 1;
+                    match escaped {
+                        b'n' => result.push(b'\n'),
+                        b't' => result.push(b'\t'),
+                        b'r' => result.push(b'\r'),
+                        b'b' => result.push(0x08),
+                        b'f' => result.push(0x0c),
+                        b'u' => {
+                            let hex = self
+                                .bytes
+                                .get(self.position..self.position + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or("Bad \\u escape")?;
+                            self.position += 4;
+
+                            // Surrogate pairs are out of scope, use a
+                            // replacement character for those
+                            let c = char::from_u32(hex).unwrap_or(char::REPLACEMENT_CHARACTER);
+                            let mut buffer = [0u8; 4];
+                            result.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                        other => result.push(other),
+                    }
+                }
+                other => result.push(other),
+            }
+        }
+
+        // The input was a &str and we only split at ASCII characters, so this
+        // can't fail
+        return Ok(String::from_utf8(result).unwrap());
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        return text
+            .parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| format!("Not a number: {}", text));
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        if !self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            return Err(format!(
+                "Unexpected character at column {}",
+                self.position + 1
+            ));
+        }
+        self.position += literal.len();
+        return Ok(value);
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return Err(format!(
+                "Expected '{}' at column {}",
+                expected as char,
+                self.position + 1
+            ));
+        }
+        self.position += 1;
+        return Ok(());
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.bytes.get(self.position).copied();
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.position += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parsed = parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\"å", "c": {}} "#);
+        assert_eq!(
+            parsed,
+            Ok(Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.0),
+                        Json::Number(-25.0),
+                        Json::Bool(true),
+                        Json::Null
+                    ])
+                ),
+                ("b".to_string(), Json::String("x\"å".to_string())),
+                ("c".to_string(), Json::Object(vec![])),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("{").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert!(parse(r#""unterminated"#).is_err());
+        assert!(parse("nope").is_err());
+    }
+
+    #[test]
+    fn test_nesting() {
+        assert!(parse("[[[[[[[[1]]]]]]]]").is_ok());
+        assert!(parse("[[[[[[[[[1]]]]]]]]]").is_err());
+
+        // Must not overflow the stack
+        let deep = format!("{{\"cpus\": {}", "[".repeat(1_000_000));
+        assert_eq!(
+            Err("Nested too deep at column 17".to_string()),
+            parse(&deep)
+        );
+    }
+}
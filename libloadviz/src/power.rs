@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+/// If we don't know the package power limit, assume it's this. 65W is a common
+/// desktop CPU TDP.
+static FALLBACK_PACKAGE_LIMIT_WATTS: f32 = 65.0;
+
+/// One RAPL (Running Average Power Limit) energy counter
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyCounters {
+    /// Unique for each counter, `intel-rapl:0:1` for example
+    pub id: String,
+
+    /// `package-0`, `core`, `uncore`, `dram`, ...
+    pub name: String,
+
+    pub energy_microjoules: usize,
+
+    /// `energy_microjoules` wraps around to zero when it reaches this value
+    pub max_energy_range_microjoules: usize,
+
+    /// Long term power limit, only known for package domains. Roughly the
+    /// same thing as the TDP of the CPU.
+    pub power_limit_microwatts: Option<usize>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default)]
+pub struct PowerDraw {
+    /// Power drawn by all CPU packages, including cores, caches and memory
+    /// controllers
+    pub package_watts: f32,
+
+    /// Power drawn by the CPU cores only
+    pub core_watts: f32,
+
+    /// Power drawn by the RAM
+    pub dram_watts: f32,
+
+    /// Package power relative to the package power limits. 0 means idle, 1
+    /// means at the limit. `None` if the limits are unknown.
+    pub package_0_to_1: Option<f32>,
+}
+
+impl PowerDraw {
+    /// 0 means idle, 1 means the CPU packages are drawing as much power as
+    /// they are supposed to long term.
+    pub fn package_load_0_to_1(&self) -> f32 {
+        if let Some(package_0_to_1) = self.package_0_to_1 {
+            return package_0_to_1;
+        }
+
+        return (self.package_watts / FALLBACK_PACKAGE_LIMIT_WATTS).clamp(0.0, 1.0);
+    }
+}
+
+/// Based on two energy counter snapshots taken `dt` apart, compute the power
+/// draw.
+///
+/// Counters that are only present in one of the snapshots are ignored.
+pub fn diff(older: &[EnergyCounters], newer: &[EnergyCounters], dt: Duration) -> PowerDraw {
+    let seconds = dt.as_secs_f32();
+    if seconds <= 0.0 {
+        return PowerDraw::default();
+    }
+
+    let mut result = PowerDraw::default();
+    let mut package_limit_watts = 0.0;
+    for newer in newer.iter() {
+        let older = older.iter().find(|older| older.id == newer.id);
+        let Some(older) = older else {
+            // New counter, no diff possible
+            continue;
+        };
+
+        let Some(microjoules) = energy_delta_microjoules(older, newer) else {
+            // Counter reset, or wrapped around at some unknown range
+            continue;
+        };
+        let watts = microjoules as f32 / 1_000_000.0 / seconds;
+        if newer.name.starts_with("package") {
+            result.package_watts += watts;
+            if let Some(power_limit_microwatts) = newer.power_limit_microwatts {
+                package_limit_watts += power_limit_microwatts as f32 / 1_000_000.0;
+            }
+        } else if newer.name == "core" {
+            result.core_watts += watts;
+        } else if newer.name == "dram" {
+            result.dram_watts += watts;
+        }
+    }
+
+    if package_limit_watts > 0.0 {
+        result.package_0_to_1 = Some((result.package_watts / package_limit_watts).clamp(0.0, 1.0));
+    }
+
+    return result;
+}
+
+/// RAPL counters wrap around at `max_energy_range_microjoules`, not at
+/// `usize::MAX`.
+///
+/// `None` if the counter went backwards and we can't tell by how much.
+fn energy_delta_microjoules(older: &EnergyCounters, newer: &EnergyCounters) -> Option<usize> {
+    if newer.energy_microjoules >= older.energy_microjoules {
+        return Some(newer.energy_microjoules - older.energy_microjoules);
+    }
+
+    if newer.max_energy_range_microjoules < older.energy_microjoules {
+        // Range unknown or broken
+        return None;
+    }
+
+    return Some(
+        newer.max_energy_range_microjoules - older.energy_microjoules + newer.energy_microjoules,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(id: &str, name: &str, energy_microjoules: usize) -> EnergyCounters {
+        return EnergyCounters {
+            id: id.to_string(),
+            name: name.to_string(),
+            energy_microjoules,
+            max_energy_range_microjoules: 1_000_000_000,
+            power_limit_microwatts: if name.starts_with("package") {
+                Some(20_000_000)
+            } else {
+                None
+            },
+        };
+    }
+
+    #[test]
+    fn test_diff() {
+        let older = vec![
+            counters("intel-rapl:0", "package-0", 0),
+            counters("intel-rapl:0:0", "core", 0),
+            counters("intel-rapl:0:1", "dram", 0),
+        ];
+        let newer = vec![
+            counters("intel-rapl:0", "package-0", 20_000_000),
+            counters("intel-rapl:0:0", "core", 10_000_000),
+            counters("intel-rapl:0:1", "dram", 4_000_000),
+        ];
+
+        let result = diff(&older, &newer, Duration::from_secs(2));
+        assert_eq!(
+            result,
+            PowerDraw {
+                package_watts: 10.0,
+                core_watts: 5.0,
+                dram_watts: 2.0,
+                package_0_to_1: Some(0.5),
+            }
+        );
+    }
+
+    /// Test that diff() can handle a counter wrapping around at its max range
+    #[test]
+    fn test_diff_wrap() {
+        let older = vec![counters("intel-rapl:0", "package-0", 999_000_000)];
+        let newer = vec![counters("intel-rapl:0", "package-0", 1_000_000)];
+
+        let result = diff(&older, &newer, Duration::from_secs(1));
+        assert_eq!(2.0, result.package_watts);
+    }
+
+    /// Test that a counter going backwards with an unknown range is skipped
+    #[test]
+    fn test_diff_reset() {
+        let unknown_range = |energy_microjoules| {
+            return EnergyCounters {
+                max_energy_range_microjoules: 0,
+                ..counters("intel-rapl:0", "package-0", energy_microjoules)
+            };
+        };
+        let older = vec![
+            unknown_range(999_000_000),
+            counters("intel-rapl:0:0", "core", 0),
+        ];
+        let newer = vec![
+            unknown_range(1_000_000),
+            counters("intel-rapl:0:0", "core", 3_000_000),
+        ];
+
+        let result = diff(&older, &newer, Duration::from_secs(1));
+        assert_eq!(0.0, result.package_watts);
+        assert_eq!(None, result.package_0_to_1);
+        assert_eq!(3.0, result.core_watts);
+    }
+}
@@ -0,0 +1,307 @@
+//! Rendering on a thread of its own, so that the UI thread doesn't have to do
+//! the noise maths.
+//!
+//! A [`FrameProducer`] renders frames of the requested size at the requested
+//! frame rate. Get them either through a callback, or by asking for the
+//! latest one whenever you want to draw.
+//!
+//! Without a callback, rendering pauses when nobody has asked for a frame for
+//! a while, and resumes on the next request. That request gets the last frame
+//! rendered before the pause.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::shared::{Frame, SharedLoadViz};
+
+/// Without a callback, pause if nobody has asked for a frame for this long
+static IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Slower frame rates render once per this many seconds
+static MAX_SECONDS_PER_FRAME: f32 = 60.0;
+
+type FrameCallback = Box<dyn FnMut(&Frame) + Send>;
+
+/// Stops rendering when dropped
+pub struct FrameProducer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    loadviz: SharedLoadViz,
+    state: Mutex<State>,
+
+    /// Notified on any state change
+    changed: Condvar,
+}
+
+struct State {
+    width: usize,
+    height: usize,
+    fps: f32,
+    callback: Option<FrameCallback>,
+
+    /// Bumped whenever the callback is set or cleared, so that the rendering
+    /// thread can tell whether the one it's calling is still current
+    callback_generation: u64,
+
+    /// Last time somebody asked for a frame
+    last_request: Instant,
+    idle_timeout: Duration,
+
+    stopped: bool,
+}
+
+impl State {
+    /// Should the thread render, or wait for something to change?
+    fn is_paused(&self) -> bool {
+        if self.width == 0 || self.height == 0 {
+            return true;
+        }
+        if self.fps.is_nan() || self.fps <= 0.0 {
+            return true;
+        }
+        if self.callback.is_some() {
+            return false;
+        }
+        return self.last_request.elapsed() > self.idle_timeout;
+    }
+}
+
+impl FrameProducer {
+    /// Start rendering `loadviz` at `width` x `height` pixels, `fps` frames per
+    /// second. Use `loadviz` to change settings while rendering.
+    pub fn new(loadviz: SharedLoadViz, width: usize, height: usize, fps: f32) -> FrameProducer {
+        let shared = Arc::new(Shared {
+            loadviz,
+            state: Mutex::new(State {
+                width,
+                height,
+                fps,
+                callback: None,
+                callback_generation: 0,
+                last_request: Instant::now(),
+                idle_timeout: IDLE_TIMEOUT,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let thread = std::thread::spawn(move || run(&thread_shared));
+
+        return FrameProducer {
+            shared,
+            thread: Some(thread),
+        };
+    }
+
+    /// Takes effect from the next frame on. Frames you're holding keep their
+    /// size. A zero width or height pauses rendering.
+    pub fn set_size(&self, width: usize, height: usize) {
+        self.update(|state| {
+            state.width = width;
+            state.height = height;
+        });
+    }
+
+    /// Zero pauses rendering
+    pub fn set_fps(&self, fps: f32) {
+        self.update(|state| state.fps = fps);
+    }
+
+    /// Called on the rendering thread with each new frame. Don't drop the
+    /// producer from inside the callback, that will deadlock.
+    ///
+    /// With a callback, rendering never pauses by itself.
+    pub fn set_callback(&self, callback: impl FnMut(&Frame) + Send + 'static) {
+        self.update(|state| {
+            state.callback = Some(Box::new(callback));
+            state.callback_generation += 1;
+        });
+    }
+
+    /// Takes effect before the next frame, even when called from inside the
+    /// callback
+    pub fn clear_callback(&self) {
+        self.update(|state| {
+            state.callback = None;
+            state.callback_generation += 1;
+        });
+    }
+
+    /// The most recently rendered frame, or `None` if nothing has been
+    /// rendered yet. Also resumes rendering if we were paused.
+    pub fn latest_frame(&self) -> Option<Frame> {
+        self.update(|state| state.last_request = Instant::now());
+        return self.shared.loadviz.latest_frame();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.shared.state.lock().unwrap().is_paused();
+    }
+
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        change(&mut self.shared.state.lock().unwrap());
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Drop for FrameProducer {
+    fn drop(&mut self) {
+        self.update(|state| state.stopped = true);
+        if let Some(thread) = self.thread.take() {
+            // If rendering panicked, there's nothing more we can do about it
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut next_frame = Instant::now();
+    loop {
+        let mut state = shared.state.lock().unwrap();
+        if state.stopped {
+            return;
+        }
+
+        if state.is_paused() {
+            // Check again when the idle timeout would have run out, in case
+            // that's why we're paused
+            let timeout = state.idle_timeout;
+            drop(shared.changed.wait_timeout(state, timeout).unwrap());
+            next_frame = Instant::now();
+            continue;
+        }
+
+        let now = Instant::now();
+        if now < next_frame {
+            // Wake up early on changes, a new frame rate for example
+            drop(
+                shared
+                    .changed
+                    .wait_timeout(state, next_frame - now)
+                    .unwrap(),
+            );
+            continue;
+        }
+
+        let (width, height) = (state.width, state.height);
+        let frame_duration = Duration::from_secs_f32((1.0 / state.fps).min(MAX_SECONDS_PER_FRAME));
+
+        // Render and call back without holding the lock, so that the
+        // callback can change settings
+        let mut callback = state.callback.take();
+        let callback_generation = state.callback_generation;
+        drop(state);
+
+        let frame = shared.loadviz.render(width, height);
+        if let Some(callback) = &mut callback {
+            callback(&frame);
+        }
+
+        let mut state = shared.state.lock().unwrap();
+        if state.callback_generation == callback_generation {
+            // Unless it was replaced or cleared while we were calling it
+            state.callback = callback;
+        }
+
+        // If we fall behind, skip frames rather than trying to catch up
+        next_frame = (next_frame + frame_duration).max(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Scenario, ScenarioSource};
+    use crate::test_util::wait_for;
+    use crate::LoadViz;
+
+    fn new_shared() -> SharedLoadViz {
+        return SharedLoadViz::new(
+            LoadViz::builder()
+                .load_source(Box::new(ScenarioSource::new(
+                    Scenario::preset("demo").unwrap(),
+                )))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_latest_frame_and_resize() {
+        let producer = FrameProducer::new(new_shared(), 4, 3, 200.0);
+        wait_for("first frame", || producer.latest_frame().is_some());
+        let frame = producer.latest_frame().unwrap();
+        assert_eq!((4, 3), (frame.width(), frame.height()));
+
+        producer.set_size(6, 2);
+        wait_for("resized frame", || {
+            producer.latest_frame().unwrap().width() == 6
+        });
+        assert_eq!(6 * 2 * 3, producer.latest_frame().unwrap().len());
+
+        // Still the old size
+        assert_eq!(4 * 3 * 3, frame.len());
+    }
+
+    #[test]
+    fn test_callback() {
+        let producer = FrameProducer::new(new_shared(), 4, 3, 200.0);
+        let count = Arc::new(Mutex::new(0));
+        let callback_count = count.clone();
+        producer.set_callback(move |frame| {
+            assert_eq!(4 * 3 * 3, frame.len());
+            *callback_count.lock().unwrap() += 1;
+        });
+        wait_for("five callbacks", || *count.lock().unwrap() >= 5);
+    }
+
+    #[test]
+    fn test_clear_callback_from_callback() {
+        let producer = Arc::new(FrameProducer::new(new_shared(), 4, 3, 200.0));
+        let count = Arc::new(Mutex::new(0));
+        let callback_count = count.clone();
+
+        // Weak, so that the callback doesn't keep the producer alive
+        let callback_producer = Arc::downgrade(&producer);
+        producer.set_callback(move |_frame| {
+            *callback_count.lock().unwrap() += 1;
+            callback_producer.upgrade().unwrap().clear_callback();
+        });
+        wait_for("callback", || *count.lock().unwrap() >= 1);
+
+        // Not called again
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(1, *count.lock().unwrap());
+    }
+
+    #[test]
+    fn test_pause_when_idle() {
+        let loadviz = new_shared();
+        let producer = FrameProducer::new(loadviz.clone(), 4, 3, 200.0);
+        producer.shared.state.lock().unwrap().idle_timeout = Duration::from_millis(50);
+
+        wait_for("pause", || producer.is_paused());
+
+        // Let a frame that was already being rendered finish
+        std::thread::sleep(Duration::from_millis(20));
+        let paused_at = loadviz.latest_frame().map_or(0, |frame| frame.sequence());
+        std::thread::sleep(Duration::from_millis(100));
+        let still_at = loadviz.latest_frame().map_or(0, |frame| frame.sequence());
+        assert_eq!(paused_at, still_at);
+
+        // Asking resumes
+        producer.latest_frame();
+        assert!(!producer.is_paused());
+        wait_for("new frame", || {
+            loadviz.latest_frame().unwrap().sequence() > still_at
+        });
+
+        // No size, no frames
+        producer.set_size(0, 3);
+        assert!(producer.is_paused());
+    }
+}
@@ -0,0 +1,536 @@
+//! Read CPU load from Prometheus node_exporter metrics.
+//!
+//! We only look at the `node_cpu_seconds_total` counters in the text
+//! exposition format:
+//!
+//! ```text
+//! # TYPE node_cpu_seconds_total counter
+//! node_cpu_seconds_total{cpu="0",mode="idle"} 12345.67
+//! node_cpu_seconds_total{cpu="0",mode="user"} 234.5
+//! ```
+//!
+//! Modes are mapped onto [`LoadCounters`] the same way as for sysstat imports:
+//! nice counts as user, steal / IRQ / soft IRQ count as system, and I/O wait
+//! counts as idle. Guest time is already included in user time, so it is
+//! ignored.
+//!
+//! A [`PrometheusSource`] scrapes on a background thread, so that a slow
+//! server never holds up rendering.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::cpuload::LoadCounters;
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+
+/// node_exporter reports seconds, we turn them into counter ticks at this rate
+static TICKS_PER_SECOND: f64 = 100.0;
+
+/// How often to scrape
+static SCRAPE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connecting, and each read and write, fail after this long
+static HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum PrometheusError {
+    Io(io::Error),
+
+    /// The HTTP server said something other than 200 OK
+    Http(String),
+
+    /// `line_number` is 1 based
+    Malformed {
+        line_number: usize,
+        message: String,
+    },
+
+    /// No `node_cpu_seconds_total` counters found
+    NoCpuCounters,
+}
+
+impl fmt::Display for PrometheusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            PrometheusError::Io(error) => write!(f, "I/O error: {}", error),
+            PrometheusError::Http(status) => write!(f, "HTTP error: {}", status),
+            PrometheusError::Malformed {
+                line_number,
+                message,
+            } => write!(f, "Malformed metrics line {}: {}", line_number, message),
+            PrometheusError::NoCpuCounters => write!(f, "No node_cpu_seconds_total counters"),
+        };
+    }
+}
+
+impl std::error::Error for PrometheusError {}
+
+impl From<io::Error> for PrometheusError {
+    fn from(error: io::Error) -> Self {
+        return PrometheusError::Io(error);
+    }
+}
+
+/// Extract per-CPU load counters from node_exporter output, sorted by CPU
+/// number. CPUs missing from the output are left out.
+pub fn parse_cpu_counters(text: &str) -> Result<Vec<LoadCounters>, PrometheusError> {
+    // CPU number -> (user, system, idle) seconds
+    let mut cpus: BTreeMap<usize, (f64, f64, f64)> = BTreeMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let malformed = |message: &str| PrometheusError::Malformed {
+            line_number: index + 1,
+            message: message.to_string(),
+        };
+
+        let Some(rest) = line.strip_prefix("node_cpu_seconds_total") else {
+            continue;
+        };
+        let Some(rest) = rest.strip_prefix('{') else {
+            // Some other metric with the same prefix
+            continue;
+        };
+
+        let (labels, rest) = parse_labels(rest).map_err(|message| malformed(&message))?;
+
+        // Value, optionally followed by a timestamp
+        let value = rest.split_whitespace().next().unwrap_or("");
+        let seconds: f64 = value
+            .parse()
+            .map_err(|_| malformed(&format!("Not a number: {}", value)))?;
+
+        let cpu = labels.get("cpu").ok_or_else(|| malformed("No cpu label"))?;
+        let cpu: usize = cpu
+            .parse()
+            .map_err(|_| malformed(&format!("Not a CPU number: {}", cpu)))?;
+        let mode = labels
+            .get("mode")
+            .ok_or_else(|| malformed("No mode label"))?;
+
+        let counters = cpus.entry(cpu).or_insert((0.0, 0.0, 0.0));
+        match mode.as_str() {
+            "user" | "nice" => counters.0 += seconds,
+            "system" | "irq" | "softirq" | "steal" => counters.1 += seconds,
+            "idle" | "iowait" => counters.2 += seconds,
+            _ => {}
+        }
+    }
+
+    if cpus.is_empty() {
+        return Err(PrometheusError::NoCpuCounters);
+    }
+
+    return Ok(cpus
+        .values()
+        .map(|&(user, system, idle)| {
+            return LoadCounters {
+                user: (user * TICKS_PER_SECOND) as usize,
+                system: (system * TICKS_PER_SECOND) as usize,
+                idle: (idle * TICKS_PER_SECOND) as usize,
+            };
+        })
+        .collect());
+}
+
+/// Parse `name="value",...}` and return the labels plus whatever follows the
+/// closing brace.
+fn parse_labels(text: &str) -> Result<(BTreeMap<String, String>, &str), String> {
+    let mut labels = BTreeMap::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start_matches([' ', ',']);
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((labels, after));
+        }
+
+        let Some((name, after_name)) = rest.split_once('=') else {
+            return Err("Expected label=\"value\"".to_string());
+        };
+        let Some(mut after_name) = after_name.strip_prefix('"') else {
+            return Err(format!("Label {} value not quoted", name.trim()));
+        };
+
+        let mut value = String::new();
+        loop {
+            let mut chars = after_name.chars();
+            match chars.next() {
+                None => return Err("Unterminated label value".to_string()),
+                Some('"') => {
+                    after_name = chars.as_str();
+                    break;
+                }
+                Some('\\') => {
+                    match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some(escaped) => value.push(escaped),
+                        None => return Err("Unterminated label value".to_string()),
+                    }
+                    after_name = chars.as_str();
+                }
+                Some(c) => {
+                    value.push(c);
+                    after_name = chars.as_str();
+                }
+            }
+        }
+
+        labels.insert(name.trim().to_string(), value);
+        rest = after_name;
+    }
+}
+
+/// Where to read node_exporter metrics from
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricsLocation {
+    /// A file that something else keeps updating, with `curl -o` for example
+    File(PathBuf),
+
+    /// An `http://host:port/path` URL. HTTPS is not supported.
+    Http { host_and_port: String, path: String },
+}
+
+impl MetricsLocation {
+    /// `http://` URLs are HTTP endpoints, anything else is a file name
+    pub fn parse(location: &str) -> Result<MetricsLocation, String> {
+        if location.starts_with("https://") {
+            return Err(format!("HTTPS is not supported: {}", location));
+        }
+        let Some(url) = location.strip_prefix("http://") else {
+            return Ok(MetricsLocation::File(PathBuf::from(location)));
+        };
+
+        let (host_and_port, path) = match url.find('/') {
+            Some(slash) => (&url[..slash], &url[slash..]),
+            None => (url, "/metrics"),
+        };
+        if host_and_port.is_empty() {
+            return Err(format!("No host in URL: {}", location));
+        }
+        let host_and_port = if host_and_port.contains(':') {
+            host_and_port.to_string()
+        } else {
+            format!("{}:80", host_and_port)
+        };
+
+        return Ok(MetricsLocation::Http {
+            host_and_port,
+            path: path.to_string(),
+        });
+    }
+}
+
+/// Turns node_exporter scrapes into samples.
+///
+/// Scraping happens on a background thread. While scraping fails,
+/// [`LoadSource::is_connected()`] returns `false`.
+pub struct PrometheusSource {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    /// The most recent scrape, `None` if it failed
+    latest: Option<Sample>,
+
+    connected: bool,
+
+    /// Set when the `PrometheusSource` is dropped, tells the background
+    /// thread to stop
+    stopped: bool,
+}
+
+impl PrometheusSource {
+    pub fn new(location: MetricsLocation) -> PrometheusSource {
+        return PrometheusSource::with_interval(location, SCRAPE_INTERVAL);
+    }
+
+    fn with_interval(location: MetricsLocation, interval: Duration) -> PrometheusSource {
+        let shared = Arc::new(Mutex::new(Shared {
+            latest: None,
+            connected: false,
+            stopped: false,
+        }));
+
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            while !thread_shared.lock().unwrap().stopped {
+                let latest = location.scrape().ok();
+
+                let mut shared = thread_shared.lock().unwrap();
+                shared.connected = latest.is_some();
+                shared.latest = latest;
+                drop(shared);
+
+                std::thread::sleep(interval);
+            }
+        });
+
+        return PrometheusSource { shared };
+    }
+}
+
+impl Drop for PrometheusSource {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stopped = true;
+    }
+}
+
+impl LoadSource for PrometheusSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return self.shared.lock().unwrap().latest.clone();
+    }
+
+    fn is_connected(&self) -> bool {
+        return self.shared.lock().unwrap().connected;
+    }
+}
+
+impl MetricsLocation {
+    /// Scrape once.
+    ///
+    /// File samples are timestamped with the file's modification time, so an
+    /// unchanged file shows up as nothing having changed.
+    pub fn scrape(&self) -> Result<Sample, PrometheusError> {
+        let (text, timestamp) = match self {
+            MetricsLocation::File(path) => {
+                let timestamp = std::fs::metadata(path)?.modified()?;
+                (std::fs::read_to_string(path)?, timestamp)
+            }
+            MetricsLocation::Http {
+                host_and_port,
+                path,
+            } => (http_get(host_and_port, path)?, SystemTime::now()),
+        };
+
+        return Ok(Sample::cpus_only(timestamp, parse_cpu_counters(&text)?));
+    }
+}
+
+/// Minimal HTTP/1.0 GET, enough for talking to node_exporter
+fn http_get(host_and_port: &str, path: &str) -> Result<String, PrometheusError> {
+    let mut stream = None;
+    for address in std::net::ToSocketAddrs::to_socket_addrs(host_and_port)? {
+        if let Ok(connected) = TcpStream::connect_timeout(&address, HTTP_TIMEOUT) {
+            stream = Some(connected);
+            break;
+        }
+    }
+    let Some(mut stream) = stream else {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("Unable to connect to {}", host_and_port),
+        )
+        .into());
+    };
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    // HTTP/1.0 so that we don't have to deal with chunked responses
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: text/plain\r\n\r\n",
+        path, host_and_port
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    let Some((head, body)) = response.split_once("\r\n\r\n") else {
+        return Err(PrometheusError::Http("No end of headers".to_string()));
+    };
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(PrometheusError::Http(status.to_string()));
+    }
+
+    return Ok(body.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpuload::diff;
+    use crate::test_util::wait_for;
+    use std::net::TcpListener;
+
+    static METRICS: &str = r#"# HELP node_cpu_seconds_total Seconds the CPUs spent in each mode.
+# TYPE node_cpu_seconds_total counter
+node_cpu_seconds_total{cpu="0",mode="idle"} 100
+node_cpu_seconds_total{cpu="0",mode="iowait"} 10
+node_cpu_seconds_total{cpu="0",mode="irq"} 0
+node_cpu_seconds_total{cpu="0",mode="nice"} 5
+node_cpu_seconds_total{cpu="0",mode="softirq"} 1
+node_cpu_seconds_total{cpu="0",mode="steal"} 0
+node_cpu_seconds_total{cpu="0",mode="system"} 20
+node_cpu_seconds_total{cpu="0",mode="user"} 50.5
+node_cpu_seconds_total{mode="idle",cpu="1"} 200 1700000000000
+# HELP node_cpu_seconds_total_other Not what we want
+node_cpu_seconds_total_other{cpu="0"} 1
+node_load1 0.5
+"#;
+
+    #[test]
+    fn test_parse() {
+        let counters = parse_cpu_counters(METRICS).unwrap();
+        assert_eq!(
+            counters,
+            vec![
+                LoadCounters {
+                    user: 5550,
+                    system: 2100,
+                    idle: 11000,
+                },
+                LoadCounters {
+                    user: 0,
+                    system: 0,
+                    idle: 20000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cpu_number_gap() {
+        let scrape = "node_cpu_seconds_total{cpu=\"0\",mode=\"user\"} 10\n\
+                      node_cpu_seconds_total{cpu=\"2\",mode=\"user\"} 20\n";
+        let counters = parse_cpu_counters(scrape).unwrap();
+        assert_eq!(
+            vec![1000, 2000],
+            counters.iter().map(|c| c.user).collect::<Vec<_>>()
+        );
+
+        // No made up CPU 1 whose load would be 0 / 0
+        let newer = parse_cpu_counters(
+            "node_cpu_seconds_total{cpu=\"0\",mode=\"user\"} 11\n\
+             node_cpu_seconds_total{cpu=\"2\",mode=\"idle\"} 1\n\
+             node_cpu_seconds_total{cpu=\"2\",mode=\"user\"} 20\n",
+        )
+        .unwrap();
+        let loads = diff(&counters, &newer);
+        assert_eq!(
+            vec![1.0, 0.0],
+            loads.iter().map(|l| l.user_0_to_1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_scrapes_diff() {
+        let older = parse_cpu_counters(
+            "node_cpu_seconds_total{cpu=\"0\",mode=\"user\"} 10\n\
+             node_cpu_seconds_total{cpu=\"0\",mode=\"idle\"} 10\n",
+        )
+        .unwrap();
+        let newer = parse_cpu_counters(
+            "node_cpu_seconds_total{cpu=\"0\",mode=\"user\"} 13\n\
+             node_cpu_seconds_total{cpu=\"0\",mode=\"idle\"} 11\n",
+        )
+        .unwrap();
+
+        let loads = diff(&older, &newer);
+        assert_eq!(0.75, loads[0].user_0_to_1);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(matches!(
+            parse_cpu_counters("node_cpu_seconds_total{cpu=\"0\",mode=\"user\"} many\n"),
+            Err(PrometheusError::Malformed { line_number: 1, .. })
+        ));
+        assert!(matches!(
+            parse_cpu_counters("node_cpu_seconds_total{cpu=\"0,mode=\"user\"} 1\n"),
+            Err(PrometheusError::Malformed { .. })
+        ));
+        assert!(matches!(
+            parse_cpu_counters("node_load1 0.5\n"),
+            Err(PrometheusError::NoCpuCounters)
+        ));
+    }
+
+    #[test]
+    fn test_escaped_label_values() {
+        let (labels, rest) = parse_labels(r#"a="x\"y\\z", b="1"} 5"#).unwrap();
+        assert_eq!("x\"y\\z", labels["a"]);
+        assert_eq!("1", labels["b"]);
+        assert_eq!(" 5", rest);
+    }
+
+    #[test]
+    fn test_location() {
+        assert_eq!(
+            MetricsLocation::parse("http://localhost:9100/metrics").unwrap(),
+            MetricsLocation::Http {
+                host_and_port: "localhost:9100".to_string(),
+                path: "/metrics".to_string(),
+            }
+        );
+        assert_eq!(
+            MetricsLocation::parse("http://buildhost").unwrap(),
+            MetricsLocation::Http {
+                host_and_port: "buildhost:80".to_string(),
+                path: "/metrics".to_string(),
+            }
+        );
+        assert_eq!(
+            MetricsLocation::parse("scrape.txt").unwrap(),
+            MetricsLocation::File(PathBuf::from("scrape.txt"))
+        );
+        assert!(MetricsLocation::parse("https://buildhost/metrics").is_err());
+    }
+
+    #[test]
+    fn test_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut connection, _) = listener.accept().unwrap();
+
+            // Read the whole request, closing with unread data would reset the
+            // connection
+            let mut request: Vec<u8> = vec![];
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let count = connection.read(&mut buffer).unwrap();
+                assert!(count > 0);
+                request.extend_from_slice(&buffer[..count]);
+            }
+            write!(
+                connection,
+                "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n{}",
+                METRICS
+            )
+            .unwrap();
+        });
+
+        let location =
+            MetricsLocation::parse(&format!("http://127.0.0.1:{}/metrics", port)).unwrap();
+        let sample = location.scrape().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(2, sample.cpus.len());
+        assert_eq!(5550, sample.cpus[0].user);
+    }
+
+    #[test]
+    fn test_source() {
+        let path = std::env::temp_dir().join(format!("node-exporter-{}.prom", std::process::id()));
+        let location = MetricsLocation::File(path.clone());
+        let mut source = PrometheusSource::with_interval(location, Duration::from_millis(5));
+
+        // No file yet
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!source.is_connected());
+
+        std::fs::write(&path, METRICS).unwrap();
+        wait_for("connection", || source.is_connected());
+        assert_eq!(2, source.get_sample().unwrap().cpus.len());
+
+        std::fs::remove_file(&path).unwrap();
+        wait_for("disconnection", || !source.is_connected());
+        assert!(source.get_sample().is_none());
+    }
+}
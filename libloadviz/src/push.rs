@@ -0,0 +1,194 @@
+//! Load that the embedding app knows about and we don't, like a game engine's
+//! job system.
+//!
+//! Either push loads into a [`PushSource`] through its [`PushHandle`] whenever
+//! you have them, or let a [`CallbackSource`] ask for them whenever we want
+//! them. Loads are the same fractions between 0 and 1 that LoadViz shows for
+//! CPU cores, one [`CpuLoad`] per column.
+//!
+//! Pushing out of range loads is an error. Callback samples with out of range
+//! loads are skipped, and reported through [`CallbackStatus`].
+
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::cpuload::{make_load, CpuLoad, SyntheticCounters};
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+
+/// Shows whatever was pushed through its [`PushHandle`] most recently
+pub struct PushSource {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Clones push into the same [`PushSource`], from any thread
+#[derive(Clone)]
+pub struct PushHandle {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Option<Sample>,
+    counters: SyntheticCounters,
+}
+
+/// Asks a function for the current loads each time a sample is needed
+pub struct CallbackSource {
+    callback: Box<dyn FnMut() -> Option<Vec<CpuLoad>> + Send>,
+    counters: SyntheticCounters,
+    skipped: Arc<Mutex<SkippedSamples>>,
+}
+
+/// Tells you which samples a [`CallbackSource`] skipped. Clones share the same
+/// status.
+#[derive(Clone)]
+pub struct CallbackStatus {
+    skipped: Arc<Mutex<SkippedSamples>>,
+}
+
+#[derive(Default)]
+struct SkippedSamples {
+    count: usize,
+    last_error: Option<String>,
+}
+
+impl PushSource {
+    pub fn new() -> PushSource {
+        return PushSource {
+            shared: Default::default(),
+        };
+    }
+
+    pub fn handle(&self) -> PushHandle {
+        return PushHandle {
+            shared: self.shared.clone(),
+        };
+    }
+}
+
+impl Default for PushSource {
+    fn default() -> Self {
+        return PushSource::new();
+    }
+}
+
+impl LoadSource for PushSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return self.shared.lock().unwrap().latest.clone();
+    }
+}
+
+impl PushHandle {
+    /// The current load of each column. Loads outside of 0-1 are rejected, and
+    /// if user + system load is more than 1, system load is reduced.
+    pub fn push(&self, loads: &[CpuLoad]) -> Result<(), String> {
+        let loads = check_loads(loads)?;
+
+        let mut shared = self.shared.lock().unwrap();
+        let cpus = shared.counters.add(&loads);
+        shared.latest = Some(Sample::cpus_only(SystemTime::now(), cpus));
+        return Ok(());
+    }
+}
+
+impl CallbackSource {
+    /// `callback` returns the current load of each column, or `None` if it
+    /// has nothing new. It is called from whatever thread is rendering.
+    pub fn new(callback: impl FnMut() -> Option<Vec<CpuLoad>> + Send + 'static) -> CallbackSource {
+        return CallbackSource {
+            callback: Box::new(callback),
+            counters: Default::default(),
+            skipped: Default::default(),
+        };
+    }
+
+    pub fn status(&self) -> CallbackStatus {
+        return CallbackStatus {
+            skipped: self.skipped.clone(),
+        };
+    }
+}
+
+impl CallbackStatus {
+    pub fn skipped_sample_count(&self) -> usize {
+        return self.skipped.lock().unwrap().count;
+    }
+
+    /// Why the most recently skipped sample was skipped
+    pub fn last_error(&self) -> Option<String> {
+        return self.skipped.lock().unwrap().last_error.clone();
+    }
+}
+
+impl LoadSource for CallbackSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        let loads = (self.callback)()?;
+        let loads = match check_loads(&loads) {
+            Ok(loads) => loads,
+            Err(message) => {
+                let mut skipped = self.skipped.lock().unwrap();
+                skipped.count += 1;
+                skipped.last_error = Some(message);
+                return None;
+            }
+        };
+        return Some(Sample::cpus_only(
+            SystemTime::now(),
+            self.counters.add(&loads),
+        ));
+    }
+}
+
+fn check_loads(loads: &[CpuLoad]) -> Result<Vec<CpuLoad>, String> {
+    return loads
+        .iter()
+        .map(|load| make_load(load.user_0_to_1, load.system_0_to_1))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpuload::diff;
+
+    #[test]
+    fn test_push() {
+        let mut source = PushSource::new();
+        let handle = source.handle();
+        assert!(source.get_sample().is_none());
+
+        handle.push(&[CpuLoad::new(0.5, 0.25)]).unwrap();
+        let older = source.get_sample().unwrap();
+        assert!(handle.push(&[CpuLoad::new(1.5, 0.0)]).is_err());
+        handle.clone().push(&[CpuLoad::new(0.25, 0.5)]).unwrap();
+        let newer = source.get_sample().unwrap();
+
+        assert_eq!(
+            vec![CpuLoad::new(0.25, 0.5)],
+            diff(&older.cpus, &newer.cpus)
+        );
+    }
+
+    #[test]
+    fn test_callback() {
+        let mut calls = 0;
+        let mut source = CallbackSource::new(move || {
+            calls += 1;
+            return match calls {
+                1 => None,
+                2 => Some(vec![CpuLoad::new(-1.0, 0.0)]),
+                _ => Some(vec![CpuLoad::new(0.5, 0.0), CpuLoad::new(0.0, 0.5)]),
+            };
+        });
+
+        let status = source.status();
+
+        assert!(source.get_sample().is_none());
+        assert_eq!(0, status.skipped_sample_count());
+        assert!(source.get_sample().is_none());
+        assert_eq!(1, status.skipped_sample_count());
+        assert!(status.last_error().unwrap().contains("-1"));
+        assert_eq!(2, source.get_sample().unwrap().cpus.len());
+    }
+}
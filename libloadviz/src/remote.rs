@@ -0,0 +1,529 @@
+//! Watching the load of another machine over the network.
+//!
+//! The agent (`cargo run --bin=loadviz-agent`) runs on the machine to watch,
+//! and a [`RemoteSource`] on the machine doing the watching connects to it
+//! over TCP or a Unix domain socket.
+//!
+//! The protocol is line based text. The agent starts by saying who it is, and
+//! then answers sample requests until the client disconnects:
+//!
+//! ```text
+//! agent:  loadviz-agent 1
+//! client: sample
+//! agent:  t=1700000000000 cpu=10,5,85;20,5,75 disk=sda,512,1024,3 net= battery=- energy=
+//! client: sample
+//! agent:  none
+//! ```
+//!
+//! Samples are sent in the load trace format, see [`crate::trace`]. `none`
+//! means the agent had no sample to give. Clients must disconnect from agents
+//! speaking a version they don't know, and agents hang up on clients sending
+//! request lines of 64 bytes or more.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+use crate::trace::{format_sample, parse_sample};
+
+static MAGIC: &str = "loadviz-agent";
+pub static VERSION: u32 = 1;
+
+pub static DEFAULT_PORT: u16 = 8723;
+
+/// How often clients ask for a new sample
+static POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Clients consider the agent gone if connecting or getting an answer takes
+/// longer than this
+static TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Agents drop clients that have been quiet, or that haven't been reading
+/// what we send, for this long
+static CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Agents drop clients sending longer request lines than this, rather than
+/// buffering whatever they send until we run out of memory
+static MAX_REQUEST_LENGTH: u64 = 64;
+
+/// Serve samples from `source` to anybody connecting, forever
+pub fn serve_tcp(listener: TcpListener, source: Box<dyn LoadSource>) {
+    serve(
+        listener.incoming().map(|stream| {
+            let stream = stream?;
+            stream.set_read_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+            stream.set_write_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+            return Ok(stream);
+        }),
+        source,
+    );
+}
+
+/// Serve samples from `source` to anybody connecting, forever
+#[cfg(unix)]
+pub fn serve_unix(listener: UnixListener, source: Box<dyn LoadSource>) {
+    serve(
+        listener.incoming().map(|stream| {
+            let stream = stream?;
+            stream.set_read_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+            stream.set_write_timeout(Some(CLIENT_IDLE_TIMEOUT))?;
+            return Ok(stream);
+        }),
+        source,
+    );
+}
+
+fn serve<S: Read + Write + Send + 'static>(
+    incoming: impl Iterator<Item = io::Result<S>>,
+    source: Box<dyn LoadSource>,
+) {
+    let source = Arc::new(Mutex::new(source));
+    for stream in incoming {
+        let Ok(stream) = stream else {
+            // Out of file descriptors or similar, hopefully temporary
+            continue;
+        };
+
+        let source = source.clone();
+        std::thread::spawn(move || {
+            // Errors here are about this client only, nothing to do about them
+            let _ = serve_client(stream, &source);
+        });
+    }
+}
+
+/// Answer sample requests from one client until it disconnects
+pub fn serve_client<S: Read + Write>(
+    stream: S,
+    source: &Mutex<Box<dyn LoadSource>>,
+) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    send_line(stream.get_mut(), &format!("{} {}", MAGIC, VERSION))?;
+
+    loop {
+        let mut request = String::new();
+        let length = (&mut stream)
+            .take(MAX_REQUEST_LENGTH)
+            .read_line(&mut request)?;
+        if length == 0 {
+            return Ok(());
+        }
+        if length as u64 == MAX_REQUEST_LENGTH && !request.ends_with('\n') {
+            send_line(stream.get_mut(), "error Request too long")?;
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        let response = match request.trim_end() {
+            "sample" => match source.lock().unwrap().get_sample() {
+                Some(sample) => format_sample(&sample),
+                None => "none".to_string(),
+            },
+            unknown => format!("error Unknown request: {}", unknown),
+        };
+        send_line(stream.get_mut(), &response)?;
+    }
+}
+
+fn send_line(stream: &mut impl Write, line: &str) -> io::Result<()> {
+    // One write, so that the line doesn't get split into several packets
+    stream.write_all(format!("{}\n", line).as_bytes())?;
+    return stream.flush();
+}
+
+/// Where the agent is
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteAddress {
+    /// `host:port`
+    Tcp(String),
+
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl RemoteAddress {
+    /// `unix:/path/to/socket` is a Unix domain socket, anything else is a
+    /// `host` or `host:port`
+    pub fn parse(address: &str) -> RemoteAddress {
+        #[cfg(unix)]
+        if let Some(path) = address.strip_prefix("unix:") {
+            return RemoteAddress::Unix(PathBuf::from(path));
+        }
+
+        // "::1" is an IPv6 address without a port, "[::1]:8723" has one
+        let has_port = match address.rsplit_once(':') {
+            Some((host, _)) => !host.contains(':') || host.ends_with(']'),
+            None => false,
+        };
+        if has_port {
+            return RemoteAddress::Tcp(address.to_string());
+        }
+        if address.contains(':') {
+            return RemoteAddress::Tcp(format!("[{}]:{}", address, DEFAULT_PORT));
+        }
+        return RemoteAddress::Tcp(format!("{}:{}", address, DEFAULT_PORT));
+    }
+}
+
+/// Gets samples from an agent on another machine.
+///
+/// Talking to the agent happens on a background thread, so a slow network
+/// never holds up rendering. If the agent goes away we keep trying to
+/// reconnect, and [`LoadSource::is_connected()`] returns `false` until we
+/// succeed.
+pub struct RemoteSource {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    /// The most recent sample from the agent, `None` while disconnected
+    latest: Option<Sample>,
+
+    connected: bool,
+
+    /// Set when the `RemoteSource` is dropped, tells the background thread to
+    /// stop
+    stopped: bool,
+}
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+struct Connection {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl RemoteSource {
+    pub fn new(address: RemoteAddress) -> RemoteSource {
+        return RemoteSource::with_timing(address, POLL_INTERVAL, TIMEOUT);
+    }
+
+    fn with_timing(
+        address: RemoteAddress,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> RemoteSource {
+        let shared = Arc::new(Mutex::new(Shared {
+            latest: None,
+            connected: false,
+            stopped: false,
+        }));
+
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            let mut connection: Option<Connection> = None;
+            while !thread_shared.lock().unwrap().stopped {
+                if connection.is_none() {
+                    connection = Connection::open(&address, timeout).ok();
+                }
+
+                let latest = match &mut connection {
+                    Some(open) => open.request_sample(),
+                    None => Err(io::ErrorKind::NotConnected.into()),
+                };
+                let mut shared = thread_shared.lock().unwrap();
+                match latest {
+                    Ok(Some(sample)) => {
+                        shared.latest = Some(sample);
+                        shared.connected = true;
+                    }
+                    Ok(None) => {
+                        // Connected, but the agent has nothing for us
+                        shared.connected = true;
+                    }
+                    Err(_) => {
+                        connection = None;
+                        shared.latest = None;
+                        shared.connected = false;
+                    }
+                }
+                drop(shared);
+
+                std::thread::sleep(poll_interval);
+            }
+        });
+
+        return RemoteSource { shared };
+    }
+}
+
+impl Drop for RemoteSource {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().stopped = true;
+    }
+}
+
+impl LoadSource for RemoteSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return self.shared.lock().unwrap().latest.clone();
+    }
+
+    fn is_connected(&self) -> bool {
+        return self.shared.lock().unwrap().connected;
+    }
+}
+
+impl Connection {
+    fn open(address: &RemoteAddress, timeout: Duration) -> io::Result<Connection> {
+        let stream: Box<dyn Stream> = match address {
+            RemoteAddress::Tcp(host_and_port) => {
+                let mut last_error: io::Error = io::ErrorKind::NotFound.into();
+                let mut connected = None;
+                for socket_address in host_and_port.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&socket_address, timeout) {
+                        Ok(stream) => {
+                            connected = Some(stream);
+                            break;
+                        }
+                        Err(error) => last_error = error,
+                    }
+                }
+                let stream = connected.ok_or(last_error)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Box::new(stream)
+            }
+
+            #[cfg(unix)]
+            RemoteAddress::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                Box::new(stream)
+            }
+        };
+
+        let mut connection = Connection {
+            stream: BufReader::new(stream),
+        };
+        let greeting = connection.read_line()?;
+        if greeting != format!("{} {}", MAGIC, VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Expected {} {}, got: {}", MAGIC, VERSION, greeting),
+            ));
+        }
+
+        return Ok(connection);
+    }
+
+    fn request_sample(&mut self) -> io::Result<Option<Sample>> {
+        send_line(self.stream.get_mut(), "sample")?;
+        let response = self.read_line()?;
+        if response == "none" {
+            return Ok(None);
+        }
+
+        let sample = parse_sample(&response)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+        return Ok(Some(sample));
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        return Ok(line.trim_end().to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::cpuload::LoadCounters;
+    use crate::test_util::wait_for;
+
+    /// Samples one second apart, with `user` counting up
+    struct FixtureSource {
+        count: usize,
+    }
+
+    impl LoadSource for FixtureSource {
+        fn get_sample(&mut self) -> Option<Sample> {
+            self.count += 1;
+            return Some(Sample::cpus_only(
+                UNIX_EPOCH + Duration::from_secs(1000 + self.count as u64),
+                vec![LoadCounters {
+                    user: self.count,
+                    system: 0,
+                    idle: 0,
+                }],
+            ));
+        }
+    }
+
+    fn fixture() -> Box<dyn LoadSource> {
+        return Box::new(FixtureSource { count: 0 });
+    }
+
+    fn fast_client(address: RemoteAddress) -> RemoteSource {
+        return RemoteSource::with_timing(
+            address,
+            Duration::from_millis(10),
+            Duration::from_millis(200),
+        );
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = RemoteAddress::Tcp(listener.local_addr().unwrap().to_string());
+        std::thread::spawn(move || serve_tcp(listener, fixture()));
+
+        let mut client = fast_client(address);
+        wait_for("connection", || client.is_connected());
+
+        let first = client.get_sample().unwrap();
+        wait_for("new sample", || {
+            return client
+                .shared
+                .lock()
+                .unwrap()
+                .latest
+                .as_ref()
+                .unwrap()
+                .timestamp
+                > first.timestamp;
+        });
+        assert!(client.get_sample().unwrap().cpus[0].user > first.cpus[0].user);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix() {
+        let path =
+            std::env::temp_dir().join(format!("loadviz-agent-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || serve_unix(listener, fixture()));
+
+        let mut client = fast_client(RemoteAddress::Unix(path.clone()));
+        wait_for("connection", || client.is_connected());
+        assert_eq!(1, client.get_sample().unwrap().cpus.len());
+
+        drop(client);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = RemoteAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let (reconnect_sender, reconnect_receiver) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            // Serve one sample, then hang up
+            let source = Mutex::new(fixture());
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            send_line(stream.get_mut(), "loadviz-agent 1").unwrap();
+            let mut request = String::new();
+            stream.read_line(&mut request).unwrap();
+            let sample = source.lock().unwrap().get_sample().unwrap();
+            send_line(stream.get_mut(), &format_sample(&sample)).unwrap();
+            drop(stream);
+
+            // Come back when the test says so
+            reconnect_receiver.recv().unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let _ = serve_client(stream, &source);
+        });
+
+        let mut client = fast_client(address);
+        wait_for("connection", || client.is_connected());
+        wait_for("disconnection", || !client.is_connected());
+        assert_eq!(None, client.get_sample());
+
+        reconnect_sender.send(()).unwrap();
+        wait_for("reconnection", || client.is_connected());
+        assert!(client.get_sample().is_some());
+    }
+
+    #[test]
+    fn test_silent_agent_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = RemoteAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let (accepted_sender, accepted_receiver) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            // Accept, but never say anything
+            let (_stream, _) = listener.accept().unwrap();
+            accepted_sender.send(()).unwrap();
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        let client = fast_client(address);
+        accepted_receiver.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(!client.is_connected());
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = RemoteAddress::Tcp(listener.local_addr().unwrap().to_string());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                send_line(&mut stream, "loadviz-agent 2").unwrap();
+                send_line(&mut stream, "t=1000000 cpu=1,2,3").unwrap();
+            }
+        });
+
+        assert!(Connection::open(&address, Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_request_too_long() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve_tcp(listener, fixture()));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        // Just enough to get disconnected. More than this would be left unread
+        // when the agent hangs up, and then we'd get a connection reset
+        // instead of the response.
+        stream
+            .write_all(&vec![b'x'; MAX_REQUEST_LENGTH as usize])
+            .unwrap();
+
+        let mut stream = BufReader::new(stream);
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!("loadviz-agent 1\nerror Request too long\n", response);
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            RemoteAddress::parse("buildhost"),
+            RemoteAddress::Tcp("buildhost:8723".to_string())
+        );
+        assert_eq!(
+            RemoteAddress::parse("buildhost:1234"),
+            RemoteAddress::Tcp("buildhost:1234".to_string())
+        );
+        assert_eq!(
+            RemoteAddress::parse("::1"),
+            RemoteAddress::Tcp("[::1]:8723".to_string())
+        );
+        assert_eq!(
+            RemoteAddress::parse("[::1]:1234"),
+            RemoteAddress::Tcp("[::1]:1234".to_string())
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            RemoteAddress::parse("unix:/run/loadviz.sock"),
+            RemoteAddress::Unix(PathBuf::from("/run/loadviz.sock"))
+        );
+    }
+}
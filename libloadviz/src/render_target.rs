@@ -0,0 +1,402 @@
+//! Where rendered pixels go: any part of any buffer, in whatever pixel format
+//! your GUI toolkit wants.
+
+use std::fmt;
+
+use crate::renderer::Rgba;
+
+/// How one pixel is laid out in memory
+///
+/// cbindgen:prefix-with-name
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Three bytes: red, green, blue. What [`crate::LoadViz::render()`]
+    /// gives you.
+    Rgb888,
+
+    /// Four bytes: red, green, blue, alpha
+    Rgba8888,
+
+    /// Four bytes: blue, green, red, alpha
+    Bgra8888,
+
+    /// One native endian 32 bit word, `0xAARRGGBB`, with the color
+    /// premultiplied by alpha. Cairo's `CAIRO_FORMAT_ARGB32`.
+    Argb32Premultiplied,
+
+    /// One native endian 16 bit word: 5 bits red, 6 bits green, 5 bits blue
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        return match self {
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgba8888 | PixelFormat::Bgra8888 | PixelFormat::Argb32Premultiplied => 4,
+            PixelFormat::Rgb565 => 2,
+        };
+    }
+
+    /// Write one pixel into `to`, which must be `bytes_per_pixel()` long.
+    /// Formats without alpha get the color on black.
+    fn write(self, color: &Rgba, to: &mut [u8]) {
+        let [red, green, blue, alpha] = *color;
+        match self {
+            PixelFormat::Rgba8888 => to.copy_from_slice(color),
+            PixelFormat::Bgra8888 => to.copy_from_slice(&[blue, green, red, alpha]),
+            PixelFormat::Argb32Premultiplied => {
+                let [red, green, blue] = premultiply(color);
+                let argb =
+                    (alpha as u32) << 24 | (red as u32) << 16 | (green as u32) << 8 | blue as u32;
+                to.copy_from_slice(&argb.to_ne_bytes());
+            }
+            PixelFormat::Rgb888 => to.copy_from_slice(&premultiply(color)),
+            PixelFormat::Rgb565 => {
+                let [red, green, blue] = premultiply(color);
+                let rgb565 = (red as u16 >> 3) << 11 | (green as u16 >> 2) << 5 | blue as u16 >> 3;
+                to.copy_from_slice(&rgb565.to_ne_bytes());
+            }
+        }
+    }
+}
+
+fn premultiply(color: &Rgba) -> [u8; 3] {
+    let alpha = color[3] as u32;
+    if alpha == 0xff {
+        return [color[0], color[1], color[2]];
+    }
+
+    // Rounded
+    return [0, 1, 2].map(|i| ((color[i] as u32 * alpha + 0x7f) / 0xff) as u8);
+}
+
+/// Why a [`RenderTarget`] couldn't be created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderTargetError {
+    /// Rows would overlap
+    StrideTooSmall {
+        stride: usize,
+        minimum: usize,
+    },
+
+    BufferTooSmall {
+        length: usize,
+        minimum: usize,
+    },
+
+    /// A sub-rectangle reaching outside of its parent
+    OutOfBounds,
+
+    /// The buffer would have to be larger than any buffer can be
+    TooLarge,
+}
+
+impl fmt::Display for RenderTargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            RenderTargetError::StrideTooSmall { stride, minimum } => write!(
+                f,
+                "Stride must be at least {} bytes, got {}",
+                minimum, stride
+            ),
+            RenderTargetError::BufferTooSmall { length, minimum } => write!(
+                f,
+                "Buffer must be at least {} bytes, got {}",
+                minimum, length
+            ),
+            RenderTargetError::OutOfBounds => write!(f, "Rectangle outside of the target"),
+            RenderTargetError::TooLarge => write!(f, "Size too large"),
+        };
+    }
+}
+
+impl std::error::Error for RenderTargetError {}
+
+/// A `width` x `height` pixel rectangle in some buffer
+pub struct RenderTarget<'a> {
+    pixels: &'a mut [u8],
+    width: usize,
+    height: usize,
+
+    /// Bytes from the start of one row to the start of the next
+    stride: usize,
+
+    format: PixelFormat,
+}
+
+impl<'a> RenderTarget<'a> {
+    /// Rows are `stride` bytes apart, the first one starting at the start of
+    /// `pixels`. Padding at the end of rows is left alone.
+    pub fn new(
+        pixels: &'a mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        format: PixelFormat,
+    ) -> Result<RenderTarget<'a>, RenderTargetError> {
+        let row_length = get_row_length(width, format)?;
+        if stride < row_length {
+            return Err(RenderTargetError::StrideTooSmall {
+                stride,
+                minimum: row_length,
+            });
+        }
+
+        let minimum = get_minimum_length(width, height, stride, format)?;
+        if pixels.len() < minimum {
+            return Err(RenderTargetError::BufferTooSmall {
+                length: pixels.len(),
+                minimum,
+            });
+        }
+
+        return Ok(RenderTarget {
+            pixels,
+            width,
+            height,
+            stride,
+            format,
+        });
+    }
+
+    /// Rows without padding
+    pub fn packed(
+        pixels: &'a mut [u8],
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+    ) -> Result<RenderTarget<'a>, RenderTargetError> {
+        let stride = get_row_length(width, format)?;
+        return RenderTarget::new(pixels, width, height, stride, format);
+    }
+
+    /// Part of this target. Pixels outside of the rectangle are left alone.
+    pub fn sub_target(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<RenderTarget<'_>, RenderTargetError> {
+        let fits = |start: usize, length: usize, limit: usize| {
+            return start.checked_add(length).is_some_and(|end| end <= limit);
+        };
+        if !fits(x, width, self.width) || !fits(y, height, self.height) {
+            return Err(RenderTargetError::OutOfBounds);
+        }
+
+        // Saturating, an empty rectangle can start past the end of the buffer
+        let start = y
+            .saturating_mul(self.stride)
+            .saturating_add(x * self.format.bytes_per_pixel())
+            .min(self.pixels.len());
+        return RenderTarget::new(
+            &mut self.pixels[start..],
+            width,
+            height,
+            self.stride,
+            self.format,
+        );
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        return self.format;
+    }
+
+    pub(crate) fn put_pixel(&mut self, x: usize, y: usize, color: &Rgba) {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        let i = y * self.stride + x * bytes_per_pixel;
+        self.format
+            .write(color, &mut self.pixels[i..i + bytes_per_pixel]);
+    }
+}
+
+fn get_row_length(width: usize, format: PixelFormat) -> Result<usize, RenderTargetError> {
+    return width
+        .checked_mul(format.bytes_per_pixel())
+        .ok_or(RenderTargetError::TooLarge);
+}
+
+/// How many bytes a buffer needs for `height` rows `stride` bytes apart. The
+/// last row doesn't need any padding.
+///
+/// Fails if that's more than `isize::MAX`, which no buffer can be.
+pub(crate) fn get_minimum_length(
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+) -> Result<usize, RenderTargetError> {
+    if height == 0 {
+        return Ok(0);
+    }
+    return stride
+        .checked_mul(height - 1)
+        .and_then(|length| length.checked_add(get_row_length(width, format).ok()?))
+        .filter(|&length| length <= isize::MAX as usize)
+        .ok_or(RenderTargetError::TooLarge);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ORANGE: Rgba = [0xff, 0x80, 0x10, 0xff];
+
+    /// Half transparent
+    static GHOST: Rgba = [0xff, 0x80, 0x10, 0x80];
+
+    fn put_one(format: PixelFormat, color: &Rgba) -> Vec<u8> {
+        let mut pixels = vec![0; format.bytes_per_pixel()];
+        RenderTarget::packed(&mut pixels, 1, 1, format)
+            .unwrap()
+            .put_pixel(0, 0, color);
+        return pixels;
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            vec![0xff, 0x80, 0x10],
+            put_one(PixelFormat::Rgb888, &ORANGE)
+        );
+        assert_eq!(
+            vec![0xff, 0x80, 0x10, 0xff],
+            put_one(PixelFormat::Rgba8888, &ORANGE)
+        );
+        assert_eq!(
+            vec![0x10, 0x80, 0xff, 0xff],
+            put_one(PixelFormat::Bgra8888, &ORANGE)
+        );
+        assert_eq!(
+            0xffff8010u32.to_ne_bytes().to_vec(),
+            put_one(PixelFormat::Argb32Premultiplied, &ORANGE)
+        );
+        assert_eq!(
+            (0b11111 << 11 | 0b100000 << 5 | 0b00010u16)
+                .to_ne_bytes()
+                .to_vec(),
+            put_one(PixelFormat::Rgb565, &ORANGE)
+        );
+    }
+
+    #[test]
+    fn test_formats_with_alpha() {
+        assert_eq!(
+            vec![0xff, 0x80, 0x10, 0x80],
+            put_one(PixelFormat::Rgba8888, &GHOST)
+        );
+        assert_eq!(
+            vec![0x10, 0x80, 0xff, 0x80],
+            put_one(PixelFormat::Bgra8888, &GHOST)
+        );
+        assert_eq!(
+            0x80804008u32.to_ne_bytes().to_vec(),
+            put_one(PixelFormat::Argb32Premultiplied, &GHOST)
+        );
+
+        // On black
+        assert_eq!(vec![0x80, 0x40, 0x08], put_one(PixelFormat::Rgb888, &GHOST));
+    }
+
+    #[test]
+    fn test_stride_padding_untouched() {
+        // Two RGB565 pixels per row, plus two bytes of padding
+        let mut pixels = vec![0xaa; 6 * 2];
+        let mut target = RenderTarget::new(&mut pixels, 2, 2, 6, PixelFormat::Rgb565).unwrap();
+        for y in 0..2 {
+            for x in 0..2 {
+                target.put_pixel(x, y, &[0, 0, 0, 0xff]);
+            }
+        }
+        assert_eq!(vec![0, 0, 0, 0, 0xaa, 0xaa, 0, 0, 0, 0, 0xaa, 0xaa], pixels);
+    }
+
+    #[test]
+    fn test_sub_target() {
+        // 4x3 RGBA image, draw into the 2x1 rectangle at (1, 1)
+        let mut pixels = vec![0; 4 * 3 * 4];
+        let mut target = RenderTarget::packed(&mut pixels, 4, 3, PixelFormat::Rgba8888).unwrap();
+        assert_eq!(
+            Some(RenderTargetError::OutOfBounds),
+            target.sub_target(3, 0, 2, 1).err()
+        );
+
+        let mut sub = target.sub_target(1, 1, 2, 1).unwrap();
+        assert_eq!((2, 1), (sub.width(), sub.height()));
+        sub.put_pixel(0, 0, &ORANGE);
+        sub.put_pixel(1, 0, &ORANGE);
+
+        for y in 0..3 {
+            for x in 0..4 {
+                let i = 4 * (y * 4 + x);
+                let expected: &[u8] = if y == 1 && (x == 1 || x == 2) {
+                    &[0xff, 0x80, 0x10, 0xff]
+                } else {
+                    &[0, 0, 0, 0]
+                };
+                assert_eq!(expected, &pixels[i..i + 4], "x={} y={}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut pixels = vec![0; 10];
+        assert_eq!(
+            Some(RenderTargetError::StrideTooSmall {
+                stride: 5,
+                minimum: 6
+            }),
+            RenderTarget::new(&mut pixels, 2, 1, 5, PixelFormat::Rgb888).err()
+        );
+        assert_eq!(
+            Some(RenderTargetError::BufferTooSmall {
+                length: 10,
+                minimum: 12
+            }),
+            RenderTarget::packed(&mut pixels, 2, 2, PixelFormat::Rgb888).err()
+        );
+
+        // The last row needs no padding
+        assert!(RenderTarget::new(&mut pixels, 1, 2, 7, PixelFormat::Rgb888).is_ok());
+        assert!(RenderTarget::packed(&mut pixels, 0, 0, PixelFormat::Rgb888).is_ok());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut pixels = vec![0; 16];
+        let huge = usize::MAX / 2;
+        assert_eq!(
+            Some(RenderTargetError::TooLarge),
+            RenderTarget::packed(&mut pixels, huge, 1, PixelFormat::Rgba8888).err()
+        );
+        assert_eq!(
+            Some(RenderTargetError::TooLarge),
+            RenderTarget::new(&mut pixels, 1, huge, huge, PixelFormat::Rgb888).err()
+        );
+        assert_eq!(
+            Some(RenderTargetError::TooLarge),
+            get_minimum_length(1, 2, isize::MAX as usize, PixelFormat::Rgba8888).err()
+        );
+
+        let mut target = RenderTarget::packed(&mut pixels, 2, 2, PixelFormat::Rgba8888).unwrap();
+        assert_eq!(
+            Some(RenderTargetError::OutOfBounds),
+            target.sub_target(usize::MAX, 0, 2, 1).err()
+        );
+        assert_eq!(
+            Some(RenderTargetError::OutOfBounds),
+            target.sub_target(0, 1, 1, usize::MAX).err()
+        );
+    }
+}
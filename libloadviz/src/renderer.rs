@@ -1,78 +1,254 @@
-use bracket_noise::prelude::FastNoise;
+use crate::render_target::{PixelFormat, RenderTarget};
+use crate::theme::Theme;
+use crate::{battery::BatteryState, cpuload::CpuLoad};
 
-use crate::cpuload::CpuLoad;
+/// The background in transparent mode
+static TRANSPARENT: Rgba = [0, 0, 0, 0];
 
-static BG_COLOR_RGB: &[u8; 3] = &[0x30, 0x30, 0x90];
+/// A color with straight alpha, as opposed to premultiplied
+pub(crate) type Rgba = [u8; 4];
+
+/// What `FastNoise::new()` uses, so this is what the pictures have always
+/// looked like
+static DEFAULT_NOISE_SEED: u64 = 1337;
+
+/// How bright the picture is while disconnected, compared to normal
+static DISCONNECTED_BRIGHTNESS_0_TO_1: f32 = 0.6;
+
+/// Non-CPU metrics affecting the picture
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Weather {
+    /// Disk I/O activity, shown as rain falling from the clouds
+    pub rain_0_to_1: f32,
+
+    /// Network activity, shown as wind bending the flames and the rain.
+    /// Positive values blow to the right.
+    pub wind_m1_to_1: f32,
+
+    /// `wind_m1_to_1` integrated over time, in seconds. This decides how far
+    /// the wind has moved the clouds.
+    pub wind_distance: f32,
+
+    /// Battery charge, shown as the sky going from blue (full) to a starry
+    /// night (empty). `None` means no battery, which gives you the flat
+    /// background of the theme.
+    pub battery: Option<BatteryState>,
+
+    /// CPU power draw, shown as a warm glow rising from the bottom of the
+    /// image
+    pub glow_0_to_1: f32,
+
+    /// We're showing the load of another machine, but can't reach it. The
+    /// picture is drawn faded to gray.
+    pub disconnected: bool,
+}
+
+mod bars;
+mod blackbody;
+mod classic;
+mod cloud;
+mod flame;
+mod glow;
+mod layer;
+mod rain;
+mod sky;
+
+// Public for the screenshot crossfade
+pub mod srgb;
+
+pub use bars::Bars;
+pub use classic::Classic;
+pub use layer::{BlendMode, Fragment, Layer, LayerInput};
+
+/// One look for the picture. Each visualization draws every pixel itself, so
+/// a new one doesn't need to touch the drawing code of the others.
+///
+/// Register visualizations with [`Renderer::add_visualization()`]. The
+/// settings methods are called with the current settings when registering,
+/// and then whenever the settings change. By default they do nothing.
+pub trait Visualization: Send {
+    /// Used for choosing it, see [`Renderer::select_visualization()`]
+    fn name(&self) -> &str;
+
+    /// Precomputing palettes is best done here rather than while rendering
+    fn set_theme(&mut self, _theme: &Theme) {}
+
+    /// The same seed should always give you the same picture
+    fn set_noise_seed(&mut self, _seed: u64) {}
+
+    /// See [`Renderer::set_transparent_background()`]
+    fn set_transparent_background(&mut self, _transparent: bool) {}
+
+    /// Draw every pixel of `target`
+    fn render(
+        &self,
+        currently_displayed_loads: &[CpuLoad],
+        weather: &Weather,
+        dt_seconds: f32,
+        target: &mut RenderTarget,
+    );
+}
 
 pub struct Renderer {
-    noise: FastNoise,
+    /// [`Classic`] and [`Bars`], plus anything added by
+    /// [`add_visualization()`](Renderer::add_visualization)
+    visualizations: Vec<Box<dyn Visualization>>,
+
+    /// Index into `visualizations`
+    selected: usize,
+
+    theme: Theme,
+    noise_seed: u64,
+    transparent_background: bool,
 }
 
 impl Default for Renderer {
     fn default() -> Self {
-        return Self {
-            noise: FastNoise::new(),
+        let mut renderer = Self {
+            visualizations: vec![],
+            selected: 0,
+            theme: Default::default(),
+            noise_seed: DEFAULT_NOISE_SEED,
+            transparent_background: false,
         };
+        renderer.add_visualization(Box::<Classic>::default());
+        renderer.add_visualization(Box::<Bars>::default());
+        return renderer;
     }
 }
 
-mod cloud;
-mod flame;
-
 impl Renderer {
+    /// Colors to draw in, [`Theme::default()`] unless you change it
+    pub fn set_theme(&mut self, theme: Theme) {
+        for visualization in &mut self.visualizations {
+            visualization.set_theme(&theme);
+        }
+        self.theme = theme;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        return &self.theme;
+    }
+
+    /// The same seed, loads and time always give you the same picture.
+    /// Different seeds give you differently shaped flames and clouds.
+    pub fn set_noise_seed(&mut self, seed: u64) {
+        for visualization in &mut self.visualizations {
+            visualization.set_noise_seed(seed);
+        }
+        self.noise_seed = seed;
+    }
+
+    /// With a transparent background, flames and clouds fade towards
+    /// transparent rather than towards the background color. The sky isn't
+    /// drawn at all.
+    ///
+    /// Use a [`PixelFormat`] with alpha to get the transparency, the other
+    /// formats get the picture on black.
+    pub fn set_transparent_background(&mut self, transparent: bool) {
+        for visualization in &mut self.visualizations {
+            visualization.set_transparent_background(transparent);
+        }
+        self.transparent_background = transparent;
+    }
+
+    /// Makes a new look available to
+    /// [`select_visualization()`](Renderer::select_visualization). Replaces
+    /// any visualization with the same name.
+    pub fn add_visualization(&mut self, mut visualization: Box<dyn Visualization>) {
+        visualization.set_theme(&self.theme);
+        visualization.set_noise_seed(self.noise_seed);
+        visualization.set_transparent_background(self.transparent_background);
+
+        let existing = self
+            .visualizations
+            .iter()
+            .position(|registered| registered.name() == visualization.name());
+        if let Some(index) = existing {
+            self.visualizations[index] = visualization;
+        } else {
+            self.visualizations.push(visualization);
+        }
+    }
+
+    /// Choose what the picture looks like, `classic` by default
+    pub fn select_visualization(&mut self, name: &str) -> Result<(), String> {
+        self.selected = self
+            .visualizations
+            .iter()
+            .position(|visualization| visualization.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "No such visualization, try one of {}: {}",
+                    self.visualization_names().join(", "),
+                    name
+                )
+            })?;
+        return Ok(());
+    }
+
+    /// The selected one, see
+    /// [`select_visualization()`](Renderer::select_visualization)
+    pub fn visualization_name(&self) -> &str {
+        return self.visualizations[self.selected].name();
+    }
+
+    /// In the order they were added
+    pub fn visualization_names(&self) -> Vec<&str> {
+        return self
+            .visualizations
+            .iter()
+            .map(|visualization| visualization.name())
+            .collect();
+    }
+
     /// Don't call this! It's public for benchmarking purposes only.
     ///
-    /// You should call `LoadViz::render_image()` instead.
+    /// You should call `LoadViz::render()` instead.
     pub fn render_image(
         &self,
-        currently_displayed_loads: &Vec<CpuLoad>,
+        currently_displayed_loads: &[CpuLoad],
+        weather: &Weather,
         width: usize,
         height: usize,
         dt_seconds: f32,
         pixels: &mut [u8],
     ) {
-        if currently_displayed_loads.is_empty() {
-            // FIXME: Draw something nice?
-            return;
-        }
-        let viz_loads = mirror_sort(currently_displayed_loads);
-
-        for pixel_x in 0..width {
-            for pixel_y_from_top in 0..height {
-                let pixel_y_from_bottom = height - 1 - pixel_y_from_top;
-
-                let color = if let Some(flame_color) = self.get_flame_pixel(
-                    &viz_loads,
-                    dt_seconds,
-                    pixel_x,
-                    pixel_y_from_bottom,
-                    width,
-                    height,
-                ) {
-                    flame_color
-                } else if let Some(cloud_color) = self.get_cloud_pixel(
-                    &viz_loads,
-                    dt_seconds,
-                    pixel_x,
-                    pixel_y_from_top,
-                    width,
-                    height,
-                ) {
-                    cloud_color
-                } else {
-                    *BG_COLOR_RGB
-                };
-
-                let i = 3 * (pixel_y_from_top * width + pixel_x);
-                pixels[i] = color[0];
-                pixels[i + 1] = color[1];
-                pixels[i + 2] = color[2];
-            }
-        }
+        let mut target = RenderTarget::packed(pixels, width, height, PixelFormat::Rgb888)
+            .unwrap_or_else(|error| panic!("Can't render {}x{}: {}", width, height, error));
+        self.render_to(currently_displayed_loads, weather, dt_seconds, &mut target);
+    }
+
+    /// Like [`render_image()`](Renderer::render_image), but into any kind of
+    /// target
+    pub fn render_to(
+        &self,
+        currently_displayed_loads: &[CpuLoad],
+        weather: &Weather,
+        dt_seconds: f32,
+        target: &mut RenderTarget,
+    ) {
+        self.visualizations[self.selected].render(
+            currently_displayed_loads,
+            weather,
+            dt_seconds,
+            target,
+        );
     }
 }
 
-fn get_load(viz_loads: &Vec<CpuLoad>, x_fraction_0_to_1: f32) -> CpuLoad {
+/// Gray and dimmed, so that it's obvious we aren't showing live data
+fn get_disconnected_color(color: &Rgba) -> Rgba {
+    let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+    let gray = (luma * DISCONNECTED_BRIGHTNESS_0_TO_1) as u8;
+    return [gray, gray, gray, color[3]];
+}
+
+fn opaque(color: &[u8; 3]) -> Rgba {
+    return [color[0], color[1], color[2], 0xff];
+}
+
+fn get_load(viz_loads: &[CpuLoad], x_fraction_0_to_1: f32) -> CpuLoad {
     let flen = viz_loads.len() as f32;
     let float_part_index = (flen * x_fraction_0_to_1 - 0.5).clamp(0.0, flen - 1.0);
     let i0 = float_part_index.floor() as usize;
@@ -91,8 +267,8 @@ fn get_load(viz_loads: &Vec<CpuLoad>, x_fraction_0_to_1: f32) -> CpuLoad {
 }
 
 /// Turns `[3, 1, 2]` into `[1, 2, 3, 3, 2, 1]`
-fn mirror_sort(cpu_loads: &Vec<CpuLoad>) -> Vec<CpuLoad> {
-    let mut result = cpu_loads.clone();
+fn mirror_sort(cpu_loads: &[CpuLoad]) -> Vec<CpuLoad> {
+    let mut result = cpu_loads.to_owned();
 
     // Sort criteria is same as in `LoadViz::update_currently_displayed_loads()`
     result.sort_by(|a, b| a.partial_cmp(b).unwrap());
@@ -104,19 +280,51 @@ fn mirror_sort(cpu_loads: &Vec<CpuLoad>) -> Vec<CpuLoad> {
     return result;
 }
 
-fn interpolate(factor_0_to_1: f32, color1: &[u8; 3], color2: &[u8; 3]) -> [u8; 3] {
+/// Mixes in linear light, see [`srgb`]. Fading towards a transparent color
+/// fades out.
+fn interpolate(factor_0_to_1: f32, color1: &Rgba, color2: &Rgba) -> Rgba {
     let factor_0_to_1 = factor_0_to_1.clamp(0.0, 1.0);
 
-    let mut result = [0; 3];
+    if color1[3] == 0xff && color2[3] == 0xff {
+        // Both opaque, the only case without a transparent background
+        let mut result = [0xff; 4];
+        srgb::mix_rgb(factor_0_to_1, color1, color2, &mut result);
+        return result;
+    }
 
-    for i in 0..3 {
-        result[i] =
-            (color1[i] as f32 * (1.0 - factor_0_to_1) + color2[i] as f32 * factor_0_to_1) as u8;
+    // Weigh the colors by their alphas, so that transparent colors don't tint
+    // the result
+    let weight1 = color1[3] as f32 * (1.0 - factor_0_to_1);
+    let weight2 = color2[3] as f32 * factor_0_to_1;
+    let alpha = weight1 + weight2;
+    if alpha < 0.5 {
+        return TRANSPARENT;
     }
 
+    let mut result = [0; 4];
+    for i in 0..3 {
+        result[i] = srgb::from_linear(
+            (srgb::to_linear(color1[i]) * weight1 + srgb::to_linear(color2[i]) * weight2) / alpha,
+        );
+    }
+    result[3] = alpha.round() as u8;
     return result;
 }
 
+/// Cheap deterministic integer hash, mapped to 0-1.
+///
+/// Based on the MurmurHash3 finalizer.
+fn hash_0_to_1(n: u32) -> f32 {
+    let mut hash = n;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^= hash >> 16;
+
+    return hash as f32 / u32::MAX as f32;
+}
+
 fn pixel_to_fraction(pixel: f32, maxpixel: usize) -> f32 {
     return (pixel + 1.0) / (maxpixel + 2) as f32;
 }
@@ -129,13 +337,218 @@ mod tests {
 
     #[test]
     fn test_interpolate() {
-        let black: [u8; 3] = [0x00, 0x00, 0x00];
-        let white: [u8; 3] = [0xff, 0xff, 0xff];
+        let black: Rgba = [0x00, 0x00, 0x00, 0xff];
+        let white: Rgba = [0xff, 0xff, 0xff, 0xff];
 
         assert_eq!(black, super::interpolate(0.0, &black, &white));
         assert_eq!(white, super::interpolate(1.0, &black, &white));
     }
 
+    #[test]
+    fn test_interpolate_transparent() {
+        let orange: Rgba = [0xff, 0x80, 0x00, 0xff];
+
+        // Fading out keeps the color
+        assert_eq!(
+            [0xff, 0x80, 0x00, 0x40],
+            super::interpolate(0.75, &orange, &TRANSPARENT)
+        );
+        assert_eq!(TRANSPARENT, super::interpolate(1.0, &orange, &TRANSPARENT));
+        assert_eq!(orange, super::interpolate(1.0, &TRANSPARENT, &orange));
+    }
+
+    #[test]
+    fn test_render_transparent() {
+        let width = 10;
+        let height = 10;
+        let mut renderer: Renderer = Default::default();
+        renderer.set_transparent_background(true);
+        let mut pixels = vec![0xaa; width * height * 4];
+        let mut target =
+            RenderTarget::packed(&mut pixels, width, height, PixelFormat::Rgba8888).unwrap();
+
+        // No load, nothing to see
+        let idle = [CpuLoad {
+            user_0_to_1: 0.0,
+            system_0_to_1: 0.0,
+        }];
+        renderer.render_to(&idle, &Default::default(), 42.0, &mut target);
+        assert!(pixels.chunks(4).all(|pixel| pixel[3] == 0));
+
+        // Flames at the bottom, clouds at the top, all fading to transparent
+        let busy = [CpuLoad {
+            user_0_to_1: 0.5,
+            system_0_to_1: 0.5,
+        }];
+        let mut target =
+            RenderTarget::packed(&mut pixels, width, height, PixelFormat::Rgba8888).unwrap();
+        renderer.render_to(&busy, &Default::default(), 42.0, &mut target);
+        let alphas: Vec<u8> = pixels.chunks(4).map(|pixel| pixel[3]).collect();
+        assert!(alphas.contains(&0xff));
+        assert!(alphas.iter().any(|alpha| *alpha > 0 && *alpha < 0xff));
+
+        // No blue halo
+        for pixel in pixels.chunks(4) {
+            assert!(pixel[2] <= pixel[0], "Blueish: {:?}", pixel);
+        }
+    }
+
+    /// FNV-1a, so that we can compare whole images without checking them in
+    fn hash_pixels(pixels: &[u8]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in pixels {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        return hash;
+    }
+
+    /// Render some flames, clouds and weather, and hash the results
+    fn render_golden(renderer: &Renderer) -> Vec<u64> {
+        let (width, height) = (97, 61);
+        let loads = [
+            CpuLoad {
+                user_0_to_1: 0.9,
+                system_0_to_1: 0.1,
+            },
+            CpuLoad {
+                user_0_to_1: 0.3,
+                system_0_to_1: 0.6,
+            },
+            CpuLoad {
+                user_0_to_1: 0.5,
+                system_0_to_1: 0.3,
+            },
+        ];
+        let weathers = [
+            Weather::default(),
+            Weather {
+                rain_0_to_1: 0.8,
+                wind_m1_to_1: 0.5,
+                wind_distance: 3.0,
+                glow_0_to_1: 0.7,
+                battery: Some(BatteryState {
+                    charge_0_to_1: 0.3,
+                    charging: true,
+                }),
+                disconnected: false,
+            },
+            Weather {
+                disconnected: true,
+                ..Default::default()
+            },
+        ];
+
+        return weathers
+            .iter()
+            .map(|weather| {
+                let mut pixels = vec![0; width * height * 3];
+                renderer.render_image(&loads, weather, width, height, 12.5, &mut pixels);
+                return hash_pixels(&pixels);
+            })
+            .collect();
+    }
+
+    /// The classic theme should look exactly like the default one, and
+    /// neither should change by accident. When changing the looks on purpose,
+    /// update the hashes.
+    #[test]
+    fn test_classic_theme_golden() {
+        let expected = vec![0x5a61dea0bbf1da7c, 0x23815d978623bd2d, 0xa42a63138595ea5b];
+
+        let renderer: Renderer = Default::default();
+        assert_eq!(expected, render_golden(&renderer));
+
+        let mut renderer: Renderer = Default::default();
+        renderer.set_theme(Theme::preset("classic").unwrap());
+        assert_eq!(expected, render_golden(&renderer));
+    }
+
+    #[test]
+    fn test_noise_seed() {
+        let default = render_golden(&Default::default());
+
+        let mut renderer: Renderer = Default::default();
+        renderer.set_noise_seed(DEFAULT_NOISE_SEED);
+        assert_eq!(default, render_golden(&renderer));
+
+        renderer.set_noise_seed(42);
+        let seeded = render_golden(&renderer);
+        assert_ne!(default, seeded);
+        assert_eq!(seeded, render_golden(&renderer));
+    }
+
+    #[test]
+    fn test_themes_differ() {
+        let classic = render_golden(&Default::default());
+        for name in Theme::preset_names() {
+            if name == "classic" {
+                continue;
+            }
+            let mut renderer: Renderer = Default::default();
+            renderer.set_theme(Theme::preset(name).unwrap());
+            assert_ne!(classic[0], render_golden(&renderer)[0], "{}", name);
+        }
+    }
+
+    /// Paints everything in one color
+    struct Solid(&'static str, Rgba);
+
+    impl Visualization for Solid {
+        fn name(&self) -> &str {
+            return self.0;
+        }
+
+        fn render(&self, _: &[CpuLoad], _: &Weather, _: f32, target: &mut RenderTarget) {
+            for x in 0..target.width() {
+                for y in 0..target.height() {
+                    target.put_pixel(x, y, &self.1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_visualizations() {
+        let mut renderer: Renderer = Default::default();
+        assert_eq!("classic", renderer.visualization_name());
+        assert_eq!(vec!["classic", "bars"], renderer.visualization_names());
+        let classic = render_golden(&renderer);
+
+        renderer.select_visualization("bars").unwrap();
+        assert_eq!("bars", renderer.visualization_name());
+        assert_ne!(classic, render_golden(&renderer));
+
+        let error = renderer.select_visualization("sparkles").unwrap_err();
+        assert!(error.contains("classic, bars"), "{}", error);
+        assert_eq!("bars", renderer.visualization_name());
+
+        renderer.add_visualization(Box::new(Solid("solid", [0x12, 0x34, 0x56, 0xff])));
+        renderer.select_visualization("solid").unwrap();
+        let mut pixels = vec![0; 2 * 2 * 3];
+        renderer.render_image(&[], &Default::default(), 2, 2, 0.0, &mut pixels);
+        assert_eq!([0x12, 0x34, 0x56].repeat(4), pixels);
+
+        // Same name replaces
+        renderer.add_visualization(Box::new(Solid("solid", [0x65, 0x43, 0x21, 0xff])));
+        assert_eq!(
+            vec!["classic", "bars", "solid"],
+            renderer.visualization_names()
+        );
+        renderer.render_image(&[], &Default::default(), 2, 2, 0.0, &mut pixels);
+        assert_eq!([0x65, 0x43, 0x21].repeat(4), pixels);
+
+        // Settings reach the visualizations that aren't selected
+        let dark_panel = Theme::preset("dark-panel").unwrap();
+        renderer.set_theme(dark_panel.clone());
+        renderer.set_noise_seed(42);
+        renderer.select_visualization("classic").unwrap();
+        let mut expected: Renderer = Default::default();
+        expected.set_theme(dark_panel);
+        expected.set_noise_seed(42);
+        assert_eq!(render_golden(&expected), render_golden(&renderer));
+    }
+
     #[test]
     /// Test rendering an empty list of loads. The point is just that we
     /// shouldn't crash.
@@ -145,7 +558,94 @@ mod tests {
         let mut pixels = vec![0; width * height * 3];
         let renderer: Renderer = Default::default();
 
-        renderer.render_image(&Vec::new(), width, height, 42.0, &mut pixels);
+        renderer.render_image(&[], &Default::default(), width, height, 42.0, &mut pixels);
+    }
+
+    #[test]
+    fn test_render_disconnected() {
+        let width = 10;
+        let height = 10;
+        let mut pixels = vec![0; width * height * 3];
+        let renderer: Renderer = Default::default();
+        let loads = [CpuLoad {
+            user_0_to_1: 0.5,
+            system_0_to_1: 0.5,
+        }];
+        let weather = Weather {
+            disconnected: true,
+            ..Default::default()
+        };
+
+        renderer.render_image(&loads, &weather, width, height, 42.0, &mut pixels);
+
+        for pixel in pixels.chunks(3) {
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[1], pixel[2]);
+        }
+    }
+
+    /// Every format should show the same picture as plain RGB, with rows
+    /// padded and the padding left alone
+    #[test]
+    fn test_render_to_formats() {
+        let width = 7;
+        let height = 5;
+        let renderer: Renderer = Default::default();
+        let loads = [CpuLoad {
+            user_0_to_1: 0.7,
+            system_0_to_1: 0.2,
+        }];
+        let mut rgb = vec![0; width * height * 3];
+        renderer.render_image(&loads, &Default::default(), width, height, 42.0, &mut rgb);
+
+        for format in [
+            PixelFormat::Rgb888,
+            PixelFormat::Rgba8888,
+            PixelFormat::Bgra8888,
+            PixelFormat::Argb32Premultiplied,
+            PixelFormat::Rgb565,
+        ] {
+            let bytes_per_pixel = format.bytes_per_pixel();
+            let stride = width * bytes_per_pixel + 3;
+            let mut pixels = vec![0xaa; stride * height];
+            let mut target = RenderTarget::new(&mut pixels, width, height, stride, format).unwrap();
+            renderer.render_to(&loads, &Default::default(), 42.0, &mut target);
+
+            for y in 0..height {
+                let row = &pixels[y * stride..(y + 1) * stride];
+                assert_eq!([0xaa; 3], row[width * bytes_per_pixel..], "{:?}", format);
+
+                for x in 0..width {
+                    let expected = &rgb[3 * (y * width + x)..3 * (y * width + x) + 3];
+                    let pixel = &row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel];
+                    let (actual, expected) = match format {
+                        PixelFormat::Rgb888 => (pixel.to_vec(), expected.to_vec()),
+                        PixelFormat::Rgba8888 => (pixel.to_vec(), [expected, &[0xff]].concat()),
+                        PixelFormat::Bgra8888 => (
+                            pixel.to_vec(),
+                            vec![expected[2], expected[1], expected[0], 0xff],
+                        ),
+                        PixelFormat::Argb32Premultiplied => {
+                            let argb = u32::from_ne_bytes(pixel.try_into().unwrap());
+                            (argb.to_be_bytes().to_vec(), [&[0xff], expected].concat())
+                        }
+                        PixelFormat::Rgb565 => {
+                            // Compare the bits that made it into the pixel
+                            let rgb565 = u16::from_ne_bytes(pixel.try_into().unwrap());
+                            (
+                                vec![
+                                    (rgb565 >> 11) as u8,
+                                    (rgb565 >> 5) as u8 & 0x3f,
+                                    rgb565 as u8 & 0x1f,
+                                ],
+                                vec![expected[0] >> 3, expected[1] >> 2, expected[2] >> 3],
+                            )
+                        }
+                    };
+                    assert_eq!(expected, actual, "{:?} x={} y={}", format, x, y);
+                }
+            }
+        }
     }
 
     #[test]
@@ -155,7 +655,7 @@ mod tests {
 
     #[test]
     fn test_mirror_sort_same() {
-        let mirror_sorted = mirror_sort(&vec![
+        let mirror_sorted = mirror_sort(&[
             // This one is identical to...
             CpuLoad {
                 user_0_to_1: 0.1,
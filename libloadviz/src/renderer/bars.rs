@@ -0,0 +1,166 @@
+//! A plain bar chart, one bar per CPU. User load grows from the bottom in the
+//! flame colors, and system load hangs from the top in the cloud colors, just
+//! like the flames and clouds of the classic look.
+
+use crate::cpuload::CpuLoad;
+use crate::render_target::RenderTarget;
+use crate::theme::Theme;
+
+use super::{
+    flame, get_disconnected_color, interpolate, opaque, Rgba, Visualization, Weather, TRANSPARENT,
+};
+
+/// Bars at least this many pixels wide get a one pixel gap between them
+static MIN_GAPPED_BAR_WIDTH: usize = 3;
+
+pub struct Bars {
+    theme: Theme,
+
+    /// From [`flame::get_flame_colors()`], coolest to hottest
+    flame_colors: Vec<Rgba>,
+
+    transparent_background: bool,
+}
+
+impl Default for Bars {
+    fn default() -> Self {
+        let theme: Theme = Default::default();
+        return Self {
+            flame_colors: flame::get_flame_colors(&theme),
+            theme,
+            transparent_background: false,
+        };
+    }
+}
+
+impl Bars {
+    fn get_pixel(
+        &self,
+        load: &CpuLoad,
+        pixel_y_from_top: usize,
+        height: usize,
+        background: &Rgba,
+    ) -> Rgba {
+        let y_from_top_0_to_1 = (pixel_y_from_top as f32 + 0.5) / height as f32;
+        let y_from_bottom_0_to_1 = 1.0 - y_from_top_0_to_1;
+
+        if y_from_bottom_0_to_1 < load.user_0_to_1 {
+            // Hotter towards the top of the bar
+            let last_step = self.flame_colors.len() - 1;
+            let step = (y_from_bottom_0_to_1 * last_step as f32 + 0.5) as usize;
+            return self.flame_colors[step.min(last_step)];
+        }
+
+        if y_from_top_0_to_1 < load.system_0_to_1 {
+            // Brighter towards the bottom of the bar
+            return interpolate(
+                y_from_top_0_to_1,
+                &opaque(&self.theme.cloud_dark_rgb),
+                &opaque(&self.theme.cloud_bright_rgb),
+            );
+        }
+
+        return *background;
+    }
+}
+
+impl Visualization for Bars {
+    fn name(&self) -> &str {
+        return "bars";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.flame_colors = flame::get_flame_colors(theme);
+        self.theme = theme.clone();
+    }
+
+    fn set_transparent_background(&mut self, transparent: bool) {
+        self.transparent_background = transparent;
+    }
+
+    fn render(
+        &self,
+        currently_displayed_loads: &[CpuLoad],
+        weather: &Weather,
+        _dt_seconds: f32,
+        target: &mut RenderTarget,
+    ) {
+        let width = target.width();
+        let height = target.height();
+        if currently_displayed_loads.is_empty() || self.flame_colors.is_empty() {
+            return;
+        }
+
+        let background = if self.transparent_background {
+            TRANSPARENT
+        } else {
+            opaque(&self.theme.background_rgb)
+        };
+        let bar_count = currently_displayed_loads.len();
+        let gapped = width / bar_count >= MIN_GAPPED_BAR_WIDTH;
+
+        for pixel_x in 0..width {
+            let bar = (pixel_x * bar_count / width).min(bar_count - 1);
+            let is_gap = gapped && (pixel_x + 1) * bar_count / width != bar;
+            let load = &currently_displayed_loads[bar];
+
+            for pixel_y_from_top in 0..height {
+                let color = if is_gap {
+                    background
+                } else {
+                    self.get_pixel(load, pixel_y_from_top, height, &background)
+                };
+                let color = if weather.disconnected {
+                    get_disconnected_color(&color)
+                } else {
+                    color
+                };
+
+                target.put_pixel(pixel_x, pixel_y_from_top, &color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_target::PixelFormat;
+
+    #[test]
+    fn test_bars() {
+        let (width, height) = (8, 10);
+        let loads = [
+            CpuLoad {
+                user_0_to_1: 1.0,
+                system_0_to_1: 0.0,
+            },
+            CpuLoad {
+                user_0_to_1: 0.0,
+                system_0_to_1: 0.5,
+            },
+        ];
+        let bars: Bars = Default::default();
+        let mut pixels = vec![0; width * height * 3];
+        let mut target =
+            RenderTarget::packed(&mut pixels, width, height, PixelFormat::Rgb888).unwrap();
+        bars.render(&loads, &Default::default(), 0.0, &mut target);
+
+        let pixel = |x: usize, y: usize| &pixels[3 * (y * width + x)..3 * (y * width + x) + 3];
+        let background = Theme::default().background_rgb;
+
+        // Left bar all the way up, hottest and brightest at the top
+        let brightness = |rgb: &[u8]| rgb.iter().map(|c| *c as u32).sum::<u32>();
+        assert!(brightness(pixel(0, 0)) > brightness(pixel(0, height - 1)));
+        assert_ne!(background, *pixel(0, height - 1));
+
+        // Gap after each bar
+        assert_eq!(background, *pixel(3, 0));
+        assert_eq!(background, *pixel(7, height - 1));
+
+        // Right bar hangs half way down from the top
+        assert_ne!(background, *pixel(4, 0));
+        assert_ne!(background, *pixel(4, 4));
+        assert_eq!(background, *pixel(4, 5));
+    }
+}
@@ -0,0 +1,71 @@
+//! What color something glowing at a given temperature is.
+//!
+//! This is Tanner Helland's curve fit to Mitchell Charity's blackbody colors,
+//! which is where the flame colors originally came from:
+//! * <https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html>
+//! * <http://www.vendian.org/mncharity/dir3/blackbody/>
+//!
+//! The fit is made for 1000K-40000K. Below that, it keeps getting redder,
+//! which is good enough for flames.
+
+/// Too slow to call for every pixel, precompute a palette instead
+pub(super) fn blackbody_rgb(kelvin: f32) -> [u8; 3] {
+    let hecto_kelvin = kelvin as f64 / 100.0;
+
+    let red = if hecto_kelvin <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (hecto_kelvin - 60.0).powf(-0.1332047592)
+    };
+
+    let green = if hecto_kelvin <= 66.0 {
+        99.4708025861 * hecto_kelvin.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (hecto_kelvin - 60.0).powf(-0.0755148492)
+    };
+
+    let blue = if hecto_kelvin >= 66.0 {
+        255.0
+    } else if hecto_kelvin <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (hecto_kelvin - 10.0).ln() - 305.0447927307
+    };
+
+    // Negative temperatures give us NaN. clamp() passes that through, and
+    // "as" turns it into 0.
+    return [red, green, blue].map(|component| component.clamp(0.0, 255.0).round() as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: [u8; 3], actual: [u8; 3]) {
+        for i in 0..3 {
+            assert!(
+                (expected[i] as i32 - actual[i] as i32).abs() <= 12,
+                "Expected {:02x?}, got {:02x?}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    /// Compare to Mitchell Charity's table
+    #[test]
+    fn test_blackbody_rgb() {
+        assert_close([0xff, 0x38, 0x00], blackbody_rgb(1000.0));
+        assert_close([0xff, 0xb4, 0x6b], blackbody_rgb(3000.0));
+        assert_close([0xff, 0xf9, 0xfd], blackbody_rgb(6500.0));
+        assert_close([0xcc, 0xdb, 0xff], blackbody_rgb(10000.0));
+    }
+
+    #[test]
+    fn test_colder_is_redder() {
+        let [_, green, blue] = blackbody_rgb(800.0);
+        assert!(green < 0x38);
+        assert_eq!(0, blue);
+        assert_eq!([255, 0, 0], blackbody_rgb(0.0));
+    }
+}
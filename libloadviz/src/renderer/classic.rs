@@ -0,0 +1,377 @@
+//! The original look: flames at the bottom, clouds at the top, and weather
+//! in between.
+
+use std::ops::Range;
+
+use crate::cpuload::CpuLoad;
+use crate::render_target::RenderTarget;
+use crate::theme::Theme;
+
+use super::cloud::CloudLayer;
+use super::flame::FlameLayer;
+use super::glow::GlowLayer;
+use super::layer::{composite, BlendMode, Layer, LayerInput};
+use super::rain::RainLayer;
+use super::sky::SkyLayer;
+use super::{
+    get_disconnected_color, get_load, mirror_sort, pixel_to_fraction, Visualization, Weather,
+    DEFAULT_NOISE_SEED, TRANSPARENT,
+};
+
+struct StackedLayer {
+    layer: Box<dyn Layer>,
+    blend_mode: BlendMode,
+
+    /// See [`Classic::set_knockout()`]
+    knockout: bool,
+}
+
+/// A stack of [`Layer`]s, composited bottom first.
+///
+/// Neighboring knockout layers form a group where only the topmost layer
+/// covering a pixel is drawn, see [`Classic::set_knockout()`].
+///
+/// To add layers of your own, insert them into a new `Classic` and replace the
+/// built in one with it using
+/// [`Renderer::add_visualization()`](super::Renderer::add_visualization).
+pub struct Classic {
+    /// Bottom first
+    layers: Vec<StackedLayer>,
+
+    /// For layers inserted later
+    theme: Theme,
+    noise_seed: u64,
+    transparent_background: bool,
+}
+
+impl Default for Classic {
+    fn default() -> Self {
+        let mut classic = Self {
+            layers: vec![],
+            theme: Default::default(),
+            noise_seed: DEFAULT_NOISE_SEED,
+            transparent_background: false,
+        };
+
+        let layers: [Box<dyn Layer>; 5] = [
+            Box::<SkyLayer>::default(),
+            Box::<GlowLayer>::default(),
+            Box::<RainLayer>::default(),
+            Box::<CloudLayer>::default(),
+            Box::<FlameLayer>::default(),
+        ];
+        for layer in layers {
+            classic.insert_layer(classic.layers.len(), layer, BlendMode::Normal);
+        }
+
+        // Flames hide clouds, and both hide rain, rather than showing through
+        for name in ["rain", "clouds", "flames"] {
+            classic.set_knockout(name, true).unwrap();
+        }
+        return classic;
+    }
+}
+
+impl Classic {
+    /// Bottom first: `sky`, `glow`, `rain`, `clouds`, `flames`, plus anything
+    /// inserted
+    pub fn layer_names(&self) -> Vec<&str> {
+        return self
+            .layers
+            .iter()
+            .map(|stacked| stacked.layer.name())
+            .collect();
+    }
+
+    /// Put `layer` right on top of the layer called `below`. To draw
+    /// something in front of the clouds but behind the flames, insert it
+    /// above `clouds`.
+    pub fn insert_layer_above(
+        &mut self,
+        below: &str,
+        layer: Box<dyn Layer>,
+        blend_mode: BlendMode,
+    ) -> Result<(), String> {
+        let index = self.find_layer(below)?;
+        self.insert_layer(index + 1, layer, blend_mode);
+        return Ok(());
+    }
+
+    /// How the layer called `name` is combined with the layers below it,
+    /// [`BlendMode::Normal`] by default
+    pub fn set_blend_mode(&mut self, name: &str, blend_mode: BlendMode) -> Result<(), String> {
+        let index = self.find_layer(name)?;
+        self.layers[index].blend_mode = blend_mode;
+        return Ok(());
+    }
+
+    /// Neighboring knockout layers form a group. Where several layers of a
+    /// group cover a pixel, only the topmost one is drawn, on top of what's
+    /// below the group. Layers further down the group don't show through its
+    /// partially covering edges.
+    ///
+    /// `rain`, `clouds` and `flames` are knockout layers by default, so the
+    /// edges of the flames fade into the sky and not into the clouds. Inserted
+    /// layers are not, and split any group they are inserted into.
+    pub fn set_knockout(&mut self, name: &str, knockout: bool) -> Result<(), String> {
+        let index = self.find_layer(name)?;
+        self.layers[index].knockout = knockout;
+        return Ok(());
+    }
+
+    /// Indices into `layers`, bottom first. Every knockout group is one
+    /// range, and every other layer a range of its own.
+    fn get_groups(&self) -> Vec<Range<usize>> {
+        let mut groups: Vec<Range<usize>> = vec![];
+        for (index, stacked) in self.layers.iter().enumerate() {
+            if let Some(group) = groups.last_mut() {
+                if stacked.knockout && self.layers[group.start].knockout {
+                    group.end = index + 1;
+                    continue;
+                }
+            }
+            groups.push(index..index + 1);
+        }
+        return groups;
+    }
+
+    fn find_layer(&self, name: &str) -> Result<usize, String> {
+        return self
+            .layers
+            .iter()
+            .position(|stacked| stacked.layer.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "No such layer, try one of {}: {}",
+                    self.layer_names().join(", "),
+                    name
+                )
+            });
+    }
+
+    fn insert_layer(&mut self, index: usize, mut layer: Box<dyn Layer>, blend_mode: BlendMode) {
+        layer.set_theme(&self.theme);
+        layer.set_noise_seed(self.noise_seed);
+        layer.set_transparent_background(self.transparent_background);
+        self.layers.insert(
+            index,
+            StackedLayer {
+                layer,
+                blend_mode,
+                knockout: false,
+            },
+        );
+    }
+}
+
+impl Visualization for Classic {
+    fn name(&self) -> &str {
+        return "classic";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        for stacked in &mut self.layers {
+            stacked.layer.set_theme(theme);
+        }
+        self.theme = theme.clone();
+    }
+
+    fn set_noise_seed(&mut self, seed: u64) {
+        for stacked in &mut self.layers {
+            stacked.layer.set_noise_seed(seed);
+        }
+        self.noise_seed = seed;
+    }
+
+    fn set_transparent_background(&mut self, transparent: bool) {
+        for stacked in &mut self.layers {
+            stacked.layer.set_transparent_background(transparent);
+        }
+        self.transparent_background = transparent;
+    }
+
+    fn render(
+        &self,
+        currently_displayed_loads: &[CpuLoad],
+        weather: &Weather,
+        dt_seconds: f32,
+        target: &mut RenderTarget,
+    ) {
+        let width = target.width();
+        let height = target.height();
+        if currently_displayed_loads.is_empty() {
+            // FIXME: Draw something nice?
+            return;
+        }
+        let viz_loads = mirror_sort(currently_displayed_loads);
+
+        // Computing these for every pixel in every layer is measurably slower
+        let highest_load = CpuLoad {
+            user_0_to_1: viz_loads
+                .iter()
+                .map(|load| load.user_0_to_1)
+                .fold(0.0, f32::max),
+            system_0_to_1: viz_loads
+                .iter()
+                .map(|load| load.system_0_to_1)
+                .fold(0.0, f32::max),
+        };
+        let groups = self.get_groups();
+        let mut rows = vec![0..0; self.layers.len()];
+        for pixel_x in 0..width {
+            let mut input = LayerInput {
+                viz_loads: &viz_loads,
+                column_load: get_load(&viz_loads, pixel_to_fraction(pixel_x as f32, width)),
+                highest_load,
+                weather,
+                dt_seconds,
+                pixel_x,
+                pixel_y_from_top: 0,
+                width,
+                height,
+            };
+            for (rows, stacked) in rows.iter_mut().zip(&self.layers) {
+                *rows = stacked.layer.get_rows(&input);
+            }
+
+            for pixel_y_from_top in 0..height {
+                input.pixel_y_from_top = pixel_y_from_top;
+
+                let mut color = TRANSPARENT;
+                for group in &groups {
+                    // Topmost first, so that hidden layers are never asked
+                    for index in group.clone().rev() {
+                        if !rows[index].contains(&pixel_y_from_top) {
+                            continue;
+                        }
+                        let stacked = &self.layers[index];
+                        if let Some(fragment) = stacked.layer.get_pixel(&input) {
+                            color = composite(&color, &fragment, stacked.blend_mode);
+                            break;
+                        }
+                    }
+                }
+                let color = if weather.disconnected {
+                    get_disconnected_color(&color)
+                } else {
+                    color
+                };
+
+                target.put_pixel(pixel_x, pixel_y_from_top, &color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_target::PixelFormat;
+    use crate::renderer::layer::Fragment;
+
+    /// Covers everything in one color, fully unless told otherwise
+    struct Fog([u8; 3], f32);
+
+    fn fog(rgb: [u8; 3]) -> Box<Fog> {
+        return Box::new(Fog(rgb, 1.0));
+    }
+
+    impl Layer for Fog {
+        fn name(&self) -> &str {
+            return "fog";
+        }
+
+        fn get_pixel(&self, _input: &LayerInput) -> Option<Fragment> {
+            return Some(Fragment {
+                rgb: self.0,
+                coverage_0_to_1: self.1,
+            });
+        }
+    }
+
+    fn render(classic: &Classic) -> Vec<u8> {
+        return render_with_clouds(classic, 0.0);
+    }
+
+    fn render_with_clouds(classic: &Classic, system_0_to_1: f32) -> Vec<u8> {
+        let (width, height) = (10, 10);
+        let loads = [CpuLoad {
+            user_0_to_1: 0.5,
+            system_0_to_1,
+        }];
+        let mut pixels = vec![0; width * height * 3];
+        let mut target =
+            RenderTarget::packed(&mut pixels, width, height, PixelFormat::Rgb888).unwrap();
+        classic.render(&loads, &Default::default(), 0.0, &mut target);
+        return pixels;
+    }
+
+    #[test]
+    fn test_insert_layer() {
+        let mut classic: Classic = Default::default();
+        assert_eq!(
+            vec!["sky", "glow", "rain", "clouds", "flames"],
+            classic.layer_names()
+        );
+        let without_fog = render(&classic);
+
+        classic
+            .insert_layer_above("clouds", fog([0x40, 0x40, 0x40]), BlendMode::Normal)
+            .unwrap();
+        assert_eq!(
+            vec!["sky", "glow", "rain", "clouds", "fog", "flames"],
+            classic.layer_names()
+        );
+        let with_fog = render(&classic);
+
+        // Fog in the sky at the top, flames in front of the fog at the bottom
+        assert_eq!([0x40, 0x40, 0x40], with_fog[0..3]);
+        let bottom = with_fog.len() - 3;
+        assert_ne!([0x40, 0x40, 0x40], with_fog[bottom..]);
+        assert_eq!(without_fog[bottom..], with_fog[bottom..]);
+
+        // Multiplying darkens the sky rather than replacing it
+        classic.set_blend_mode("fog", BlendMode::Multiply).unwrap();
+        let multiplied = render(&classic);
+        assert!(multiplied[2] < without_fog[2]);
+        assert_ne!(with_fog[0..3], multiplied[0..3]);
+    }
+
+    #[test]
+    fn test_no_such_layer() {
+        let mut classic: Classic = Default::default();
+        let error = classic
+            .insert_layer_above("sparks", fog([0, 0, 0]), BlendMode::Add)
+            .unwrap_err();
+        assert!(
+            error.contains("sky, glow, rain, clouds, flames"),
+            "{}",
+            error
+        );
+        assert!(classic.set_blend_mode("fog", BlendMode::Add).is_err());
+        assert!(classic.set_knockout("fog", true).is_err());
+        assert_eq!(5, classic.layer_names().len());
+    }
+
+    #[test]
+    fn test_knockout() {
+        let mut classic: Classic = Default::default();
+        classic
+            .insert_layer_above(
+                "clouds",
+                Box::new(Fog([0x40, 0x40, 0x40], 0.5)),
+                BlendMode::Normal,
+            )
+            .unwrap();
+
+        // The clouds show through the fog
+        let sky_only = render_with_clouds(&classic, 0.0);
+        let cloudy = render_with_clouds(&classic, 1.0);
+        assert_ne!(sky_only[0..3], cloudy[0..3]);
+
+        // In the same knockout group as the clouds, the fog hides them and
+        // lets the sky show through instead
+        classic.set_knockout("fog", true).unwrap();
+        assert_eq!(sky_only[0..3], render_with_clouds(&classic, 1.0)[0..3]);
+    }
+}
@@ -1,39 +1,71 @@
-use crate::cpuload::CpuLoad;
-
-use super::{get_load, interpolate, pixel_to_fraction, Renderer, BG_COLOR_RGB};
-
-static CLOUD_COLOR_DARK: &[u8; 3] = &[0x88, 0x88, 0x88];
-static CLOUD_COLOR_BRIGHT: &[u8; 3] = &[0xff, 0xff, 0xff];
-
-/// How much of the cloud should fade towards transparent?
-///
-/// This is a fraction of the height of the whole image, not a fraction of the
-/// height of the cloud.
-///
-/// Lower values make the cloud edge sharper.
-static CLOUD_TRANSPARENT_FRACTION: f32 = 0.4;
-
-impl Renderer {
-    pub(super) fn get_cloud_pixel(
-        &self,
-        viz_loads: &Vec<CpuLoad>,
-        dt_seconds: f32,
-        pixel_x: usize,
-        pixel_y_from_top: usize,
-        width: usize,
-        height: usize,
-    ) -> Option<[u8; 3]> {
+use std::ops::Range;
+
+use bracket_noise::prelude::FastNoise;
+
+use crate::theme::Theme;
+
+use super::layer::{Fragment, Layer, LayerInput};
+use super::{interpolate, opaque, pixel_to_fraction, Rgba};
+
+/// How many cloud colors to precompute, from dark to bright
+static CLOUD_COLOR_STEPS: usize = 256;
+
+/// In full wind, clouds drift this many image widths per second
+static CLOUD_DRIFT_WIDTHS_PER_SECOND: f32 = 0.2;
+
+/// System load, hanging from the top of the image
+pub(super) struct CloudLayer {
+    noise: FastNoise,
+
+    /// From [`get_cloud_colors()`], dark to bright
+    colors: Vec<Rgba>,
+
+    /// See [`Theme::cloud_transparent_0_to_1`]
+    transparent_0_to_1: f32,
+}
+
+impl Default for CloudLayer {
+    fn default() -> Self {
+        return Self {
+            noise: FastNoise::new(),
+            colors: vec![],
+            transparent_0_to_1: Theme::default().cloud_transparent_0_to_1,
+        };
+    }
+}
+
+impl Layer for CloudLayer {
+    fn name(&self) -> &str {
+        return "clouds";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.colors = get_cloud_colors(theme);
+        self.transparent_0_to_1 = theme.cloud_transparent_0_to_1;
+    }
+
+    fn set_noise_seed(&mut self, seed: u64) {
+        self.noise.set_seed(seed);
+    }
+
+    fn get_rows(&self, input: &LayerInput) -> Range<usize> {
+        // Below this, pixel_to_fraction() is below the cloud
+        let rows = (input.column_load.system_0_to_1 * (input.height + 2) as f32).ceil() as usize;
+        return 0..rows.min(input.height);
+    }
+
+    fn get_pixel(&self, input: &LayerInput) -> Option<Fragment> {
+        let (pixel_x, pixel_y_from_top) = (input.pixel_x, input.pixel_y_from_top);
+        let (width, height) = (input.width, input.height);
+
         // Higher number = more details.
         let detail = 5.0 / width as f32;
 
         // Higher speed number = faster cloud turbulence.
         let speed = 0.3;
 
-        let x_fraction_0_to_1 = pixel_to_fraction(pixel_x as f32, width);
-        let cpu_load = get_load(viz_loads, x_fraction_0_to_1);
-
         // Compute the sysload height for this load
-        let cloud_height_0_to_1 = cpu_load.system_0_to_1;
+        let cloud_height_0_to_1 = input.column_load.system_0_to_1;
         let y_from_top_0_to_1 = pixel_to_fraction(pixel_y_from_top as f32, height);
         if y_from_top_0_to_1 > cloud_height_0_to_1 {
             return None;
@@ -41,38 +73,68 @@ impl Renderer {
 
         // Noise output is -1 to 1, deciphered from here:
         // https://github.com/amethyst/bracket-lib/blob/0d2d5e6a9a8e7c7ae3710cfef85be4cab0109a27/bracket-noise/examples/simplex_fractal.rs#L34-L39
+        //
+        // Only the cloud texture drifts with the wind, the cloud shape is
+        // decided by the loads.
+        let drift_pixels =
+            input.weather.wind_distance * CLOUD_DRIFT_WIDTHS_PER_SECOND * width as f32;
         let noise_m1_to_1 = self.noise.get_noise3d(
-            detail * pixel_x as f32,
+            detail * (pixel_x as f32 - drift_pixels),
             detail * pixel_y_from_top as f32,
-            speed * dt_seconds,
+            speed * input.dt_seconds,
         );
 
         let brightness_0_to_1 = (noise_m1_to_1 + 1.0) / 2.0;
-        let color = interpolate(brightness_0_to_1, CLOUD_COLOR_DARK, CLOUD_COLOR_BRIGHT);
+        let step = (brightness_0_to_1 * (CLOUD_COLOR_STEPS - 1) as f32 + 0.5) as usize;
+        let color = self.colors[step.min(CLOUD_COLOR_STEPS - 1)];
+        let rgb = [color[0], color[1], color[2]];
 
-        let opaque_height_0_to_1 = cloud_height_0_to_1 - CLOUD_TRANSPARENT_FRACTION;
+        let transparent_fraction = self.transparent_0_to_1;
+        let opaque_height_0_to_1 = cloud_height_0_to_1 - transparent_fraction;
         if y_from_top_0_to_1 < opaque_height_0_to_1 {
             // Cloud interior
-            return Some(color);
+            return Some(Fragment {
+                rgb,
+                coverage_0_to_1: 1.0,
+            });
         }
 
         // When we get here, we're closer to the edge of the cloud
 
         // 0-1, higher means more transparent
-        let alpha = (y_from_top_0_to_1 - opaque_height_0_to_1) / CLOUD_TRANSPARENT_FRACTION;
+        let alpha = (y_from_top_0_to_1 - opaque_height_0_to_1) / transparent_fraction;
 
         // Replace dark with transparent. Towards the edge of the cloud, we won't
         // see as many dark colors since the sun won't be blocked by thick cloud
         // parts.
-        let color = interpolate(alpha * (1.0 - brightness_0_to_1), &color, BG_COLOR_RGB);
+        let darkness_fade_0_to_1 = alpha * (1.0 - brightness_0_to_1);
 
-        return Some(interpolate(alpha, &color, BG_COLOR_RGB));
+        // Fading first by one and then by the other, in one go
+        return Some(Fragment {
+            rgb,
+            coverage_0_to_1: (1.0 - darkness_fade_0_to_1) * (1.0 - alpha),
+        });
     }
 }
 
+/// The cloud gradient is mixed in linear light, which is too slow to do for
+/// every pixel
+pub(super) fn get_cloud_colors(theme: &Theme) -> Vec<Rgba> {
+    return (0..CLOUD_COLOR_STEPS)
+        .map(|step| {
+            interpolate(
+                step as f32 / (CLOUD_COLOR_STEPS - 1) as f32,
+                &opaque(&theme.cloud_dark_rgb),
+                &opaque(&theme.cloud_bright_rgb),
+            )
+        })
+        .collect();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cpuload::CpuLoad;
 
     #[test]
     fn test_no_load_no_cloud() {
@@ -80,8 +142,10 @@ mod tests {
             user_0_to_1: 0.0,
             system_0_to_1: 0.0,
         }];
-        let renderer: Renderer = Default::default();
-        let pixel = renderer.get_cloud_pixel(&viz_loads, 0.0, 0, 0, 1, 1);
+        let mut clouds: CloudLayer = Default::default();
+        clouds.set_theme(&Default::default());
+        let weather = Default::default();
+        let pixel = clouds.get_pixel(&LayerInput::for_pixel(&viz_loads, &weather, (0, 0), (1, 1)));
         assert_eq!(pixel, None);
     }
 }
@@ -1,26 +1,74 @@
 use std::ops::Range;
 
+use bracket_noise::prelude::FastNoise;
+
 use crate::cpuload::CpuLoad;
+use crate::theme::{FlameColors, Theme};
+
+use super::blackbody::blackbody_rgb;
+use super::layer::{Fragment, Layer, LayerInput};
+use super::{get_load, interpolate, opaque, pixel_to_fraction, Rgba};
+
+/// How many flame colors to precompute, from the coolest to the hottest
+static FLAME_COLOR_STEPS: usize = 256;
+
+/// In full wind, the top of the image gets shifted sideways by this fraction of
+/// the image height.
+static WIND_LEAN_0_TO_1: f32 = 0.3;
+
+/// User load, rising from the bottom of the image. The top layer.
+pub(super) struct FlameLayer {
+    noise: FastNoise,
+
+    /// From [`get_flame_colors()`], coolest to hottest
+    colors: Vec<Rgba>,
+
+    /// See [`Theme::flame_transparent_0_to_1`]
+    transparent_0_to_1: f32,
+}
+
+impl Default for FlameLayer {
+    fn default() -> Self {
+        return Self {
+            noise: FastNoise::new(),
+            colors: vec![],
+            transparent_0_to_1: Theme::default().flame_transparent_0_to_1,
+        };
+    }
+}
+
+impl Layer for FlameLayer {
+    fn name(&self) -> &str {
+        return "flames";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.colors = get_flame_colors(theme);
+        self.transparent_0_to_1 = theme.flame_transparent_0_to_1;
+    }
+
+    fn set_noise_seed(&mut self, seed: u64) {
+        self.noise.set_seed(seed);
+    }
+
+    /// Check whether we should even try to do flames maths. This improves our
+    /// idle-system benchmark by 63%.
+    fn get_rows(&self, input: &LayerInput) -> Range<usize> {
+        let height = input.height;
+        let highest_possible_flame_height_pixels = input.highest_load.user_0_to_1 * height as f32
+            + get_distortion_pixel_radius(input.width, height);
+
+        // Above this, we're above all flames, no need for any (costly) noise
+        // maths
+        let rows = (highest_possible_flame_height_pixels.floor() as usize + 1).min(height);
+        return height - rows..height;
+    }
+
+    fn get_pixel(&self, input: &LayerInput) -> Option<Fragment> {
+        let (viz_loads, dt_seconds) = (input.viz_loads, input.dt_seconds);
+        let (pixel_x, pixel_y_from_bottom) = (input.pixel_x, input.pixel_y_from_bottom());
+        let (width, height) = (input.width, input.height);
 
-use super::{get_load, interpolate, pixel_to_fraction, Renderer, BG_COLOR_RGB};
-
-// Blackbody RGB values from: http://www.vendian.org/mncharity/dir3/blackbody/
-static USER_LOAD_COLOR_RGB_WARMER: &[u8; 3] = &[0xff, 0xb4, 0x6b]; // 3000K
-static USER_LOAD_COLOR_RGB_COOLER: &[u8; 3] = &[0xff, 0x38, 0x00]; // 1000K
-
-// What fraction of the inside of the fire fades towards transparent?
-static TRANSPARENT_INTERNAL_0_TO_1: f32 = 0.3;
-
-impl Renderer {
-    pub(super) fn get_flame_pixel(
-        &self,
-        viz_loads: &Vec<CpuLoad>,
-        dt_seconds: f32,
-        pixel_x: usize,
-        pixel_y_from_bottom: usize,
-        width: usize,
-        height: usize,
-    ) -> Option<[u8; 3]> {
         // This number determines how uneven the edge of the fire is. Also, it
         // decides how much warping happens to the internal base image.
         let distortion_detail = 7.0 / width as f32;
@@ -30,23 +78,7 @@ impl Renderer {
         // changing this number.
         let internal_detail = 6.0 / width as f32;
 
-        let distortion_pixel_radius = width.min(height) as f32 / 10.0;
-
-        // Check whether we should even try to do flames maths. This improves
-        // our idle-system benchmark by 63%.
-        let highest_load_0_to_1 = viz_loads
-            .iter()
-            .map(|load| load.user_0_to_1)
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap();
-        let highest_possible_flame_height_pixels =
-            highest_load_0_to_1 * height as f32 + distortion_pixel_radius;
-        if pixel_y_from_bottom as f32 > highest_possible_flame_height_pixels {
-            // We're above all flames, no need for any (costly) noise maths
-            //
-            // This check improves our idle-system benchmark by 63%.
-            return None;
-        }
+        let distortion_pixel_radius = get_distortion_pixel_radius(width, height);
 
         // Noise output is -1 to 1, deciphered from here:
         // https://github.com/amethyst/bracket-lib/blob/0d2d5e6a9a8e7c7ae3710cfef85be4cab0109a27/bracket-noise/examples/simplex_fractal.rs#L34-L39
@@ -56,8 +88,16 @@ impl Renderer {
             dt_seconds,
         );
 
+        // Bend the flames with the wind, more so the higher up we get
+        let y_from_bottom_pixels = pixel_y_from_bottom as f32;
+        let wind_dx_pixels = input.weather.wind_m1_to_1
+            * WIND_LEAN_0_TO_1
+            * y_from_bottom_pixels
+            * y_from_bottom_pixels
+            / height as f32;
+
         // Pick the load to show
-        let dx_pixels = noise1_m1_to_1 * distortion_pixel_radius;
+        let dx_pixels = noise1_m1_to_1 * distortion_pixel_radius - wind_dx_pixels;
         let distorted_pixel_x = pixel_x as f32 + dx_pixels;
         let x_fraction_0_to_1 = pixel_to_fraction(distorted_pixel_x, width);
         let cpu_load = get_load(viz_loads, x_fraction_0_to_1);
@@ -100,10 +140,14 @@ impl Renderer {
         let temperature_0_to_1 =
             temperature_0_to_1 * get_cooling_factor(y_from_bottom_0_to_1, cpu_load);
 
-        return Some(get_color_by_temperature(temperature_0_to_1));
+        return self.get_fragment_by_temperature(temperature_0_to_1);
     }
 }
 
+fn get_distortion_pixel_radius(width: usize, height: usize) -> f32 {
+    return width.min(height) as f32 / 10.0;
+}
+
 fn map_range(value: f32, from: Range<f32>, to: Range<f32>) -> f32 {
     return (value - from.start) * (to.end - to.start) / (from.end - from.start) + to.start;
 }
@@ -127,25 +171,93 @@ fn get_cooling_factor(y_from_bottom_0_to_1: f32, cpu_load: CpuLoad) -> f32 {
     }
 }
 
-fn get_color_by_temperature(temperature_0_to_1: f32) -> [u8; 3] {
-    if temperature_0_to_1 < TRANSPARENT_INTERNAL_0_TO_1 {
-        return interpolate(
-            temperature_0_to_1 / TRANSPARENT_INTERNAL_0_TO_1,
-            BG_COLOR_RGB,
-            USER_LOAD_COLOR_RGB_COOLER,
-        );
+impl FlameLayer {
+    fn get_fragment_by_temperature(&self, temperature_0_to_1: f32) -> Option<Fragment> {
+        let Some(coolest) = self.colors.first() else {
+            // Still hides what's behind the flames, see Classic::set_knockout()
+            return Some(Fragment {
+                rgb: [0, 0, 0],
+                coverage_0_to_1: 0.0,
+            });
+        };
+
+        let transparent_0_to_1 = self.transparent_0_to_1;
+        if temperature_0_to_1 < transparent_0_to_1 {
+            return Some(Fragment {
+                rgb: [coolest[0], coolest[1], coolest[2]],
+                coverage_0_to_1: temperature_0_to_1 / transparent_0_to_1,
+            });
+        }
+
+        let last_step = self.colors.len() - 1;
+        let step = ((temperature_0_to_1 - transparent_0_to_1) / (1.0 - transparent_0_to_1)
+            * last_step as f32
+            + 0.5) as usize;
+        let color = self.colors[step.min(last_step)];
+        return Some(Fragment {
+            rgb: [color[0], color[1], color[2]],
+            coverage_0_to_1: 1.0,
+        });
+    }
+}
+
+/// The flame colors of the theme, spread evenly over the temperatures above
+/// the transparent ones. Mixing them in linear light, or computing blackbody
+/// colors, is too slow to do for every pixel.
+pub(super) fn get_flame_colors(theme: &Theme) -> Vec<Rgba> {
+    let stops = match &theme.flame_colors {
+        FlameColors::Gradient(stops) => stops,
+        FlameColors::Blackbody {
+            coolest_kelvin,
+            hottest_kelvin,
+        } => {
+            return (0..FLAME_COLOR_STEPS)
+                .map(|step| {
+                    let fraction_0_to_1 = step as f32 / (FLAME_COLOR_STEPS - 1) as f32;
+                    let kelvin =
+                        coolest_kelvin + (hottest_kelvin - coolest_kelvin) * fraction_0_to_1;
+                    return opaque(&blackbody_rgb(kelvin));
+                })
+                .collect();
+        }
+    };
+
+    if stops.len() < 2 {
+        return stops.iter().map(opaque).collect();
     }
 
-    return interpolate(
-        (temperature_0_to_1 - TRANSPARENT_INTERNAL_0_TO_1) / (1.0 - TRANSPARENT_INTERNAL_0_TO_1),
-        USER_LOAD_COLOR_RGB_COOLER,
-        USER_LOAD_COLOR_RGB_WARMER,
-    );
+    return (0..FLAME_COLOR_STEPS)
+        .map(|step| {
+            let stop_index =
+                step as f32 / (FLAME_COLOR_STEPS - 1) as f32 * (stops.len() - 1) as f32;
+            let i0 = (stop_index.floor() as usize).min(stops.len() - 2);
+            return interpolate(
+                stop_index - i0 as f32,
+                &opaque(&stops[i0]),
+                &opaque(&stops[i0 + 1]),
+            );
+        })
+        .collect();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::renderer::layer::{composite, BlendMode};
+
+    fn flame_layer(theme: &Theme) -> FlameLayer {
+        let mut flames: FlameLayer = Default::default();
+        flames.set_theme(theme);
+        return flames;
+    }
+
+    /// The color of a flame at this temperature, in front of `background`
+    fn get_color(flames: &FlameLayer, temperature_0_to_1: f32, background: &Rgba) -> Rgba {
+        let fragment = flames
+            .get_fragment_by_temperature(temperature_0_to_1)
+            .unwrap();
+        return composite(background, &fragment, BlendMode::Normal);
+    }
 
     #[test]
     fn test_flame_reaches_the_top() {
@@ -153,9 +265,70 @@ mod tests {
             user_0_to_1: 1.0,
             system_0_to_1: 0.0,
         }];
-        let renderer: Renderer = Default::default();
-        let height = 100;
-        let pixel = renderer.get_flame_pixel(&viz_loads, 0.0, 0, height - 1, 1, height);
+        let flames = flame_layer(&Default::default());
+        let weather = Default::default();
+        let input = LayerInput::for_pixel(&viz_loads, &weather, (0, 0), (1, 100));
+        assert!(flames.get_rows(&input).contains(&0));
+        let pixel = flames.get_pixel(&input);
         assert!(pixel.is_some());
     }
+
+    #[test]
+    fn test_color_stops() {
+        let flames = flame_layer(&Theme {
+            flame_colors: FlameColors::Gradient(vec![
+                [0x00, 0x00, 0x00],
+                [0x80, 0x80, 0x80],
+                [0xff, 0xff, 0xff],
+            ]),
+            flame_transparent_0_to_1: 0.5,
+            ..Default::default()
+        });
+        let background = opaque(&[0x00, 0x00, 0xff]);
+
+        // Fading from the background to the coolest stop, half way in linear
+        // light
+        assert_eq!(
+            [0x00, 0x00, 0xbc, 0xff],
+            get_color(&flames, 0.25, &background)
+        );
+
+        assert_eq!(
+            [0x00, 0x00, 0x00, 0xff],
+            get_color(&flames, 0.5, &background)
+        );
+        assert_eq!(
+            [0xff, 0xff, 0xff, 0xff],
+            get_color(&flames, 1.0, &background)
+        );
+
+        // The middle stop is half way between two precomputed colors
+        let middle = get_color(&flames, 0.75, &background);
+        assert!((0x80..=0x81).contains(&middle[0]), "{:?}", middle);
+    }
+
+    #[test]
+    fn test_one_color() {
+        let theme = Theme {
+            flame_colors: FlameColors::Gradient(vec![[0xff, 0x00, 0x00]]),
+            ..Default::default()
+        };
+        let flames = flame_layer(&theme);
+        assert_eq!(
+            [0xff, 0x00, 0x00, 0xff],
+            get_color(&flames, 0.9, &opaque(&theme.background_rgb))
+        );
+    }
+
+    #[test]
+    fn test_blackbody_colors() {
+        let flames = flame_layer(&Default::default());
+        let background = opaque(&Theme::default().background_rgb);
+
+        // Dark orange at the edges, white at the hottest
+        let coolest = get_color(&flames, 0.3, &background);
+        assert_eq!(opaque(&blackbody_rgb(1000.0)), coolest);
+        let hottest = get_color(&flames, 1.0, &background);
+        assert!(hottest.iter().all(|component| *component >= 0xf0));
+    }
 }
@@ -0,0 +1,96 @@
+use std::ops::Range;
+
+use crate::theme::Theme;
+
+use super::layer::{Fragment, Layer, LayerInput};
+use super::pixel_to_fraction;
+
+/// At full power draw, the glow reaches this far up, as a fraction of the image
+/// height
+static MAX_GLOW_HEIGHT_0_TO_1: f32 = 0.6;
+
+/// How opaque the glow is at the bottom edge of the image
+static MAX_GLOW_OPACITY_0_TO_1: f32 = 0.6;
+
+/// Power draw, shown as a warm glow rising from the bottom of the image,
+/// behind the flames.
+#[derive(Default)]
+pub(super) struct GlowLayer {
+    glow_rgb: [u8; 3],
+}
+
+impl Layer for GlowLayer {
+    fn name(&self) -> &str {
+        return "glow";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.glow_rgb = theme.glow_rgb;
+    }
+
+    fn get_rows(&self, input: &LayerInput) -> Range<usize> {
+        let glow_height_0_to_1 = input.weather.glow_0_to_1 * MAX_GLOW_HEIGHT_0_TO_1;
+        if glow_height_0_to_1 <= 0.0 {
+            return 0..0;
+        }
+
+        // Above this, pixel_to_fraction() is above the glow
+        let rows = (glow_height_0_to_1 * (input.height + 2) as f32).ceil() as usize;
+        return input.height - rows.min(input.height)..input.height;
+    }
+
+    fn get_pixel(&self, input: &LayerInput) -> Option<Fragment> {
+        let weather = input.weather;
+        if weather.glow_0_to_1 <= 0.0 {
+            return None;
+        }
+
+        let glow_height_0_to_1 = weather.glow_0_to_1 * MAX_GLOW_HEIGHT_0_TO_1;
+        let y_from_bottom_0_to_1 =
+            pixel_to_fraction(input.pixel_y_from_bottom() as f32, input.height);
+        if y_from_bottom_0_to_1 >= glow_height_0_to_1 {
+            return None;
+        }
+
+        return Some(Fragment {
+            rgb: self.glow_rgb,
+            coverage_0_to_1: MAX_GLOW_OPACITY_0_TO_1
+                * weather.glow_0_to_1
+                * (1.0 - y_from_bottom_0_to_1 / glow_height_0_to_1),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Weather;
+
+    fn get_pixel(weather: &Weather, pixel_y_from_top: usize) -> Option<Fragment> {
+        let mut glow: GlowLayer = Default::default();
+        glow.set_theme(&Default::default());
+        return glow.get_pixel(&LayerInput::for_pixel(
+            &[],
+            weather,
+            (0, pixel_y_from_top),
+            (1, 10),
+        ));
+    }
+
+    #[test]
+    fn test_no_power_no_glow() {
+        assert_eq!(None, get_pixel(&Default::default(), 9));
+    }
+
+    #[test]
+    fn test_glow_at_the_bottom() {
+        let weather = Weather {
+            glow_0_to_1: 1.0,
+            ..Default::default()
+        };
+        let bottom = get_pixel(&weather, 9).unwrap();
+        assert_eq!(Theme::default().glow_rgb, bottom.rgb);
+        assert!(bottom.coverage_0_to_1 > 0.0);
+        assert_eq!(None, get_pixel(&weather, 0));
+    }
+}
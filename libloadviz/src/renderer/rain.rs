@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+use crate::theme::Theme;
+
+use super::hash_0_to_1;
+use super::layer::{Fragment, Layer, LayerInput};
+use super::pixel_to_fraction;
+
+/// How opaque is the head of a raindrop? The tail fades towards transparent.
+static RAIN_OPACITY_0_TO_1: f32 = 0.7;
+
+/// At full intensity, this fraction of all pixel columns will have rain in them
+static MAX_RAINY_COLUMNS_0_TO_1: f32 = 0.4;
+
+/// Raindrop streak length, as a fraction of the image height
+static STREAK_LENGTH_0_TO_1: f32 = 0.15;
+
+/// How long it takes a raindrop to fall through the whole image
+static SECONDS_TOP_TO_BOTTOM: f32 = 0.8;
+
+/// In full wind, rain moves this many pixels sideways for each pixel it falls
+static WIND_SLANT: f32 = 0.5;
+
+/// Disk I/O, drawn below the clouds
+#[derive(Default)]
+pub(super) struct RainLayer {
+    rain_rgb: [u8; 3],
+}
+
+impl Layer for RainLayer {
+    fn name(&self) -> &str {
+        return "rain";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.rain_rgb = theme.rain_rgb;
+    }
+
+    fn get_rows(&self, input: &LayerInput) -> Range<usize> {
+        if input.weather.rain_0_to_1 <= 0.0 {
+            return 0..0;
+        }
+        return 0..input.height;
+    }
+
+    fn get_pixel(&self, input: &LayerInput) -> Option<Fragment> {
+        let weather = input.weather;
+        if weather.rain_0_to_1 <= 0.0 {
+            return None;
+        }
+        let (pixel_x, pixel_y_from_top) = (input.pixel_x, input.pixel_y_from_top);
+
+        // With wind, the "columns" of rain lean sideways. Negative column
+        // numbers wrap around, which is fine since we only use them for
+        // hashing.
+        let column = (pixel_x as f32 - weather.wind_m1_to_1 * WIND_SLANT * pixel_y_from_top as f32)
+            .round() as i32 as u32;
+
+        // Decide whether this column has rain in it. More intense rain means
+        // more rainy columns.
+        if hash_0_to_1(column) >= weather.rain_0_to_1 * MAX_RAINY_COLUMNS_0_TO_1 {
+            return None;
+        }
+
+        // Give each column its own distance between drops, and its own phase,
+        // so that the drops don't line up.
+        let period_0_to_1 = 0.5 + 0.5 * hash_0_to_1(column ^ 0x5bd1e995);
+        let phase_0_to_1 = hash_0_to_1(column ^ 0x27d4eb2f);
+
+        let y_from_top_0_to_1 = pixel_to_fraction(pixel_y_from_top as f32, input.height);
+        let position_in_period_0_to_1 = (y_from_top_0_to_1
+            - input.dt_seconds / SECONDS_TOP_TO_BOTTOM
+            - phase_0_to_1 * period_0_to_1)
+            .rem_euclid(period_0_to_1);
+        if position_in_period_0_to_1 >= STREAK_LENGTH_0_TO_1 {
+            // Between drops
+            return None;
+        }
+
+        // Drops fall downwards, so the bottom end of the streak is the head
+        return Some(Fragment {
+            rgb: self.rain_rgb,
+            coverage_0_to_1: RAIN_OPACITY_0_TO_1 * position_in_period_0_to_1 / STREAK_LENGTH_0_TO_1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Weather;
+
+    fn count_rainy_pixels(weather: &Weather) -> usize {
+        let mut rain: RainLayer = Default::default();
+        rain.set_theme(&Default::default());
+        return (0..100)
+            .flat_map(|pixel_x| (0..100).map(move |pixel_y_from_top| (pixel_x, pixel_y_from_top)))
+            .filter(|(pixel_x, pixel_y_from_top)| {
+                rain.get_pixel(&LayerInput::for_pixel(
+                    &[],
+                    weather,
+                    (*pixel_x, *pixel_y_from_top),
+                    (100, 100),
+                ))
+                .is_some()
+            })
+            .count();
+    }
+
+    #[test]
+    fn test_no_io_no_rain() {
+        assert_eq!(0, count_rainy_pixels(&Default::default()));
+    }
+
+    #[test]
+    fn test_full_io_some_rain() {
+        let weather = Weather {
+            rain_0_to_1: 1.0,
+            ..Default::default()
+        };
+        assert!(count_rainy_pixels(&weather) > 0);
+    }
+}
@@ -0,0 +1,164 @@
+use std::f32::consts::PI;
+
+use crate::theme::Theme;
+
+use super::layer::{Fragment, Layer, LayerInput};
+use super::{hash_0_to_1, interpolate, opaque, pixel_to_fraction, Rgba, Weather};
+
+static DAY_SKY_TOP_RGB: &[u8; 3] = &[0x30, 0x60, 0xc0];
+static DAY_SKY_HORIZON_RGB: &[u8; 3] = &[0x80, 0xb0, 0xe8];
+static NIGHT_SKY_TOP_RGB: &[u8; 3] = &[0x04, 0x04, 0x18];
+static NIGHT_SKY_HORIZON_RGB: &[u8; 3] = &[0x14, 0x18, 0x40];
+
+/// Charging is shown as a sunrise glow along the horizon
+static SUNRISE_RGB: &[u8; 3] = &[0xff, 0x90, 0x40];
+
+/// How much of the image height the sunrise glow covers
+static SUNRISE_HEIGHT_0_TO_1: f32 = 0.5;
+
+/// Seconds for the sunrise glow to pulse once
+static SUNRISE_PULSE_SECONDS: f32 = 4.0;
+
+/// Stars start showing when the battery charge drops below this
+static STARS_VISIBLE_BELOW_0_TO_1: f32 = 0.5;
+
+/// Fraction of pixels that are stars
+static STAR_DENSITY_0_TO_1: f32 = 0.01;
+
+static STAR_RGB: &[u8; 3] = &[0xff, 0xff, 0xe0];
+
+/// The bottom layer. Not drawn at all with a transparent background.
+#[derive(Default)]
+pub(super) struct SkyLayer {
+    background_rgb: [u8; 3],
+    transparent_background: bool,
+}
+
+impl Layer for SkyLayer {
+    fn name(&self) -> &str {
+        return "sky";
+    }
+
+    fn set_theme(&mut self, theme: &Theme) {
+        self.background_rgb = theme.background_rgb;
+    }
+
+    fn set_transparent_background(&mut self, transparent: bool) {
+        self.transparent_background = transparent;
+    }
+
+    fn get_pixel(&self, input: &LayerInput) -> Option<Fragment> {
+        if self.transparent_background {
+            return None;
+        }
+
+        let color = self.get_sky_pixel(
+            input.weather,
+            input.dt_seconds,
+            input.pixel_x,
+            input.pixel_y_from_top,
+            input.height,
+        );
+        return Some(Fragment {
+            rgb: [color[0], color[1], color[2]],
+            coverage_0_to_1: 1.0,
+        });
+    }
+}
+
+impl SkyLayer {
+    /// Without a battery, this is just the flat background of the theme.
+    ///
+    /// With a battery, a full charge gives you a blue sky and an empty one
+    /// gives you a starry night.
+    fn get_sky_pixel(
+        &self,
+        weather: &Weather,
+        dt_seconds: f32,
+        pixel_x: usize,
+        pixel_y_from_top: usize,
+        height: usize,
+    ) -> Rgba {
+        let Some(battery) = weather.battery else {
+            return opaque(&self.background_rgb);
+        };
+        let charge_0_to_1 = battery.charge_0_to_1;
+
+        let top = interpolate(
+            charge_0_to_1,
+            &opaque(NIGHT_SKY_TOP_RGB),
+            &opaque(DAY_SKY_TOP_RGB),
+        );
+        let horizon = interpolate(
+            charge_0_to_1,
+            &opaque(NIGHT_SKY_HORIZON_RGB),
+            &opaque(DAY_SKY_HORIZON_RGB),
+        );
+        let y_from_top_0_to_1 = pixel_to_fraction(pixel_y_from_top as f32, height);
+        let mut color = interpolate(y_from_top_0_to_1, &top, &horizon);
+
+        if battery.charging {
+            let pulse_0_to_1 = 0.75 + 0.25 * (2.0 * PI * dt_seconds / SUNRISE_PULSE_SECONDS).sin();
+            let glow_0_to_1 = (y_from_top_0_to_1 - (1.0 - SUNRISE_HEIGHT_0_TO_1))
+                / SUNRISE_HEIGHT_0_TO_1
+                * pulse_0_to_1;
+            color = interpolate(glow_0_to_1, &color, &opaque(SUNRISE_RGB));
+        }
+
+        let star_visibility_0_to_1 =
+            (STARS_VISIBLE_BELOW_0_TO_1 - charge_0_to_1) / STARS_VISIBLE_BELOW_0_TO_1;
+        if star_visibility_0_to_1 <= 0.0 {
+            return color;
+        }
+
+        let star_hash = (pixel_x as u32).wrapping_mul(0x9e3779b1) ^ pixel_y_from_top as u32;
+        if hash_0_to_1(star_hash) >= STAR_DENSITY_0_TO_1 {
+            return color;
+        }
+
+        // Make each star twinkle in its own rhythm
+        let twinkle_phase = 2.0 * PI * hash_0_to_1(star_hash ^ 0x68e31da4);
+        let twinkle_0_to_1 = 0.6 + 0.4 * (3.0 * dt_seconds + twinkle_phase).sin();
+
+        return interpolate(
+            star_visibility_0_to_1 * twinkle_0_to_1,
+            &color,
+            &opaque(STAR_RGB),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::battery::BatteryState;
+
+    use super::*;
+
+    #[test]
+    fn test_no_battery_classic_background() {
+        let mut sky: SkyLayer = Default::default();
+        sky.set_theme(&Default::default());
+        let pixel = sky.get_sky_pixel(&Default::default(), 0.0, 0, 0, 1);
+        assert_eq!(pixel, opaque(&Theme::default().background_rgb));
+    }
+
+    #[test]
+    fn test_charging_is_brighter_at_the_horizon() {
+        let sky: SkyLayer = Default::default();
+        let mut weather = Weather {
+            battery: Some(BatteryState {
+                charge_0_to_1: 0.8,
+                charging: false,
+            }),
+            ..Default::default()
+        };
+        let height = 10;
+        let discharging = sky.get_sky_pixel(&weather, 0.0, 0, height - 1, height);
+
+        weather.battery.as_mut().unwrap().charging = true;
+        let charging = sky.get_sky_pixel(&weather, 0.0, 0, height - 1, height);
+
+        // Sunrise is red
+        assert!(charging[0] > discharging[0]);
+    }
+}
@@ -0,0 +1,112 @@
+//! sRGB bytes aren't proportional to the amount of light. Mixing them directly
+//! makes fades too dark and muddy in the middle, so we mix in linear light
+//! instead.
+//!
+//! Converting goes through lookup tables, `powf()` for every pixel would slow
+//! rendering down.
+
+use std::sync::OnceLock;
+
+/// Linear light is looked up in this many steps. With this many, every byte
+/// survives a round trip through linear light.
+const LINEAR_STEPS: usize = 4096;
+
+struct Tables {
+    to_linear: [f32; 256],
+    from_linear: [u8; LINEAR_STEPS],
+}
+
+static TABLES: OnceLock<Tables> = OnceLock::new();
+
+impl Tables {
+    fn new() -> Tables {
+        let mut tables = Tables {
+            to_linear: [0.0; 256],
+            from_linear: [0; LINEAR_STEPS],
+        };
+
+        // Formulas from https://en.wikipedia.org/wiki/SRGB#Transformation
+        for (srgb, linear) in tables.to_linear.iter_mut().enumerate() {
+            let srgb_0_to_1 = srgb as f64 / 255.0;
+            *linear = if srgb_0_to_1 <= 0.04045 {
+                srgb_0_to_1 / 12.92
+            } else {
+                ((srgb_0_to_1 + 0.055) / 1.055).powf(2.4)
+            } as f32;
+        }
+        for (step, srgb) in tables.from_linear.iter_mut().enumerate() {
+            let linear_0_to_1 = step as f64 / (LINEAR_STEPS - 1) as f64;
+            let srgb_0_to_1 = if linear_0_to_1 <= 0.0031308 {
+                linear_0_to_1 * 12.92
+            } else {
+                1.055 * linear_0_to_1.powf(1.0 / 2.4) - 0.055
+            };
+            *srgb = (srgb_0_to_1 * 255.0).round() as u8;
+        }
+
+        return tables;
+    }
+}
+
+fn tables() -> &'static Tables {
+    return TABLES.get_or_init(Tables::new);
+}
+
+impl Tables {
+    fn to_srgb(&self, linear_0_to_1: f32) -> u8 {
+        // Adding 0.5 and truncating rounds, and is a lot faster than round()
+        let step = (linear_0_to_1.clamp(0.0, 1.0) * (LINEAR_STEPS - 1) as f32 + 0.5) as usize;
+        return self.from_linear[step];
+    }
+
+    fn mix(&self, factor_0_to_1: f32, srgb1: u8, srgb2: u8) -> u8 {
+        let linear1 = self.to_linear[srgb1 as usize];
+        let linear2 = self.to_linear[srgb2 as usize];
+        return self.to_srgb(linear1 + (linear2 - linear1) * factor_0_to_1);
+    }
+}
+
+/// 0-1
+pub fn to_linear(srgb: u8) -> f32 {
+    return tables().to_linear[srgb as usize];
+}
+
+/// Values outside of 0-1 are clamped
+pub fn from_linear(linear_0_to_1: f32) -> u8 {
+    return tables().to_srgb(linear_0_to_1);
+}
+
+/// Mix two sRGB values in linear light. 0 gives you `srgb1`, 1 gives you
+/// `srgb2`.
+pub fn mix(factor_0_to_1: f32, srgb1: u8, srgb2: u8) -> u8 {
+    return tables().mix(factor_0_to_1, srgb1, srgb2);
+}
+
+/// Like [`mix()`] for each of red, green and blue
+pub(super) fn mix_rgb(factor_0_to_1: f32, srgb1: &[u8], srgb2: &[u8], result: &mut [u8]) {
+    let tables = tables();
+    for i in 0..3 {
+        result[i] = tables.mix(factor_0_to_1, srgb1[i], srgb2[i]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for srgb in 0..=255 {
+            assert_eq!(srgb, from_linear(to_linear(srgb)));
+        }
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(0x00, mix(0.0, 0x00, 0xff));
+        assert_eq!(0xff, mix(1.0, 0x00, 0xff));
+
+        // Half the light is a lot brighter than half the byte value
+        assert_eq!(0xbc, mix(0.5, 0x00, 0xff));
+    }
+}
@@ -0,0 +1,288 @@
+use std::io::BufRead;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+use crate::trace::{TraceError, TraceReader};
+
+/// Faster than this, a day of trace goes by in less than ten seconds
+static MAX_SPEED: f32 = 10_000.0;
+
+/// Plays back recorded samples as if they were live.
+///
+/// Use [`ReplaySource::controls()`] to get a handle for controlling playback
+/// after handing the source over to `LoadViz`.
+pub struct ReplaySource {
+    samples: Vec<Sample>,
+    controls: ReplayControls,
+}
+
+/// Playback controls for a [`ReplaySource`]. Clones control the same
+/// playback.
+#[derive(Clone)]
+pub struct ReplayControls {
+    playback: Arc<Mutex<Playback>>,
+}
+
+struct Playback {
+    playing: bool,
+    looping: bool,
+    speed: f32,
+
+    /// How far into the trace we were at `position_updated`
+    position: Duration,
+    position_updated: Instant,
+
+    /// Time between the first and the last sample
+    duration: Duration,
+}
+
+impl ReplaySource {
+    /// Samples should be in chronological order. Playback starts out playing,
+    /// at normal speed, not looping.
+    pub fn new(samples: Vec<Sample>) -> ReplaySource {
+        let duration = match (samples.first(), samples.last()) {
+            (Some(first), Some(last)) => last
+                .timestamp
+                .duration_since(first.timestamp)
+                .unwrap_or(Duration::ZERO),
+            _ => Duration::ZERO,
+        };
+
+        return ReplaySource {
+            samples,
+            controls: ReplayControls {
+                playback: Arc::new(Mutex::new(Playback {
+                    playing: true,
+                    looping: false,
+                    speed: 1.0,
+                    position: Duration::ZERO,
+                    position_updated: Instant::now(),
+                    duration,
+                })),
+            },
+        };
+    }
+
+    /// Read all samples from a trace. If the trace was truncated, we replay
+    /// whatever made it to disk.
+    pub fn from_trace<R: BufRead>(reader: TraceReader<R>) -> Result<ReplaySource, TraceError> {
+        let mut samples: Vec<Sample> = vec![];
+        for sample in reader {
+            match sample {
+                Ok(sample) => samples.push(sample),
+                Err(TraceError::Truncated) => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        return Ok(ReplaySource::new(samples));
+    }
+
+    pub fn controls(&self) -> ReplayControls {
+        return self.controls.clone();
+    }
+
+    fn get_sample_at(&self, now: Instant) -> Option<Sample> {
+        let first = self.samples.first()?;
+        let position = self.controls.playback.lock().unwrap().position_at(now);
+
+        // Find the last sample at or before the current position
+        let index = self.samples.partition_point(|sample| {
+            sample
+                .timestamp
+                .duration_since(first.timestamp)
+                .unwrap_or(Duration::ZERO)
+                <= position
+        });
+
+        // partition_point() is never 0 here, since the first sample is always
+        // at position zero
+        return Some(self.samples[index - 1].clone());
+    }
+}
+
+impl LoadSource for ReplaySource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        return self.get_sample_at(Instant::now());
+    }
+}
+
+impl ReplayControls {
+    pub fn play(&self) {
+        self.update(|playback| playback.playing = true);
+    }
+
+    pub fn pause(&self) {
+        self.update(|playback| playback.playing = false);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        return self.update(|playback| playback.playing);
+    }
+
+    /// Seeking past the end takes you to the end
+    pub fn seek(&self, position: Duration) {
+        self.update(|playback| playback.position = position.min(playback.duration));
+    }
+
+    /// How far into the trace we are
+    pub fn position(&self) -> Duration {
+        return self.update(|playback| playback.position);
+    }
+
+    /// Time between the first and the last sample of the trace
+    pub fn duration(&self) -> Duration {
+        return self.update(|playback| playback.duration);
+    }
+
+    /// 1.0 is real time, 2.0 is twice as fast. Negative and NaN speeds are
+    /// treated as zero, and speeds above 10000 as 10000.
+    pub fn set_speed(&self, speed: f32) {
+        // f32::max() turns NaN into 0.0, f32::clamp() would keep it
+        let speed = speed.max(0.0).min(MAX_SPEED);
+        self.update(|playback| playback.speed = speed);
+    }
+
+    /// When looping, playback restarts from the beginning after reaching the
+    /// end. When not looping, playback pauses at the end.
+    pub fn set_looping(&self, looping: bool) {
+        self.update(|playback| playback.looping = looping);
+    }
+
+    /// Bring the position up to date, then do something with the playback
+    fn update<T>(&self, action: impl FnOnce(&mut Playback) -> T) -> T {
+        let mut playback = self.playback.lock().unwrap();
+        playback.position_at(Instant::now());
+        return action(&mut playback);
+    }
+}
+
+impl Playback {
+    /// Move the position forward to `now`, and return it
+    fn position_at(&mut self, now: Instant) -> Duration {
+        if self.playing {
+            let dt = now.saturating_duration_since(self.position_updated);
+            self.position += dt.mul_f32(self.speed);
+        }
+        self.position_updated = now;
+
+        if self.position > self.duration {
+            if self.looping && !self.duration.is_zero() {
+                let nanos = self.position.as_nanos() % self.duration.as_nanos();
+                self.position = Duration::from_nanos(nanos as u64);
+            } else {
+                self.position = self.duration;
+                self.playing = false;
+            }
+        }
+
+        return self.position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::cpuload::LoadCounters;
+
+    /// Samples one second apart, with `user` set to the sample index
+    fn samples(count: usize) -> Vec<Sample> {
+        return (0..count)
+            .map(|i| {
+                return Sample::cpus_only(
+                    UNIX_EPOCH + Duration::from_secs(1000 + i as u64),
+                    vec![LoadCounters {
+                        user: i,
+                        system: 0,
+                        idle: 0,
+                    }],
+                );
+            })
+            .collect();
+    }
+
+    fn user_at(source: &ReplaySource, now: Instant) -> usize {
+        return source.get_sample_at(now).unwrap().cpus[0].user;
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut source = ReplaySource::new(vec![]);
+        assert_eq!(None, source.get_sample());
+    }
+
+    #[test]
+    fn test_play_and_speed() {
+        let source = ReplaySource::new(samples(10));
+        let t0 = source.controls.playback.lock().unwrap().position_updated;
+
+        assert_eq!(0, user_at(&source, t0));
+        assert_eq!(2, user_at(&source, t0 + Duration::from_millis(2500)));
+
+        source.controls.set_speed(2.0);
+        let t1 = source.controls.playback.lock().unwrap().position_updated;
+        let position = source.controls.position();
+        let expected = position + Duration::from_secs(4);
+        assert_eq!(
+            expected.as_secs() as usize,
+            user_at(&source, t1 + Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_extreme_speeds() {
+        let source = ReplaySource::new(samples(10));
+        let t0 = source.controls.playback.lock().unwrap().position_updated;
+
+        source.controls.set_speed(f32::NAN);
+        let position = source.controls.position();
+        assert_eq!(
+            position.as_secs() as usize,
+            user_at(&source, t0 + Duration::from_secs(5))
+        );
+
+        source.controls.set_speed(f32::INFINITY);
+        assert_eq!(9, user_at(&source, t0 + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_pause_and_seek() {
+        let source = ReplaySource::new(samples(10));
+        let controls = source.controls();
+
+        controls.pause();
+        controls.seek(Duration::from_secs(5));
+        let now = Instant::now();
+        assert_eq!(5, user_at(&source, now + Duration::from_secs(3)));
+
+        // Seeking past the end takes us to the last sample
+        controls.seek(Duration::from_secs(100));
+        assert_eq!(9, user_at(&source, Instant::now()));
+    }
+
+    #[test]
+    fn test_end_without_looping() {
+        let source = ReplaySource::new(samples(10));
+        let t0 = source.controls.playback.lock().unwrap().position_updated;
+
+        assert_eq!(9, user_at(&source, t0 + Duration::from_secs(15)));
+        assert!(!source.controls.is_playing());
+    }
+
+    #[test]
+    fn test_looping() {
+        let source = ReplaySource::new(samples(10));
+        source.controls.set_looping(true);
+        source.controls.seek(Duration::ZERO);
+        let t0 = source.controls.playback.lock().unwrap().position_updated;
+
+        // The trace is 9 seconds long, so 12 seconds in we should be 3 seconds
+        // into the second round
+        assert_eq!(3, user_at(&source, t0 + Duration::from_secs(12)));
+        assert!(source.controls.is_playing());
+    }
+}
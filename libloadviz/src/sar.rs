@@ -0,0 +1,514 @@
+//! Import CPU load history from sysstat.
+//!
+//! Input is what `sadf -d /var/log/sysstat/sa15 -- -P ALL` prints:
+//!
+//! ```text
+//! # hostname;interval;timestamp;CPU;%user;%nice;%system;%iowait;%steal;%idle
+//! buildhost;600;2023-11-15 10:10:01 UTC;-1;2.31;0.00;0.85;0.12;0.00;96.72
+//! buildhost;600;2023-11-15 10:10:01 UTC;0;4.12;0.00;1.20;0.30;0.00;94.38
+//! buildhost;600;2023-11-15 10:10:01 UTC;1;0.50;0.00;0.50;0.00;0.00;99.00
+//! ```
+//!
+//! Columns are found by their names in the header, so `sar -u ALL` style
+//! output with `%usr` / `%sys` / `%irq` / `%soft` columns works as well.
+//!
+//! Since [`LoadCounters`] only has user, system and idle:
+//! * Nice time counts as user time, like on macOS
+//! * Steal, IRQ and soft IRQ time count as system time, since that is time
+//!   not available to user processes
+//! * I/O wait time counts as idle time, since the CPU wasn't doing anything
+//!
+//! The all-CPUs summary lines (CPU `-1`) are ignored.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cpuload::LoadCounters;
+use crate::system_load::Sample;
+
+/// sysstat reports percentages, we turn them into counter ticks at this rate
+static TICKS_PER_SECOND: f64 = 100.0;
+
+#[derive(Debug)]
+pub enum SarError {
+    Io(io::Error),
+
+    /// `line_number` is 1 based
+    Malformed {
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SarError::Io(error) => write!(f, "I/O error: {}", error),
+            SarError::Malformed {
+                line_number,
+                message,
+            } => write!(f, "Malformed sysstat line {}: {}", line_number, message),
+        };
+    }
+}
+
+impl std::error::Error for SarError {}
+
+impl From<io::Error> for SarError {
+    fn from(error: io::Error) -> Self {
+        return SarError::Io(error);
+    }
+}
+
+/// Column indices, from the header
+struct Columns {
+    interval: usize,
+    timestamp: usize,
+    cpu: usize,
+    user: Vec<usize>,
+    system: Vec<usize>,
+    idle: Vec<usize>,
+    count: usize,
+}
+
+/// One line of input
+struct CpuLine {
+    interval_seconds: f64,
+    timestamp: SystemTime,
+    cpu: usize,
+    user_percent: f64,
+    system_percent: f64,
+    idle_percent: f64,
+}
+
+/// Iterate over this to get [`Sample`]s. Iteration stops after the first
+/// error.
+///
+/// The first sample is synthesized one interval before the first timestamp in
+/// the input, so that diffing it with the second sample gives you the load of
+/// the first interval.
+pub struct SarImporter<R: BufRead> {
+    reader: R,
+    line_number: usize,
+    columns: Option<Columns>,
+
+    /// A line we read that belongs to the next sample
+    pending_line: Option<CpuLine>,
+
+    /// An error we ran into after the start of the current sample
+    pending_error: Option<SarError>,
+
+    /// Samples ready to be returned
+    ready: VecDeque<Sample>,
+
+    /// Accumulated (user, system, idle) ticks for each CPU
+    ticks: Vec<(f64, f64, f64)>,
+
+    done: bool,
+}
+
+impl<R: BufRead> SarImporter<R> {
+    pub fn new(reader: R) -> SarImporter<R> {
+        return SarImporter {
+            reader,
+            line_number: 0,
+            columns: None,
+            pending_line: None,
+            pending_error: None,
+            ready: VecDeque::new(),
+            ticks: vec![],
+            done: false,
+        };
+    }
+
+    /// Returns `None` at end of input
+    fn read_cpu_line(&mut self) -> Result<Option<CpuLine>, SarError> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let line = line.trim_end();
+
+            if let Some(header) = line.strip_prefix('#') {
+                self.columns = Some(parse_header(header).map_err(|message| self.error(message))?);
+                continue;
+            }
+            if line.is_empty() || line.contains("LINUX-RESTART") {
+                continue;
+            }
+
+            let Some(columns) = &self.columns else {
+                return Err(self.error("Expected a header line first".to_string()));
+            };
+            let fields: Vec<&str> = line.split(';').collect();
+            if fields.len() != columns.count {
+                return Err(self.error(format!(
+                    "Expected {} fields, got {}",
+                    columns.count,
+                    fields.len()
+                )));
+            }
+            if fields[columns.cpu] == "-1" || fields[columns.cpu] == "all" {
+                // Summary line
+                continue;
+            }
+
+            let cpu_line =
+                parse_cpu_line(columns, &fields).map_err(|message| self.error(message))?;
+            return Ok(Some(cpu_line));
+        }
+    }
+
+    fn error(&self, message: String) -> SarError {
+        return SarError::Malformed {
+            line_number: self.line_number,
+            message,
+        };
+    }
+
+    /// Read all lines with the same timestamp, and turn them into samples
+    fn read_sample(&mut self) -> Result<(), SarError> {
+        if let Some(error) = self.pending_error.take() {
+            return Err(error);
+        }
+
+        let first = match self.pending_line.take() {
+            Some(line) => line,
+            None => match self.read_cpu_line()? {
+                Some(line) => line,
+                None => return Ok(()),
+            },
+        };
+
+        let is_first_sample = self.ticks.is_empty();
+        let timestamp = first.timestamp;
+        let interval_seconds = first.interval_seconds;
+        let mut lines = vec![first];
+        loop {
+            let line = match self.read_cpu_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(error) => {
+                    // Return what we have first, then the error
+                    self.pending_error = Some(error);
+                    break;
+                }
+            };
+            if line.timestamp != timestamp {
+                self.pending_line = Some(line);
+                break;
+            }
+            lines.push(line);
+        }
+
+        let cpu_count = lines.iter().map(|line| line.cpu + 1).max().unwrap_or(0);
+        if self.ticks.len() < cpu_count {
+            self.ticks.resize(cpu_count, (0.0, 0.0, 0.0));
+        }
+
+        if is_first_sample {
+            let first_timestamp = Duration::try_from_secs_f64(interval_seconds)
+                .ok()
+                .and_then(|interval| timestamp.checked_sub(interval))
+                .unwrap_or(UNIX_EPOCH);
+            self.ready
+                .push_back(Sample::cpus_only(first_timestamp, self.counters(cpu_count)));
+        }
+
+        for line in lines {
+            let ticks = &mut self.ticks[line.cpu];
+            let ticks_per_percent = line.interval_seconds * TICKS_PER_SECOND / 100.0;
+            ticks.0 += line.user_percent * ticks_per_percent;
+            ticks.1 += line.system_percent * ticks_per_percent;
+            ticks.2 += line.idle_percent * ticks_per_percent;
+        }
+
+        self.ready
+            .push_back(Sample::cpus_only(timestamp, self.counters(cpu_count)));
+
+        return Ok(());
+    }
+
+    fn counters(&self, cpu_count: usize) -> Vec<LoadCounters> {
+        return self.ticks[..cpu_count]
+            .iter()
+            .map(|(user, system, idle)| LoadCounters {
+                user: *user as usize,
+                system: *system as usize,
+                idle: *idle as usize,
+            })
+            .collect();
+    }
+}
+
+impl<R: BufRead> Iterator for SarImporter<R> {
+    type Item = Result<Sample, SarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ready.is_empty() && !self.done {
+            if let Err(error) = self.read_sample() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+
+        let sample = self.ready.pop_front();
+        if sample.is_none() {
+            self.done = true;
+        }
+        return sample.map(Ok);
+    }
+}
+
+fn parse_header(header: &str) -> Result<Columns, String> {
+    let names: Vec<&str> = header.trim().split(';').collect();
+    let find = |wanted: &[&str]| -> Vec<usize> {
+        return names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| wanted.contains(name))
+            .map(|(index, _)| index)
+            .collect();
+    };
+    let find_one = |wanted: &str| -> Result<usize, String> {
+        return find(&[wanted])
+            .first()
+            .copied()
+            .ok_or_else(|| format!("Header has no {} column", wanted));
+    };
+
+    let columns = Columns {
+        interval: find_one("interval")?,
+        timestamp: find_one("timestamp")?,
+        cpu: find_one("CPU")?,
+        user: find(&["%user", "%usr", "%nice"]),
+        system: find(&["%system", "%sys", "%steal", "%irq", "%soft"]),
+        idle: find(&["%idle", "%iowait"]),
+        count: names.len(),
+    };
+    if columns.user.is_empty() || columns.system.is_empty() || columns.idle.is_empty() {
+        return Err(
+            "Header needs user, system and idle columns, try sadf -d -- -P ALL".to_string(),
+        );
+    }
+
+    return Ok(columns);
+}
+
+fn parse_cpu_line(columns: &Columns, fields: &[&str]) -> Result<CpuLine, String> {
+    let sum_percent = |indices: &[usize]| -> Result<f64, String> {
+        let mut sum = 0.0;
+        for index in indices {
+            sum += fields[*index]
+                .replace(',', ".")
+                .parse::<f64>()
+                .map_err(|_| format!("Not a percentage: {}", fields[*index]))?;
+        }
+        return Ok(sum);
+    };
+
+    let interval = fields[columns.interval];
+    let interval_seconds = interval
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+        .ok_or_else(|| format!("Not an interval: {}", interval))?;
+
+    let cpu = fields[columns.cpu];
+    return Ok(CpuLine {
+        interval_seconds,
+        timestamp: parse_timestamp(fields[columns.timestamp])?,
+        cpu: cpu
+            .parse::<usize>()
+            .map_err(|_| format!("Not a CPU number: {}", cpu))?,
+        user_percent: sum_percent(&columns.user)?,
+        system_percent: sum_percent(&columns.system)?,
+        idle_percent: sum_percent(&columns.idle)?,
+    });
+}
+
+/// Either seconds since the epoch (`sadf -U`), or `2023-11-15 10:10:01 UTC`
+fn parse_timestamp(timestamp: &str) -> Result<SystemTime, String> {
+    let error = || {
+        format!(
+            "Expected seconds since the epoch or YYYY-MM-DD HH:MM:SS UTC: {}",
+            timestamp
+        )
+    };
+
+    if let Ok(seconds) = timestamp.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+
+    let timestamp = timestamp.strip_suffix(" UTC").unwrap_or(timestamp);
+    let Some((date, time)) = timestamp.split_once(' ') else {
+        return Err(error());
+    };
+    let numbers = |text: &str, separator: char| -> Option<Vec<i64>> {
+        let numbers: Option<Vec<i64>> = text
+            .split(separator)
+            .map(|part| part.parse().ok())
+            .collect();
+        return numbers.filter(|numbers| numbers.len() == 3);
+    };
+    let (Some(date), Some(time)) = (numbers(date, '-'), numbers(time, ':')) else {
+        return Err(error());
+    };
+
+    let seconds = days_from_civil(date[0], date[1], date[2]) * 86400
+        + time[0] * 3600
+        + time[1] * 60
+        + time[2];
+    if seconds < 0 {
+        return Err(error());
+    }
+    return Ok(UNIX_EPOCH + Duration::from_secs(seconds as u64));
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date, from
+/// <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpuload::diff;
+
+    static SADF: &str = "\
+# hostname;interval;timestamp;CPU;%user;%nice;%system;%iowait;%steal;%idle
+buildhost;600;2023-11-15 10:10:01 UTC;-1;30.00;0.00;10.00;0.00;0.00;60.00
+buildhost;600;2023-11-15 10:10:01 UTC;0;50.00;10.00;10.00;5.00;0.00;25.00
+buildhost;600;2023-11-15 10:10:01 UTC;1;0.00;0.00;10.00;0.00;0.00;90.00
+buildhost;-1;2023-11-15 10:15:00 UTC;LINUX-RESTART\t(2 CPU)
+buildhost;600;2023-11-15 10:20:01 UTC;-1;50.00;0.00;0.00;0.00;0.00;50.00
+buildhost;600;2023-11-15 10:20:01 UTC;0;100.00;0.00;0.00;0.00;0.00;0.00
+buildhost;600;2023-11-15 10:20:01 UTC;1;0.00;0.00;0.00;0.00;0.00;100.00
+";
+
+    fn import(input: &str) -> Vec<Sample> {
+        return SarImporter::new(input.as_bytes())
+            .map(|sample| sample.unwrap())
+            .collect();
+    }
+
+    #[test]
+    fn test_import() {
+        let samples = import(SADF);
+        assert_eq!(3, samples.len());
+
+        // 2023-11-15 10:10:01 UTC
+        let first_timestamp = UNIX_EPOCH + Duration::from_secs(1700043001);
+        assert_eq!(
+            first_timestamp - Duration::from_secs(600),
+            samples[0].timestamp
+        );
+        assert_eq!(first_timestamp, samples[1].timestamp);
+
+        let loads = diff(&samples[0].cpus, &samples[1].cpus);
+        assert_eq!(2, loads.len());
+
+        // Nice counts as user, I/O wait as idle
+        assert_eq!(0.6, loads[0].user_0_to_1);
+        assert_eq!(0.1, loads[0].system_0_to_1);
+        assert_eq!(0.0, loads[1].user_0_to_1);
+        assert_eq!(0.1, loads[1].system_0_to_1);
+
+        let loads = diff(&samples[1].cpus, &samples[2].cpus);
+        assert_eq!(1.0, loads[0].user_0_to_1);
+        assert_eq!(0.0, loads[1].user_0_to_1);
+    }
+
+    #[test]
+    fn test_usr_sys_columns_and_epoch_timestamps() {
+        let input = "\
+# hostname;interval;timestamp;CPU;%usr;%nice;%sys;%iowait;%steal;%irq;%soft;%guest;%gnice;%idle
+host;60;1700000000;0;20.00;0.00;5.00;0.00;5.00;0.00;0.00;0.00;0.00;70.00
+";
+        let samples = import(input);
+        assert_eq!(2, samples.len());
+        assert_eq!(
+            UNIX_EPOCH + Duration::from_secs(1700000000),
+            samples[1].timestamp
+        );
+
+        // Steal counts as system
+        let loads = diff(&samples[0].cpus, &samples[1].cpus);
+        assert_eq!(0.2, loads[0].user_0_to_1);
+        assert_eq!(0.1, loads[0].system_0_to_1);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let input = "\
+# hostname;interval;timestamp;CPU;%user;%nice;%system;%iowait;%steal;%idle
+host;60;1700000000;0;20.00;0.00;5.00;0.00;5.00;70.00
+host;60;1700000060;0;banana;0.00;5.00;0.00;5.00;70.00
+";
+        let results: Vec<Result<Sample, SarError>> = SarImporter::new(input.as_bytes()).collect();
+
+        // The error is found while reading the second sample, after the first
+        // two have been produced
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert!(matches!(
+            results[2],
+            Err(SarError::Malformed { line_number: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_bad_interval() {
+        for interval in ["-60", "0", "inf", "NaN"] {
+            let input = format!(
+                "# hostname;interval;timestamp;CPU;%user;%system;%idle\n\
+                 host;{};1700000000;0;20.00;5.00;75.00\n",
+                interval
+            );
+            let results: Vec<Result<Sample, SarError>> =
+                SarImporter::new(input.as_bytes()).collect();
+            assert!(
+                matches!(
+                    results[..],
+                    [Err(SarError::Malformed { line_number: 2, .. })]
+                ),
+                "{}",
+                interval
+            );
+        }
+
+        // Absurdly long, but not the end of the world
+        let samples = import(
+            "# hostname;interval;timestamp;CPU;%user;%system;%idle\n\
+             host;1e300;1700000000;0;20.00;5.00;75.00\n",
+        );
+        assert_eq!(UNIX_EPOCH, samples[0].timestamp);
+    }
+
+    #[test]
+    fn test_no_header() {
+        let results: Vec<Result<Sample, SarError>> =
+            SarImporter::new("host;60;1700000000;0;1;2;3;4;5;6\n".as_bytes()).collect();
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(0, days_from_civil(1970, 1, 1));
+        assert_eq!(19676, days_from_civil(2023, 11, 15));
+        assert_eq!(11016, days_from_civil(2000, 2, 29));
+    }
+}
@@ -0,0 +1,533 @@
+//! Synthetic load, for trying out the visualization without having to make
+//! the machine busy.
+//!
+//! A scenario is a number of lines, `#` starts a comment:
+//!
+//! ```text
+//! # How many cores to simulate, default is 2
+//! cores 4
+//!
+//! # Optional, makes time restart from zero every 20 seconds
+//! repeat 20s
+//!
+//! # <cores> <user|system> <curve>, later lines override earlier ones
+//! all system 0.05
+//! 0 user sine 0.2 0.9 10s
+//! 1-3 user steps 0s:0.1 5s:0.8 12s:0.3
+//! 1-3 system ramp 0.0 0.4 20s
+//! 2 user random-walk 0.5 0.2
+//! ```
+//!
+//! Curves are:
+//! * `<value>`: Constant
+//! * `steps <time>:<value> ...`: Jumps to each value at its time
+//! * `ramp <from> <to> <time>`: Goes from one value to the other, then stays
+//! * `sine <min> <max> <period>`: Goes up and down
+//! * `random-walk <start> <max change per second>`: Random wandering
+//!
+//! Loads are fractions between 0 and 1. If user + system load for a core is
+//! more than 1, system load is reduced.
+//!
+//! There are also some presets, see [`Scenario::preset()`].
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cpuload::LoadCounters;
+use crate::load_source::LoadSource;
+use crate::system_load::Sample;
+
+/// Counter ticks per simulated second. High, to keep rounding errors small.
+static TICKS_PER_SECOND: f64 = 10_000.0;
+
+static PRESETS: &[(&str, &str)] = &[
+    (
+        // What debug builds used to always show
+        "demo",
+        "cores 2
+        repeat 20s
+        0 user steps 0s:0.7 7s:0.2 14s:0.0
+        0 system steps 0s:0.2 7s:0.7 14s:0.0
+        1 user steps 0s:0.0 7s:0.1 14s:0.0
+        1 system steps 0s:0.1 7s:0.0 14s:0.0",
+    ),
+    (
+        "one-core-pegged",
+        "cores 8
+        all user random-walk 0.05 0.02
+        all system 0.02
+        0 user 0.97",
+    ),
+    (
+        "all-cores-busy",
+        "cores 8
+        all user sine 0.75 0.95 10s
+        all system random-walk 0.05 0.02",
+    ),
+    (
+        // Lots of system load, which is what a machine that's short on RAM
+        // looks like
+        "swap-storm",
+        "cores 8
+        all user random-walk 0.1 0.05
+        all system sine 0.5 0.85 6s",
+    ),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct ScenarioError {
+    /// 1 based
+    pub line_number: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Scenario line {}: {}", self.line_number, self.message);
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Curve {
+    Constant(f32),
+
+    /// (seconds, value) pairs, sorted by time
+    Steps(Vec<(f32, f32)>),
+
+    Ramp {
+        from: f32,
+        to: f32,
+        seconds: f32,
+    },
+
+    Sine {
+        min: f32,
+        max: f32,
+        period_seconds: f32,
+    },
+
+    RandomWalk {
+        start: f32,
+        max_change_per_second: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CoreCurves {
+    user: Curve,
+    system: Curve,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    cores: Vec<CoreCurves>,
+
+    /// If set, time restarts from zero after this many seconds
+    repeat_seconds: Option<f32>,
+}
+
+impl Scenario {
+    /// Available presets are `demo`, `one-core-pegged`, `all-cores-busy` and
+    /// `swap-storm`.
+    pub fn preset(name: &str) -> Option<Scenario> {
+        let (_, text) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+        return Some(Scenario::parse(text).expect("Presets should be valid"));
+    }
+
+    pub fn preset_names() -> Vec<&'static str> {
+        return PRESETS.iter().map(|(name, _)| *name).collect();
+    }
+
+    pub fn parse(text: &str) -> Result<Scenario, ScenarioError> {
+        let mut core_count = 2;
+        let mut repeat_seconds = None;
+        let mut assignments: Vec<(usize, (usize, usize), bool, Curve)> = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| ScenarioError {
+                line_number,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => continue,
+                ["cores", count] => {
+                    core_count = count
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| error(format!("Not a core count: {}", count)))?;
+                }
+                ["repeat", seconds] => {
+                    repeat_seconds = Some(parse_seconds(seconds).map_err(error)?);
+                }
+                [target, kind, curve @ ..] => {
+                    let range = parse_target(target).map_err(error)?;
+                    let is_user = match *kind {
+                        "user" => true,
+                        "system" => false,
+                        _ => return Err(error(format!("Expected user or system: {}", kind))),
+                    };
+                    let curve = parse_curve(curve).map_err(error)?;
+                    assignments.push((line_number, range, is_user, curve));
+                }
+                _ => return Err(error(format!("Don't know what to do with: {}", line))),
+            }
+        }
+
+        let mut cores = vec![
+            CoreCurves {
+                user: Curve::Constant(0.0),
+                system: Curve::Constant(0.0),
+            };
+            core_count
+        ];
+        for (line_number, (first, last), is_user, curve) in assignments {
+            let last = last.min(core_count - 1);
+            if first > last {
+                return Err(ScenarioError {
+                    line_number,
+                    message: format!("No such core, there are only {}", core_count),
+                });
+            }
+
+            for core in &mut cores[first..=last] {
+                if is_user {
+                    core.user = curve.clone();
+                } else {
+                    core.system = curve.clone();
+                }
+            }
+        }
+
+        return Ok(Scenario {
+            cores,
+            repeat_seconds,
+        });
+    }
+}
+
+/// `all`, `3` or `1-3`, returns an inclusive range
+fn parse_target(target: &str) -> Result<(usize, usize), String> {
+    if target == "all" {
+        return Ok((0, usize::MAX));
+    }
+
+    let parse_core = |core: &str| {
+        core.parse::<usize>()
+            .map_err(|_| format!("Expected all, a core number or a range: {}", target))
+    };
+    if let Some((first, last)) = target.split_once('-') {
+        return Ok((parse_core(first)?, parse_core(last)?));
+    }
+
+    let core = parse_core(target)?;
+    return Ok((core, core));
+}
+
+fn parse_curve(words: &[&str]) -> Result<Curve, String> {
+    return match words {
+        [value] => Ok(Curve::Constant(parse_load(value)?)),
+        ["steps", steps @ ..] if !steps.is_empty() => {
+            let mut steps = steps
+                .iter()
+                .map(|step| {
+                    let Some((seconds, value)) = step.split_once(':') else {
+                        return Err(format!("Expected <time>:<value>: {}", step));
+                    };
+                    return Ok((parse_seconds(seconds)?, parse_load(value)?));
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            steps.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            Ok(Curve::Steps(steps))
+        }
+        ["ramp", from, to, seconds] => Ok(Curve::Ramp {
+            from: parse_load(from)?,
+            to: parse_load(to)?,
+            seconds: parse_seconds(seconds)?,
+        }),
+        ["sine", min, max, period] => Ok(Curve::Sine {
+            min: parse_load(min)?,
+            max: parse_load(max)?,
+            period_seconds: parse_seconds(period)?,
+        }),
+        ["random-walk", start, max_change_per_second] => Ok(Curve::RandomWalk {
+            start: parse_load(start)?,
+            max_change_per_second: parse_load(max_change_per_second)?,
+        }),
+        _ => Err(format!("Not a curve: {}", words.join(" "))),
+    };
+}
+
+fn parse_load(value: &str) -> Result<f32, String> {
+    return value
+        .parse::<f32>()
+        .ok()
+        .filter(|load| (0.0..=1.0).contains(load))
+        .ok_or_else(|| format!("Expected a number between 0 and 1: {}", value));
+}
+
+/// `2.5s`
+fn parse_seconds(value: &str) -> Result<f32, String> {
+    return value
+        .strip_suffix('s')
+        .and_then(|seconds| seconds.parse::<f32>().ok())
+        .filter(|seconds| *seconds >= 0.0)
+        .ok_or_else(|| format!("Expected a time in seconds, like 2.5s: {}", value));
+}
+
+/// Produces samples following a [`Scenario`]
+pub struct ScenarioSource {
+    scenario: Scenario,
+
+    started: Instant,
+    started_timestamp: SystemTime,
+
+    last_seconds: f32,
+
+    /// Current values of the random walks, (user, system) for each core
+    random_walks: Vec<(Option<f32>, Option<f32>)>,
+    random_state: u32,
+
+    /// Accumulated (user, system, idle) ticks for each core
+    ticks: Vec<(f64, f64, f64)>,
+}
+
+impl ScenarioSource {
+    pub fn new(scenario: Scenario) -> ScenarioSource {
+        let core_count = scenario.cores.len();
+        return ScenarioSource {
+            scenario,
+            started: Instant::now(),
+            started_timestamp: SystemTime::now(),
+            last_seconds: 0.0,
+            random_walks: vec![(None, None); core_count],
+            random_state: 0x2545f491,
+            ticks: vec![(0.0, 0.0, 0.0); core_count],
+        };
+    }
+
+    /// Simulate up until `seconds` after we started
+    fn get_sample_at(&mut self, seconds: f32) -> Sample {
+        let dt_seconds = (seconds - self.last_seconds).max(0.0);
+        self.last_seconds = seconds;
+
+        let curve_seconds = match self.scenario.repeat_seconds {
+            Some(repeat_seconds) if repeat_seconds > 0.0 => seconds % repeat_seconds,
+            _ => seconds,
+        };
+
+        let mut cpus: Vec<LoadCounters> = vec![];
+        for core in 0..self.scenario.cores.len() {
+            let curves = self.scenario.cores[core].clone();
+            let mut walks = self.random_walks[core];
+            let user = self.evaluate(&curves.user, &mut walks.0, curve_seconds, dt_seconds);
+            let system = self.evaluate(&curves.system, &mut walks.1, curve_seconds, dt_seconds);
+            self.random_walks[core] = walks;
+
+            let system = system.min(1.0 - user);
+            let idle = 1.0 - user - system;
+
+            let ticks = &mut self.ticks[core];
+            let dt_ticks = dt_seconds as f64 * TICKS_PER_SECOND;
+            ticks.0 += user as f64 * dt_ticks;
+            ticks.1 += system as f64 * dt_ticks;
+            ticks.2 += idle as f64 * dt_ticks;
+
+            cpus.push(LoadCounters {
+                user: ticks.0 as usize,
+                system: ticks.1 as usize,
+                idle: ticks.2 as usize,
+            });
+        }
+
+        return Sample::cpus_only(
+            self.started_timestamp + Duration::from_secs_f32(seconds),
+            cpus,
+        );
+    }
+
+    fn evaluate(
+        &mut self,
+        curve: &Curve,
+        random_walk: &mut Option<f32>,
+        seconds: f32,
+        dt_seconds: f32,
+    ) -> f32 {
+        return match curve {
+            Curve::Constant(value) => *value,
+            Curve::Steps(steps) => steps
+                .iter()
+                .rev()
+                .find(|(step_seconds, _)| *step_seconds <= seconds)
+                .or(steps.first())
+                .map(|(_, value)| *value)
+                .unwrap_or(0.0),
+            Curve::Ramp {
+                from,
+                to,
+                seconds: ramp_seconds,
+            } => {
+                if *ramp_seconds <= 0.0 {
+                    *to
+                } else {
+                    from + (to - from) * (seconds / ramp_seconds).min(1.0)
+                }
+            }
+            Curve::Sine {
+                min,
+                max,
+                period_seconds,
+            } => {
+                let phase = if *period_seconds > 0.0 {
+                    2.0 * PI * seconds / period_seconds
+                } else {
+                    0.0
+                };
+                min + (max - min) * (0.5 + 0.5 * phase.sin())
+            }
+            Curve::RandomWalk {
+                start,
+                max_change_per_second,
+            } => {
+                let change_m1_to_1 = self.next_random_0_to_1() * 2.0 - 1.0;
+                let value = random_walk.unwrap_or(*start)
+                    + change_m1_to_1 * max_change_per_second * dt_seconds;
+                let value = value.clamp(0.0, 1.0);
+                *random_walk = Some(value);
+                value
+            }
+        };
+    }
+
+    /// Xorshift, deterministic so that scenarios are reproducible
+    fn next_random_0_to_1(&mut self) -> f32 {
+        let mut x = self.random_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random_state = x;
+        return x as f32 / u32::MAX as f32;
+    }
+}
+
+impl LoadSource for ScenarioSource {
+    fn get_sample(&mut self) -> Option<Sample> {
+        let seconds = self.started.elapsed().as_secs_f32();
+        return Some(self.get_sample_at(seconds));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpuload::{diff, CpuLoad};
+
+    /// Run the scenario from `from_seconds` to `to_seconds`, and return the
+    /// load in between
+    fn loads_between(
+        source: &mut ScenarioSource,
+        from_seconds: f32,
+        to_seconds: f32,
+    ) -> Vec<CpuLoad> {
+        let older = source.get_sample_at(from_seconds);
+        let newer = source.get_sample_at(to_seconds);
+        return diff(&older.cpus, &newer.cpus);
+    }
+
+    fn assert_close(expected: f32, actual: f32) {
+        assert!(
+            (expected - actual).abs() < 0.01,
+            "Expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_presets_parse() {
+        for name in Scenario::preset_names() {
+            assert!(Scenario::preset(name).is_some());
+        }
+        assert_eq!(None, Scenario::preset("no-such-preset"));
+    }
+
+    #[test]
+    fn test_steps_and_repeat() {
+        let mut source = ScenarioSource::new(Scenario::preset("demo").unwrap());
+
+        let loads = loads_between(&mut source, 1.0, 2.0);
+        assert_eq!(2, loads.len());
+        assert_close(0.7, loads[0].user_0_to_1);
+        assert_close(0.2, loads[0].system_0_to_1);
+
+        let loads = loads_between(&mut source, 8.0, 9.0);
+        assert_close(0.2, loads[0].user_0_to_1);
+        assert_close(0.7, loads[0].system_0_to_1);
+
+        // The demo repeats every 20 seconds
+        let loads = loads_between(&mut source, 21.0, 22.0);
+        assert_close(0.7, loads[0].user_0_to_1);
+    }
+
+    #[test]
+    fn test_overrides_and_ranges() {
+        let scenario = Scenario::parse(
+            "cores 4
+            all user 0.1 # Comment
+            1-2 user 0.5
+            3 system ramp 0.0 0.8 10s",
+        )
+        .unwrap();
+        let mut source = ScenarioSource::new(scenario);
+
+        let loads = loads_between(&mut source, 10.0, 11.0);
+        assert_close(0.1, loads[0].user_0_to_1);
+        assert_close(0.5, loads[1].user_0_to_1);
+        assert_close(0.5, loads[2].user_0_to_1);
+        assert_close(0.1, loads[3].user_0_to_1);
+        assert_close(0.8, loads[3].system_0_to_1);
+    }
+
+    #[test]
+    fn test_overload_reduces_system() {
+        let scenario = Scenario::parse("cores 1\nall user 0.8\nall system 0.8").unwrap();
+        let mut source = ScenarioSource::new(scenario);
+
+        let loads = loads_between(&mut source, 0.0, 1.0);
+        assert_close(0.8, loads[0].user_0_to_1);
+        assert_close(0.2, loads[0].system_0_to_1);
+    }
+
+    #[test]
+    fn test_random_walk_stays_in_range() {
+        let scenario = Scenario::parse("cores 1\nall user random-walk 0.5 1.0").unwrap();
+        let mut source = ScenarioSource::new(scenario);
+
+        for second in 0..100 {
+            let loads = loads_between(&mut source, second as f32, second as f32 + 1.0);
+            assert!((0.0..=1.0).contains(&loads[0].user_0_to_1));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = Scenario::parse("cores 2\n\n0 user banana").unwrap_err();
+        assert_eq!(3, error.line_number);
+
+        let error = Scenario::parse("cores 2\n5 user 0.5").unwrap_err();
+        assert_eq!(2, error.line_number);
+
+        let error = Scenario::parse("0 user 1.5").unwrap_err();
+        assert_eq!(1, error.line_number);
+
+        let error = Scenario::parse("repeat 20").unwrap_err();
+        assert_eq!(1, error.line_number);
+    }
+}
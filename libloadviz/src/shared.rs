@@ -0,0 +1,237 @@
+//! One [`LoadViz`] used from several threads, two windows showing the same
+//! machine for example.
+//!
+//! Rendering publishes a [`Frame`]. A frame never changes and stays valid for
+//! as long as somebody holds it, no matter how many new frames are rendered or
+//! how the size changes in the meantime. Frame buffers nobody holds anymore
+//! are reused for rendering, so usually no allocations are needed.
+
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crate::LoadViz;
+
+/// Frame buffers kept around for reuse, in addition to the latest frame. With
+/// two spares we're triple buffered, and readers never block the renderer.
+static MAX_SPARE_BUFFERS: usize = 2;
+
+/// Clones share the same [`LoadViz`]
+#[derive(Clone)]
+pub struct SharedLoadViz {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    loadviz: Mutex<LoadViz>,
+    buffers: Mutex<Buffers>,
+}
+
+#[derive(Default)]
+struct Buffers {
+    latest: Option<Arc<FrameBuffer>>,
+    spares: Vec<Arc<FrameBuffer>>,
+
+    /// Sequence number of the latest frame
+    sequence: u64,
+}
+
+#[derive(Default)]
+struct FrameBuffer {
+    width: usize,
+    height: usize,
+    sequence: u64,
+
+    /// Size: 3 * width * height. Format: RGBRGBRGB..., top row first.
+    pixels: Vec<u8>,
+}
+
+/// One rendered frame. Drop it to release it.
+#[derive(Clone)]
+pub struct Frame {
+    buffer: Arc<FrameBuffer>,
+}
+
+impl SharedLoadViz {
+    pub fn new(loadviz: LoadViz) -> SharedLoadViz {
+        return SharedLoadViz {
+            shared: Arc::new(Shared {
+                loadviz: Mutex::new(loadviz),
+                buffers: Default::default(),
+            }),
+        };
+    }
+
+    /// Change settings. Don't render from inside `action`, that will deadlock.
+    pub fn with_loadviz<T>(&self, action: impl FnOnce(&mut LoadViz) -> T) -> T {
+        return action(&mut self.shared.loadviz.lock().unwrap());
+    }
+
+    /// Take one animation step, draw it at this size and publish it as the
+    /// latest frame
+    pub fn render(&self, width: usize, height: usize) -> Frame {
+        let mut loadviz = self.shared.loadviz.lock().unwrap();
+        let mut buffer = self.take_spare();
+        {
+            // Nobody else can see spare buffers, so this can't fail
+            let buffer = Arc::get_mut(&mut buffer).unwrap();
+            loadviz.set_size(width, height);
+            let image = loadviz.render();
+            buffer.width = image.width;
+            buffer.height = image.height;
+            buffer.pixels.clear();
+            buffer.pixels.extend_from_slice(image.pixels);
+        }
+
+        let mut buffers = self.shared.buffers.lock().unwrap();
+        buffers.sequence += 1;
+        Arc::get_mut(&mut buffer).unwrap().sequence = buffers.sequence;
+        if let Some(previous) = buffers.latest.replace(buffer.clone()) {
+            if buffers.spares.len() < MAX_SPARE_BUFFERS {
+                buffers.spares.push(previous);
+            }
+        }
+
+        return Frame { buffer };
+    }
+
+    /// The most recently rendered frame, or `None` if nothing has been
+    /// rendered yet
+    pub fn latest_frame(&self) -> Option<Frame> {
+        let buffers = self.shared.buffers.lock().unwrap();
+        return buffers.latest.as_ref().map(|buffer| Frame {
+            buffer: buffer.clone(),
+        });
+    }
+
+    /// A spare buffer nobody is holding, or a new one if they're all held
+    fn take_spare(&self) -> Arc<FrameBuffer> {
+        let mut buffers = self.shared.buffers.lock().unwrap();
+
+        // Spares are only reachable from here, so once released they stay
+        // released
+        let unused = buffers
+            .spares
+            .iter()
+            .position(|buffer| Arc::strong_count(buffer) == 1);
+        return match unused {
+            Some(index) => buffers.spares.swap_remove(index),
+            None => Default::default(),
+        };
+    }
+}
+
+impl Frame {
+    pub fn width(&self) -> usize {
+        return self.buffer.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.buffer.height;
+    }
+
+    /// Increases by one for each frame rendered by the same
+    /// [`SharedLoadViz`]
+    pub fn sequence(&self) -> u64 {
+        return self.buffer.sequence;
+    }
+
+    /// Size: 3 * width * height. Format: RGBRGBRGB..., top row first.
+    pub fn pixels(&self) -> &[u8] {
+        return &self.buffer.pixels;
+    }
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        return self.pixels();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Scenario, ScenarioSource};
+
+    fn new_shared() -> SharedLoadViz {
+        return SharedLoadViz::new(
+            LoadViz::builder()
+                .load_source(Box::new(ScenarioSource::new(
+                    Scenario::preset("demo").unwrap(),
+                )))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_frames_stay_unchanged() {
+        let shared = new_shared();
+        assert!(shared.latest_frame().is_none());
+
+        let first = shared.render(4, 3);
+        let first_pixels = first.pixels().to_vec();
+        assert_eq!(4 * 3 * 3, first_pixels.len());
+
+        let second = shared.render(8, 2);
+        assert_eq!(first.sequence() + 1, second.sequence());
+        assert_eq!(second.sequence(), shared.latest_frame().unwrap().sequence());
+
+        for _ in 0..10 {
+            shared.render(5, 5);
+        }
+        assert_eq!(first_pixels, first.pixels());
+        assert_eq!((8, 2), (second.width(), second.height()));
+        assert_eq!(8 * 2 * 3, second.len());
+    }
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let shared = new_shared();
+        for _ in 0..10 {
+            shared.render(4, 4);
+        }
+        let buffers = shared.shared.buffers.lock().unwrap();
+        assert!(buffers.spares.len() <= MAX_SPARE_BUFFERS);
+    }
+
+    /// Several threads rendering at different sizes, and holding on to frames
+    /// while others render
+    #[test]
+    fn test_stress() {
+        let shared = new_shared();
+        let threads: Vec<_> = (0..4)
+            .map(|index| {
+                let shared = shared.clone();
+                return std::thread::spawn(move || {
+                    let width = 3 + index;
+                    let height = 7 - index;
+                    let mut held: Vec<(Frame, Vec<u8>)> = vec![];
+                    for round in 0..200 {
+                        let frame = shared.render(width, height);
+                        assert_eq!((width, height), (frame.width(), frame.height()));
+                        assert_eq!(width * height * 3, frame.len());
+
+                        let latest = shared.latest_frame().unwrap();
+                        assert!(latest.sequence() >= frame.sequence());
+                        assert_eq!(latest.width() * latest.height() * 3, latest.len());
+
+                        if round % 10 == 0 {
+                            let copy = frame.to_vec();
+                            held.push((frame, copy));
+                        }
+                    }
+
+                    for (frame, copy) in held {
+                        assert_eq!(copy, frame.pixels());
+                    }
+                });
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(800, shared.latest_frame().unwrap().sequence());
+    }
+}
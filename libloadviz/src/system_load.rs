@@ -1,4 +1,52 @@
-use crate::cpuload::LoadCounters;
+use std::time::SystemTime;
+
+use crate::{
+    battery::{BatteryReading, BatteryState},
+    cpuload::LoadCounters,
+    diskload::DiskCounters,
+    netload::NetworkCounters,
+    power::EnergyCounters,
+};
+
+/// The kernel always counts disk I/O in 512 byte sectors, no matter what the
+/// actual hardware sector size is.
+static DISKSTATS_SECTOR_SIZE: usize = 512;
+
+/// Everything we collect, at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub timestamp: SystemTime,
+    pub cpus: Vec<LoadCounters>,
+    pub disks: Vec<DiskCounters>,
+    pub networks: Vec<NetworkCounters>,
+    pub battery: Option<BatteryState>,
+    pub energy: Vec<EnergyCounters>,
+}
+
+impl Sample {
+    /// For sources that only know about CPU load
+    pub fn cpus_only(timestamp: SystemTime, cpus: Vec<LoadCounters>) -> Sample {
+        return Sample {
+            timestamp,
+            cpus,
+            disks: vec![],
+            networks: vec![],
+            battery: None,
+            energy: vec![],
+        };
+    }
+}
+
+pub fn get_sample() -> Sample {
+    return Sample {
+        timestamp: SystemTime::now(),
+        cpus: get_load_counters(),
+        disks: get_disk_counters(),
+        networks: get_network_counters(),
+        battery: get_battery_state(),
+        energy: get_energy_counters(),
+    };
+}
 
 #[cfg(target_os = "macos")]
 pub fn get_load_counters() -> Vec<LoadCounters> {
@@ -40,5 +88,549 @@ pub fn get_load_counters() -> Vec<LoadCounters> {
 
 #[cfg(target_os = "linux")]
 pub fn get_load_counters() -> Vec<LoadCounters> {
-    todo!();
+    let Ok(stat) = std::fs::read_to_string("/proc/stat") else {
+        return vec![];
+    };
+
+    return parse_proc_stat(&stat);
+}
+
+/// Parse the per-CPU lines of `/proc/stat`. Nice time counts as user time, like
+/// on macOS. IRQ, soft IRQ and steal time count as system time, and I/O wait
+/// counts as idle time.
+///
+/// Format docs: <https://www.kernel.org/doc/html/latest/filesystems/proc.html#miscellaneous-kernel-statistics-in-proc-stat>
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_stat(stat: &str) -> Vec<LoadCounters> {
+    let mut result: Vec<LoadCounters> = vec![];
+    for line in stat.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 5 {
+            continue;
+        }
+
+        // "cpu" is the sum of all CPUs, we want "cpu0", "cpu1", ...
+        let Some(cpu_number) = fields[0].strip_prefix("cpu") else {
+            continue;
+        };
+        if cpu_number.is_empty() {
+            continue;
+        }
+
+        // Older kernels don't have all the fields
+        let number = |index: usize| {
+            return fields
+                .get(index)
+                .and_then(|field| field.parse::<usize>().ok())
+                .unwrap_or(0);
+        };
+        let (user, nice, system, idle) = (number(1), number(2), number(3), number(4));
+        let (iowait, irq, softirq, steal) = (number(5), number(6), number(7), number(8));
+        result.push(LoadCounters {
+            user: user + nice,
+            system: system + irq + softirq + steal,
+            idle: idle + iowait,
+        });
+    }
+
+    return result;
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_disk_counters() -> Vec<DiskCounters> {
+    // NOTE: Getting these numbers on macOS requires going through IOKit, which
+    // we don't do (yet?)
+    return vec![];
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_disk_counters() -> Vec<DiskCounters> {
+    let Ok(diskstats) = std::fs::read_to_string("/proc/diskstats") else {
+        return vec![];
+    };
+
+    return parse_diskstats(&diskstats, |device| {
+        // Only whole disks are listed in /sys/block, partitions aren't
+        let class_block = std::path::Path::new("/sys/class/block").join(device);
+        if !class_block.exists() {
+            return None;
+        }
+        return Some(!std::path::Path::new("/sys/block").join(device).exists());
+    });
+}
+
+/// Parse the contents of `/proc/diskstats`. Partitions are skipped, so that
+/// their I/O doesn't get counted twice.
+///
+/// The callback says whether a device is a partition. When it doesn't know,
+/// the device name is compared to the disks listed before it.
+///
+/// Format docs: <https://www.kernel.org/doc/Documentation/ABI/testing/procfs-diskstats>
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_diskstats(
+    diskstats: &str,
+    is_partition: impl Fn(&str) -> Option<bool>,
+) -> Vec<DiskCounters> {
+    let mut result: Vec<DiskCounters> = vec![];
+    for line in diskstats.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 14 {
+            continue;
+        }
+
+        let device = fields[2];
+        let partition = is_partition(device).unwrap_or_else(|| {
+            return result
+                .iter()
+                .any(|disk| is_partition_of(device, &disk.device));
+        });
+        if partition {
+            continue;
+        }
+
+        let number = |index: usize| fields[index].parse::<usize>().unwrap_or(0);
+        result.push(DiskCounters {
+            device: device.to_string(),
+            bytes_read: number(5) * DISKSTATS_SECTOR_SIZE,
+            bytes_written: number(9) * DISKSTATS_SECTOR_SIZE,
+            time_in_queue_ms: number(13),
+        });
+    }
+
+    return result;
+}
+
+/// `sda1` is a partition of `sda`, and `nvme0n1p1` is a partition of `nvme0n1`.
+///
+/// Like the kernel does it, partition numbers get a `p` in front of them when
+/// the disk name ends in a digit. So `md10` is a disk of its own, not a
+/// partition of `md1`.
+fn is_partition_of(partition: &str, disk: &str) -> bool {
+    let Some(suffix) = partition.strip_prefix(disk) else {
+        return false;
+    };
+    let suffix = if disk.ends_with(|c: char| c.is_ascii_digit()) {
+        let Some(suffix) = suffix.strip_prefix('p') else {
+            return false;
+        };
+        suffix
+    } else {
+        suffix
+    };
+
+    return !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit());
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_network_counters() -> Vec<NetworkCounters> {
+    // NOTE: Getting these numbers on macOS requires going through getifaddrs(),
+    // which we don't do (yet?)
+    return vec![];
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_network_counters() -> Vec<NetworkCounters> {
+    let Ok(net_dev) = std::fs::read_to_string("/proc/net/dev") else {
+        return vec![];
+    };
+
+    return parse_net_dev(&net_dev, |interface| {
+        // Speed is in Mbit/s. Reading it fails for interfaces that are down,
+        // and some drivers report -1 for unknown.
+        let speed = std::fs::read_to_string(format!("/sys/class/net/{interface}/speed")).ok()?;
+        let megabits_per_second = speed.trim().parse::<usize>().ok()?;
+        return Some(megabits_per_second * 1_000_000 / 8);
+    });
+}
+
+/// Parse the contents of `/proc/net/dev`, getting link speeds from the
+/// callback.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_net_dev(
+    net_dev: &str,
+    get_link_speed_bytes_per_second: impl Fn(&str) -> Option<usize>,
+) -> Vec<NetworkCounters> {
+    let mut result: Vec<NetworkCounters> = vec![];
+
+    // The first two lines are headers
+    for line in net_dev.lines().skip(2) {
+        let Some((interface, numbers)) = line.split_once(':') else {
+            continue;
+        };
+        let interface = interface.trim();
+
+        let fields: Vec<&str> = numbers.split_whitespace().collect();
+        if fields.len() < 16 {
+            continue;
+        }
+
+        let number = |index: usize| fields[index].parse::<usize>().unwrap_or(0);
+        result.push(NetworkCounters {
+            interface: interface.to_string(),
+            bytes_received: number(0),
+            bytes_sent: number(8),
+            link_speed_bytes_per_second: get_link_speed_bytes_per_second(interface),
+        });
+    }
+
+    return result;
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_battery_state() -> Option<BatteryState> {
+    // NOTE: Getting this on macOS requires going through IOKit's
+    // IOPSCopyPowerSourcesInfo(), which we don't do (yet?)
+    return None;
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_battery_state() -> Option<BatteryState> {
+    let power_supplies = std::fs::read_dir("/sys/class/power_supply").ok()?;
+
+    let batteries: Vec<BatteryReading> = power_supplies
+        .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("uevent")).ok())
+        .filter_map(|uevent| parse_power_supply_uevent(&uevent))
+        .collect();
+
+    return crate::battery::combine(&batteries);
+}
+
+/// Parse the contents of `/sys/class/power_supply/*/uevent`.
+///
+/// Returns `None` for anything that isn't a system battery. That includes AC
+/// adapters, and also batteries in wireless mice and other peripherals.
+///
+/// Format docs: <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-power>
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_power_supply_uevent(uevent: &str) -> Option<BatteryReading> {
+    let value = |key: &str| {
+        uevent
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+    };
+    let number = |key: &str| value(key)?.parse::<f32>().ok();
+
+    if value("POWER_SUPPLY_TYPE") != Some("Battery") {
+        return None;
+    }
+    if value("POWER_SUPPLY_SCOPE") == Some("Device") {
+        // Peripheral battery
+        return None;
+    }
+
+    // Energy is in µWh. Some batteries report charge in µAh instead, which
+    // times the voltage in µV is energy in µWh.
+    let energy_now_and_full_wh = || {
+        if let (Some(now), Some(full)) = (
+            number("POWER_SUPPLY_ENERGY_NOW"),
+            number("POWER_SUPPLY_ENERGY_FULL"),
+        ) {
+            return Some((now / 1e6, full / 1e6));
+        }
+
+        let now = number("POWER_SUPPLY_CHARGE_NOW")?;
+        let full = number("POWER_SUPPLY_CHARGE_FULL")?;
+        let volts = number("POWER_SUPPLY_VOLTAGE_MIN_DESIGN")? / 1e6;
+        return Some((now * volts / 1e6, full * volts / 1e6));
+    };
+    // Worn out or confused batteries can report a full charge of zero
+    let energy_now_and_full_wh = energy_now_and_full_wh().filter(|&(_, full)| full > 0.0);
+
+    let charge_0_to_1 = match number("POWER_SUPPLY_CAPACITY") {
+        Some(capacity_percent) => capacity_percent / 100.0,
+        None => {
+            let (now, full) = energy_now_and_full_wh?;
+            now / full
+        }
+    };
+    return Some(BatteryReading {
+        state: BatteryState {
+            charge_0_to_1: charge_0_to_1.clamp(0.0, 1.0),
+            charging: value("POWER_SUPPLY_STATUS") == Some("Charging"),
+        },
+        energy_now_and_full_wh,
+    });
+}
+
+#[cfg(target_os = "macos")]
+pub fn get_energy_counters() -> Vec<EnergyCounters> {
+    // NOTE: macOS has no RAPL interface. powermetrics can do this, but only
+    // as root.
+    return vec![];
+}
+
+/// Reads RAPL energy counters. On most modern kernels, the counters are
+/// readable by root only, and then this returns nothing for everybody else.
+#[cfg(target_os = "linux")]
+pub fn get_energy_counters() -> Vec<EnergyCounters> {
+    let Ok(powercap) = std::fs::read_dir("/sys/class/powercap") else {
+        return vec![];
+    };
+
+    let mut result: Vec<EnergyCounters> = powercap
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("intel-rapl:")
+        })
+        .filter_map(|entry| read_rapl_domain(&entry.path()))
+        .collect();
+    result.sort_by(|a, b| a.id.cmp(&b.id));
+
+    return result;
+}
+
+/// Read one `/sys/class/powercap/intel-rapl:*` directory.
+///
+/// Format docs: <https://www.kernel.org/doc/html/latest/power/powercap/powercap.html>
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn read_rapl_domain(directory: &std::path::Path) -> Option<EnergyCounters> {
+    let read = |file: &str| std::fs::read_to_string(directory.join(file)).ok();
+    let read_number = |file: &str| read(file)?.trim().parse::<usize>().ok();
+
+    return Some(EnergyCounters {
+        id: directory.file_name()?.to_string_lossy().to_string(),
+        name: read("name")?.trim().to_string(),
+        energy_microjoules: read_number("energy_uj")?,
+        max_energy_range_microjoules: read_number("max_energy_range_uj").unwrap_or(0),
+        power_limit_microwatts: read_number("constraint_0_power_limit_uw"),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_stat() {
+        let stat = [
+            "cpu  300 20 110 5000 40 0 10 0 0 0",
+            "cpu0 100 10 50 2500 20 0 5 0 0 0",
+            "cpu1 200 10 60 2500 20 0 5 7 0 0",
+            "intr 12345 0 0",
+            "ctxt 98765",
+        ]
+        .join("\n");
+
+        assert_eq!(
+            parse_proc_stat(&stat),
+            vec![
+                LoadCounters {
+                    user: 110,
+                    system: 55,
+                    idle: 2520,
+                },
+                LoadCounters {
+                    user: 210,
+                    system: 72,
+                    idle: 2520,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_diskstats() {
+        let diskstats = [
+            "   7       0 loop0 55 0 2216 13 0 0 0 0 0 40 13 0 0 0 0 0 0",
+            " 259       0 nvme0n1 168203 51296 11540962 40376 323045 256134 18305738 262312 0 287076 333876 0 0 0 0 26064 31188",
+            " 259       1 nvme0n1p1 356 1175 20396 68 2 0 2 0 0 92 68 0 0 0 0 0 0",
+            "   8       0 sda 100 0 800 10 50 0 400 20 0 30 35",
+        ]
+        .join("\n");
+
+        let result = parse_diskstats(&diskstats, |_| None);
+        assert_eq!(
+            result,
+            vec![
+                DiskCounters {
+                    device: "loop0".to_string(),
+                    bytes_read: 2216 * 512,
+                    bytes_written: 0,
+                    time_in_queue_ms: 13,
+                },
+                DiskCounters {
+                    device: "nvme0n1".to_string(),
+                    bytes_read: 11540962 * 512,
+                    bytes_written: 18305738 * 512,
+                    time_in_queue_ms: 333876,
+                },
+                DiskCounters {
+                    device: "sda".to_string(),
+                    bytes_read: 800 * 512,
+                    bytes_written: 400 * 512,
+                    time_in_queue_ms: 35,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_net_dev() {
+        let net_dev = [
+            "Inter-|   Receive                                                |  Transmit",
+            " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed",
+            "    lo: 2776770   11307    0    0    0     0          0         0  2776770   11307    0    0    0     0       0          0",
+            "  eth0:1215645    2751    0    0    0     0          0         0  1782404    4324    0    0    0   427       0          0",
+        ]
+        .join("\n");
+
+        let result = parse_net_dev(&net_dev, |interface| {
+            if interface == "eth0" {
+                return Some(125_000_000);
+            }
+            return None;
+        });
+        assert_eq!(
+            result,
+            vec![
+                NetworkCounters {
+                    interface: "lo".to_string(),
+                    bytes_received: 2776770,
+                    bytes_sent: 2776770,
+                    link_speed_bytes_per_second: None,
+                },
+                NetworkCounters {
+                    interface: "eth0".to_string(),
+                    bytes_received: 1215645,
+                    bytes_sent: 1782404,
+                    link_speed_bytes_per_second: Some(125_000_000),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_power_supply_uevent() {
+        let battery = [
+            "POWER_SUPPLY_NAME=BAT0",
+            "POWER_SUPPLY_TYPE=Battery",
+            "POWER_SUPPLY_STATUS=Charging",
+            "POWER_SUPPLY_PRESENT=1",
+            "POWER_SUPPLY_CAPACITY=87",
+        ]
+        .join("\n");
+        assert_eq!(
+            parse_power_supply_uevent(&battery),
+            Some(BatteryReading {
+                state: BatteryState {
+                    charge_0_to_1: 0.87,
+                    charging: true,
+                },
+                energy_now_and_full_wh: None,
+            })
+        );
+
+        let with_energy = [
+            "POWER_SUPPLY_TYPE=Battery",
+            "POWER_SUPPLY_STATUS=Discharging",
+            "POWER_SUPPLY_ENERGY_FULL=50000000",
+            "POWER_SUPPLY_ENERGY_NOW=12500000",
+        ]
+        .join("\n");
+        assert_eq!(
+            parse_power_supply_uevent(&with_energy),
+            Some(BatteryReading {
+                state: BatteryState {
+                    charge_0_to_1: 0.25,
+                    charging: false,
+                },
+                energy_now_and_full_wh: Some((12.5, 50.0)),
+            })
+        );
+
+        let with_charge = [
+            "POWER_SUPPLY_TYPE=Battery",
+            "POWER_SUPPLY_STATUS=Discharging",
+            "POWER_SUPPLY_VOLTAGE_MIN_DESIGN=10000000",
+            "POWER_SUPPLY_CHARGE_FULL=4000000",
+            "POWER_SUPPLY_CHARGE_NOW=3000000",
+            "POWER_SUPPLY_CAPACITY=75",
+        ]
+        .join("\n");
+        assert_eq!(
+            parse_power_supply_uevent(&with_charge)
+                .unwrap()
+                .energy_now_and_full_wh,
+            Some((30.0, 40.0))
+        );
+
+        let no_full_charge = [
+            "POWER_SUPPLY_TYPE=Battery",
+            "POWER_SUPPLY_ENERGY_FULL=0",
+            "POWER_SUPPLY_ENERGY_NOW=0",
+        ]
+        .join("\n");
+        assert_eq!(parse_power_supply_uevent(&no_full_charge), None);
+
+        let ac = ["POWER_SUPPLY_NAME=AC", "POWER_SUPPLY_TYPE=Mains"].join("\n");
+        assert_eq!(parse_power_supply_uevent(&ac), None);
+
+        let mouse = [
+            "POWER_SUPPLY_NAME=hidpp_battery_0",
+            "POWER_SUPPLY_TYPE=Battery",
+            "POWER_SUPPLY_SCOPE=Device",
+            "POWER_SUPPLY_STATUS=Discharging",
+            "POWER_SUPPLY_CAPACITY=50",
+        ]
+        .join("\n");
+        assert_eq!(parse_power_supply_uevent(&mouse), None);
+    }
+
+    #[test]
+    fn test_read_rapl_domain() {
+        let directory = std::env::temp_dir().join(format!("intel-rapl:{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("name"), "package-0\n").unwrap();
+        std::fs::write(directory.join("energy_uj"), "12345\n").unwrap();
+        std::fs::write(directory.join("max_energy_range_uj"), "262143328850\n").unwrap();
+        std::fs::write(directory.join("constraint_0_power_limit_uw"), "15000000\n").unwrap();
+
+        let result = read_rapl_domain(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            result,
+            Some(EnergyCounters {
+                id: format!("intel-rapl:{}", std::process::id()),
+                name: "package-0".to_string(),
+                energy_microjoules: 12345,
+                max_energy_range_microjoules: 262143328850,
+                power_limit_microwatts: Some(15000000),
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_partition_of() {
+        assert!(is_partition_of("sda1", "sda"));
+        assert!(is_partition_of("nvme0n1p1", "nvme0n1"));
+        assert!(!is_partition_of("sda", "sda"));
+        assert!(!is_partition_of("sdaa", "sda"));
+        assert!(!is_partition_of("sdb1", "sda"));
+
+        // Disks with numbered names
+        assert!(is_partition_of("md1p1", "md1"));
+        assert!(!is_partition_of("md10", "md1"));
+        assert!(!is_partition_of("dm-10", "dm-1"));
+        assert!(!is_partition_of("nbd10", "nbd1"));
+        assert!(!is_partition_of("nvme0n10", "nvme0n1"));
+    }
+
+    #[test]
+    fn test_parse_diskstats_asks_sysfs() {
+        let diskstats = [
+            "   9       1 md1 100 0 800 10 50 0 400 20 0 30 35",
+            "   9      10 md10 100 0 800 10 50 0 400 20 0 30 35",
+            "   8       0 sda 100 0 800 10 50 0 400 20 0 30 35",
+            "   8       1 sda1 100 0 800 10 50 0 400 20 0 30 35",
+        ]
+        .join("\n");
+
+        let result = parse_diskstats(&diskstats, |device| Some(device == "sda1"));
+        let devices: Vec<&str> = result.iter().map(|disk| disk.device.as_str()).collect();
+        assert_eq!(vec!["md1", "md10", "sda"], devices);
+    }
 }
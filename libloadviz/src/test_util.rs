@@ -0,0 +1,16 @@
+//! Helpers shared between unit tests
+
+use std::time::{Duration, Instant};
+
+/// For tests of background threads. Panics after five seconds.
+pub(crate) fn wait_for(description: &str, condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(
+            Instant::now() < deadline,
+            "Timed out waiting for {}",
+            description
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
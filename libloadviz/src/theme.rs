@@ -0,0 +1,341 @@
+//! What colors things are drawn in.
+//!
+//! A theme file is a number of lines, `#` starts a comment. Anything not
+//! mentioned is the same as in the classic theme:
+//!
+//! ```text
+//! # Optional, start from some other preset than classic
+//! base dark-panel
+//!
+//! background 303090
+//!
+//! # Flame colors, from the coolest to the hottest. At least one.
+//! flame ff3800 ffb46b
+//!
+//! # Or color the flames like something glowing at 1000K where coolest, and
+//! # at 6500K where hottest
+//! flame-kelvin 1000 6500
+//!
+//! # What fraction of the inside of the fire fades towards the background
+//! flame-fade 0.3
+//!
+//! # Dark and bright cloud color
+//! cloud 888888 ffffff
+//!
+//! # What fraction of the image height at the cloud edges fades towards the
+//! # background
+//! cloud-fade 0.4
+//!
+//! rain a0b8e8
+//! glow c04010
+//! ```
+//!
+//! Colors are six hex digits, `rrggbb`. Fades are fractions between 0 and 1.
+//!
+//! There are also some presets, see [`Theme::preset()`].
+
+use std::fmt;
+
+static PRESETS: &[(&str, &str)] = &[
+    // Everything as in Theme::default()
+    ("classic", ""),
+    (
+        // For dark menu bars and panels
+        "dark-panel",
+        "background 202024
+        cloud 505058 a0a0a8
+        rain 6078a8
+        glow 803018",
+    ),
+    (
+        // White clouds and pale flames disappear on a light background, so
+        // everything is a bit darker
+        "light-panel",
+        "background ececec
+        flame c02000 ff6000 ffa030
+        cloud 686868 b8b8b8
+        rain 3858a0
+        glow e0a080",
+    ),
+    (
+        "high-contrast",
+        "background 000000
+        flame ff0000 ffff00 ffffff
+        flame-fade 0.1
+        cloud 00a0ff 00ffff
+        cloud-fade 0.1
+        rain ffffff
+        glow 600000",
+    ),
+    (
+        // From the Okabe-Ito palette. Flames are orange to yellow and clouds
+        // are blue, so they don't depend on telling red from green.
+        "colorblind-safe",
+        "background 000000
+        flame d55e00 e69f00 f0e442
+        cloud 0072b2 56b4e9
+        rain cc79a7
+        glow 803000",
+    ),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct ThemeError {
+    /// 1 based
+    pub line_number: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Theme line {}: {}", self.line_number, self.message);
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+/// How flames are colored, from their coolest to their hottest parts
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlameColors {
+    /// Spread evenly over the temperatures. Must not be empty.
+    Gradient(Vec<[u8; 3]>),
+
+    /// The colors of something glowing at these temperatures
+    Blackbody {
+        coolest_kelvin: f32,
+        hottest_kelvin: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Shown when there is no battery, see
+    /// [`Weather::battery`](crate::renderer::Weather::battery)
+    pub background_rgb: [u8; 3],
+
+    /// Used for the temperatures above `flame_transparent_0_to_1`
+    pub flame_colors: FlameColors,
+
+    /// What fraction of the inside of the fire fades towards transparent?
+    pub flame_transparent_0_to_1: f32,
+
+    pub cloud_dark_rgb: [u8; 3],
+    pub cloud_bright_rgb: [u8; 3],
+
+    /// How much of the cloud should fade towards transparent?
+    ///
+    /// This is a fraction of the height of the whole image, not a fraction of
+    /// the height of the cloud.
+    ///
+    /// Lower values make the cloud edge sharper.
+    pub cloud_transparent_0_to_1: f32,
+
+    pub rain_rgb: [u8; 3],
+    pub glow_rgb: [u8; 3],
+}
+
+impl Default for Theme {
+    /// The classic look: a blue background with blackbody colored flames
+    fn default() -> Self {
+        return Theme {
+            background_rgb: [0x30, 0x30, 0x90],
+
+            flame_colors: FlameColors::Blackbody {
+                coolest_kelvin: 1000.0,
+                hottest_kelvin: 6500.0,
+            },
+            flame_transparent_0_to_1: 0.3,
+
+            cloud_dark_rgb: [0x88, 0x88, 0x88],
+            cloud_bright_rgb: [0xff, 0xff, 0xff],
+            cloud_transparent_0_to_1: 0.4,
+
+            rain_rgb: [0xa0, 0xb8, 0xe8],
+            glow_rgb: [0xc0, 0x40, 0x10],
+        };
+    }
+}
+
+impl Theme {
+    /// Available presets are `classic`, `dark-panel`, `light-panel`,
+    /// `high-contrast` and `colorblind-safe`.
+    pub fn preset(name: &str) -> Option<Theme> {
+        let (_, text) = PRESETS.iter().find(|(preset, _)| *preset == name)?;
+        return Some(Theme::parse(text).expect("Presets should be valid"));
+    }
+
+    pub fn preset_names() -> Vec<&'static str> {
+        return PRESETS.iter().map(|(name, _)| *name).collect();
+    }
+
+    pub fn parse(text: &str) -> Result<Theme, ThemeError> {
+        let mut theme: Theme = Default::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| ThemeError {
+                line_number,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => continue,
+                ["base", name] => {
+                    // Presets don't use "base", so this can't recurse forever
+                    theme = Theme::preset(name).ok_or_else(|| {
+                        error(format!(
+                            "No such preset, try one of {}: {}",
+                            Theme::preset_names().join(", "),
+                            name
+                        ))
+                    })?;
+                }
+                ["background", color] => {
+                    theme.background_rgb = parse_color(color).map_err(error)?
+                }
+                ["flame", colors @ ..] if !colors.is_empty() => {
+                    theme.flame_colors = FlameColors::Gradient(
+                        colors
+                            .iter()
+                            .map(|color| parse_color(color))
+                            .collect::<Result<_, _>>()
+                            .map_err(error)?,
+                    );
+                }
+                ["flame-kelvin", coolest, hottest] => {
+                    let coolest_kelvin = parse_kelvin(coolest).map_err(error)?;
+                    let hottest_kelvin = parse_kelvin(hottest).map_err(error)?;
+                    if coolest_kelvin > hottest_kelvin {
+                        return Err(error(format!("Coolest is hotter than hottest: {}", line)));
+                    }
+                    theme.flame_colors = FlameColors::Blackbody {
+                        coolest_kelvin,
+                        hottest_kelvin,
+                    };
+                }
+                ["flame-fade", fraction] => {
+                    // 1 would leave no temperatures for the flame colors
+                    theme.flame_transparent_0_to_1 =
+                        parse_fraction(fraction, |fraction| fraction < 1.0).map_err(error)?;
+                }
+                ["cloud", dark, bright] => {
+                    theme.cloud_dark_rgb = parse_color(dark).map_err(error)?;
+                    theme.cloud_bright_rgb = parse_color(bright).map_err(error)?;
+                }
+                ["cloud-fade", fraction] => {
+                    // We divide by this one
+                    theme.cloud_transparent_0_to_1 =
+                        parse_fraction(fraction, |fraction| fraction > 0.0).map_err(error)?;
+                }
+                ["rain", color] => theme.rain_rgb = parse_color(color).map_err(error)?,
+                ["glow", color] => theme.glow_rgb = parse_color(color).map_err(error)?,
+                _ => return Err(error(format!("Don't know what to do with: {}", line))),
+            }
+        }
+
+        return Ok(theme);
+    }
+}
+
+/// Parses `rrggbb`
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let error = || format!("Not an rrggbb color: {}", text);
+    if text.len() != 6 || !text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(error());
+    }
+
+    let mut color = [0; 3];
+    for (i, component) in color.iter_mut().enumerate() {
+        *component = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).map_err(|_| error())?;
+    }
+    return Ok(color);
+}
+
+fn parse_kelvin(text: &str) -> Result<f32, String> {
+    return text
+        .parse::<f32>()
+        .ok()
+        .filter(|kelvin| *kelvin > 0.0 && kelvin.is_finite())
+        .ok_or_else(|| format!("Not a temperature in Kelvin: {}", text));
+}
+
+/// Parses a number between 0 and 1, that `accept` must also be happy with
+fn parse_fraction(text: &str, accept: impl Fn(f32) -> bool) -> Result<f32, String> {
+    return text
+        .parse::<f32>()
+        .ok()
+        .filter(|fraction| (0.0..=1.0).contains(fraction) && accept(*fraction))
+        .ok_or_else(|| format!("Not a usable fraction: {}", text));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_parse() {
+        for name in Theme::preset_names() {
+            let theme = Theme::preset(name).unwrap();
+            if let FlameColors::Gradient(colors) = theme.flame_colors {
+                assert!(!colors.is_empty(), "{}", name);
+            }
+        }
+        assert_eq!(Some(Theme::default()), Theme::preset("classic"));
+        assert_eq!(None, Theme::preset("no-such-preset"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let theme = Theme::parse(
+            "# Comment
+            base light-panel
+            background 102030 # Trailing comment
+            flame ff0000 00ff00 0000ff
+            cloud-fade 0.25",
+        )
+        .unwrap();
+
+        assert_eq!([0x10, 0x20, 0x30], theme.background_rgb);
+        assert_eq!(
+            FlameColors::Gradient(vec![[0xff, 0, 0], [0, 0xff, 0], [0, 0, 0xff]]),
+            theme.flame_colors
+        );
+        assert_eq!(0.25, theme.cloud_transparent_0_to_1);
+
+        // From the base
+        let light_panel = Theme::preset("light-panel").unwrap();
+        assert_eq!(light_panel.cloud_dark_rgb, theme.cloud_dark_rgb);
+        assert_eq!(light_panel.rain_rgb, theme.rain_rgb);
+
+        assert_eq!(
+            FlameColors::Blackbody {
+                coolest_kelvin: 800.0,
+                hottest_kelvin: 6500.0
+            },
+            Theme::parse("flame-kelvin 800 6500").unwrap().flame_colors
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            ThemeError {
+                line_number: 2,
+                message: "Not an rrggbb color: 12345g".to_string(),
+            },
+            Theme::parse("\nbackground 12345g").unwrap_err()
+        );
+        assert!(Theme::parse("flame").is_err());
+        assert!(Theme::parse("flame-fade 1").is_err());
+        assert!(Theme::parse("flame-kelvin 3000 1000").is_err());
+        assert!(Theme::parse("flame-kelvin 0 1000").is_err());
+        assert!(Theme::parse("flame-kelvin 1000").is_err());
+        assert!(Theme::parse("cloud-fade 0").is_err());
+        assert!(Theme::parse("cloud-fade -0.5").is_err());
+        assert!(Theme::parse("cloud 888888").is_err());
+        assert!(Theme::parse("base no-such-preset").is_err());
+        assert!(Theme::parse("sparkles ffffff").is_err());
+    }
+}
@@ -0,0 +1,530 @@
+//! Load traces are recordings of [`Sample`]s, for looking at what a machine
+//! was doing after the fact.
+//!
+//! The format is line based text. The first line is a header containing the
+//! format version, and then there is one line per sample:
+//!
+//! ```text
+//! loadviz-trace 1
+//! t=1700000000000 cpu=10,5,85;20,5,75 disk=sda,512,1024,3 net=eth0,100,200,125000000 battery=0.87,1 energy=intel-rapl:0,package-0,12345,262143328850,15000000
+//! ```
+//!
+//! * `t` is milliseconds since the Unix epoch
+//! * `cpu` is `user,system,idle` for each core
+//! * `disk` is `device,bytes_read,bytes_written,time_in_queue_ms` for each disk
+//! * `net` is `interface,bytes_received,bytes_sent,link_speed` for each network
+//!   interface, with `-` for unknown link speeds
+//! * `battery` is `charge_0_to_1,charging` or `-` for no battery
+//! * `energy` is `id,name,energy_uj,max_energy_range_uj,power_limit_uw` for
+//!   each RAPL counter, with `-` for unknown power limits
+//!
+//! Names are percent encoded if they contain any separator characters. Unknown
+//! fields are ignored, so new fields can be added without bumping the version.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::battery::BatteryState;
+use crate::cpuload::LoadCounters;
+use crate::diskload::DiskCounters;
+use crate::netload::NetworkCounters;
+use crate::power::EnergyCounters;
+use crate::system_load::Sample;
+
+static MAGIC: &str = "loadviz-trace";
+pub static VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+
+    /// The header is missing or isn't ours
+    NotATrace,
+
+    /// The trace was written by some other version of this library
+    UnsupportedVersion(u32),
+
+    /// The last sample was cut off, usually because the recording process
+    /// was killed while writing it
+    Truncated,
+
+    /// `line_number` is 1 based, and counts the header as well
+    Malformed {
+        line_number: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TraceError::Io(error) => write!(f, "I/O error: {}", error),
+            TraceError::NotATrace => write!(f, "Not a load trace"),
+            TraceError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported load trace version {}, expected {}",
+                version, VERSION
+            ),
+            TraceError::Truncated => write!(f, "Load trace truncated"),
+            TraceError::Malformed {
+                line_number,
+                message,
+            } => write!(f, "Malformed load trace line {}: {}", line_number, message),
+        };
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(error: io::Error) -> Self {
+        return TraceError::Io(error);
+    }
+}
+
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Writes the trace header
+    pub fn new(mut writer: W) -> io::Result<TraceWriter<W>> {
+        writeln!(writer, "{} {}", MAGIC, VERSION)?;
+        writer.flush()?;
+
+        return Ok(TraceWriter { writer });
+    }
+
+    /// Samples are flushed as they are written, so that as much as possible
+    /// survives if we get killed.
+    pub fn write_sample(&mut self, sample: &Sample) -> io::Result<()> {
+        writeln!(self.writer, "{}", format_sample(sample))?;
+        return self.writer.flush();
+    }
+}
+
+/// Iterate over this to get the samples. Iteration stops after the first
+/// error.
+pub struct TraceReader<R: BufRead> {
+    reader: R,
+    line_number: usize,
+    done: bool,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /// Reads and validates the trace header
+    pub fn new(mut reader: R) -> Result<TraceReader<R>, TraceError> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+
+        let mut words = header.split_whitespace();
+        if words.next() != Some(MAGIC) {
+            return Err(TraceError::NotATrace);
+        }
+        let Some(version) = words.next().and_then(|version| version.parse::<u32>().ok()) else {
+            return Err(TraceError::NotATrace);
+        };
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        return Ok(TraceReader {
+            reader,
+            line_number: 1,
+            done: false,
+        });
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<Sample, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut line = String::new();
+        let result = match self.reader.read_line(&mut line) {
+            Ok(0) => return None,
+            Err(error) => Err(TraceError::Io(error)),
+            Ok(_) if !line.ends_with('\n') => Err(TraceError::Truncated),
+            Ok(_) => {
+                self.line_number += 1;
+                parse_sample(line.trim_end()).map_err(|message| TraceError::Malformed {
+                    line_number: self.line_number,
+                    message,
+                })
+            }
+        };
+
+        if result.is_err() {
+            self.done = true;
+        }
+        return Some(result);
+    }
+}
+
+pub(crate) fn format_sample(sample: &Sample) -> String {
+    let millis = sample
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis();
+
+    let cpus = format_list(&sample.cpus, |cpu| {
+        format!("{},{},{}", cpu.user, cpu.system, cpu.idle)
+    });
+    let disks = format_list(&sample.disks, |disk| {
+        format!(
+            "{},{},{},{}",
+            escape(&disk.device),
+            disk.bytes_read,
+            disk.bytes_written,
+            disk.time_in_queue_ms
+        )
+    });
+    let networks = format_list(&sample.networks, |network| {
+        format!(
+            "{},{},{},{}",
+            escape(&network.interface),
+            network.bytes_received,
+            network.bytes_sent,
+            format_optional(network.link_speed_bytes_per_second)
+        )
+    });
+    let battery = match sample.battery {
+        Some(battery) => format!("{},{}", battery.charge_0_to_1, battery.charging as u8),
+        None => "-".to_string(),
+    };
+    let energy = format_list(&sample.energy, |energy| {
+        format!(
+            "{},{},{},{},{}",
+            escape(&energy.id),
+            escape(&energy.name),
+            energy.energy_microjoules,
+            energy.max_energy_range_microjoules,
+            format_optional(energy.power_limit_microwatts)
+        )
+    });
+
+    return format!(
+        "t={} cpu={} disk={} net={} battery={} energy={}",
+        millis, cpus, disks, networks, battery, energy
+    );
+}
+
+fn format_list<T>(items: &[T], format_item: impl Fn(&T) -> String) -> String {
+    return items.iter().map(format_item).collect::<Vec<_>>().join(";");
+}
+
+fn format_optional(value: Option<usize>) -> String {
+    return match value {
+        Some(value) => value.to_string(),
+        None => "-".to_string(),
+    };
+}
+
+pub(crate) fn parse_sample(line: &str) -> Result<Sample, String> {
+    let mut timestamp: Option<SystemTime> = None;
+    let mut sample = Sample {
+        timestamp: UNIX_EPOCH,
+        cpus: vec![],
+        disks: vec![],
+        networks: vec![],
+        battery: None,
+        energy: vec![],
+    };
+
+    for field in line.split(' ') {
+        let Some((key, value)) = field.split_once('=') else {
+            return Err(format!("Expected key=value: {}", field));
+        };
+
+        match key {
+            "t" => {
+                let millis = parse_number(value)?;
+                timestamp = Some(UNIX_EPOCH + Duration::from_millis(millis as u64));
+            }
+            "cpu" => {
+                sample.cpus = parse_list(value, 3, |parts| {
+                    return Ok(LoadCounters {
+                        user: parse_number(parts[0])?,
+                        system: parse_number(parts[1])?,
+                        idle: parse_number(parts[2])?,
+                    });
+                })?;
+            }
+            "disk" => {
+                sample.disks = parse_list(value, 4, |parts| {
+                    return Ok(DiskCounters {
+                        device: unescape(parts[0])?,
+                        bytes_read: parse_number(parts[1])?,
+                        bytes_written: parse_number(parts[2])?,
+                        time_in_queue_ms: parse_number(parts[3])?,
+                    });
+                })?;
+            }
+            "net" => {
+                sample.networks = parse_list(value, 4, |parts| {
+                    return Ok(NetworkCounters {
+                        interface: unescape(parts[0])?,
+                        bytes_received: parse_number(parts[1])?,
+                        bytes_sent: parse_number(parts[2])?,
+                        link_speed_bytes_per_second: parse_optional(parts[3])?,
+                    });
+                })?;
+            }
+            "battery" => {
+                sample.battery = parse_battery(value)?;
+            }
+            "energy" => {
+                sample.energy = parse_list(value, 5, |parts| {
+                    return Ok(EnergyCounters {
+                        id: unescape(parts[0])?,
+                        name: unescape(parts[1])?,
+                        energy_microjoules: parse_number(parts[2])?,
+                        max_energy_range_microjoules: parse_number(parts[3])?,
+                        power_limit_microwatts: parse_optional(parts[4])?,
+                    });
+                })?;
+            }
+            _ => {
+                // Written by some newer version of us, ignore
+            }
+        }
+    }
+
+    let Some(timestamp) = timestamp else {
+        return Err("Timestamp missing".to_string());
+    };
+    sample.timestamp = timestamp;
+
+    return Ok(sample);
+}
+
+fn parse_list<T>(
+    value: &str,
+    part_count: usize,
+    parse_item: impl Fn(&[&str]) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    if value.is_empty() {
+        return Ok(vec![]);
+    }
+
+    return value
+        .split(';')
+        .map(|item| {
+            let parts: Vec<&str> = item.split(',').collect();
+            if parts.len() != part_count {
+                return Err(format!("Expected {} values: {}", part_count, item));
+            }
+            return parse_item(&parts);
+        })
+        .collect();
+}
+
+fn parse_battery(value: &str) -> Result<Option<BatteryState>, String> {
+    if value == "-" {
+        return Ok(None);
+    }
+
+    let Some((charge, charging)) = value.split_once(',') else {
+        return Err(format!("Expected charge,charging: {}", value));
+    };
+    let charge_0_to_1 = charge
+        .parse::<f32>()
+        .map_err(|_| format!("Not a battery charge: {}", charge))?;
+
+    return Ok(Some(BatteryState {
+        charge_0_to_1,
+        charging: parse_number(charging)? != 0,
+    }));
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    return value
+        .parse::<usize>()
+        .map_err(|_| format!("Not a number: {}", value));
+}
+
+fn parse_optional(value: &str) -> Result<Option<usize>, String> {
+    if value == "-" {
+        return Ok(None);
+    }
+    return parse_number(value).map(Some);
+}
+
+/// Percent encode anything that would confuse the parser
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_graphic() && !b"%,;=".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    return escaped;
+}
+
+fn unescape(escaped: &str) -> Result<String, String> {
+    let mut bytes: Vec<u8> = vec![];
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+
+        let hex = tail
+            .get(0..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        let Some(decoded) = hex else {
+            return Err(format!("Broken escape sequence: {}", escaped));
+        };
+        bytes.push(decoded);
+        rest = &tail[2..];
+    }
+
+    return String::from_utf8(bytes).map_err(|_| format!("Not UTF-8: {}", escaped));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpuload;
+
+    fn sample(millis: u64, cpu_count: usize) -> Sample {
+        return Sample {
+            timestamp: UNIX_EPOCH + Duration::from_millis(millis),
+            cpus: (0..cpu_count)
+                .map(|i| LoadCounters {
+                    user: millis as usize + i,
+                    system: 2 * millis as usize,
+                    idle: 3 * millis as usize,
+                })
+                .collect(),
+            disks: vec![DiskCounters {
+                device: "sda".to_string(),
+                bytes_read: 512,
+                bytes_written: 1024,
+                time_in_queue_ms: 3,
+            }],
+            networks: vec![NetworkCounters {
+                // Separators in names must survive the round trip
+                interface: "odd name,with;separators=-%".to_string(),
+                bytes_received: 100,
+                bytes_sent: 200,
+                link_speed_bytes_per_second: None,
+            }],
+            battery: Some(BatteryState {
+                charge_0_to_1: 0.87,
+                charging: true,
+            }),
+            energy: vec![EnergyCounters {
+                id: "intel-rapl:0".to_string(),
+                name: "package-0".to_string(),
+                energy_microjoules: 12345,
+                max_energy_range_microjoules: 262143328850,
+                power_limit_microwatts: Some(15000000),
+            }],
+        };
+    }
+
+    fn write_trace(samples: &[Sample]) -> Vec<u8> {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for sample in samples {
+            writer.write_sample(sample).unwrap();
+        }
+        return writer.writer;
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let samples = vec![sample(1000, 2), sample(2000, 2)];
+        let trace = write_trace(&samples);
+
+        let read: Vec<Sample> = TraceReader::new(trace.as_slice())
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(samples, read);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut trace = write_trace(&[sample(1000, 2), sample(2000, 2)]);
+
+        // Cut the last sample in half
+        trace.truncate(trace.len() - 20);
+
+        let mut reader = TraceReader::new(trace.as_slice()).unwrap();
+        assert_eq!(sample(1000, 2), reader.next().unwrap().unwrap());
+        assert!(matches!(reader.next(), Some(Err(TraceError::Truncated))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let trace = "loadviz-trace 999\nt=1000 cpu=1,2,3\n";
+        let result = TraceReader::new(trace.as_bytes());
+        assert!(matches!(result, Err(TraceError::UnsupportedVersion(999))));
+    }
+
+    #[test]
+    fn test_not_a_trace() {
+        let result = TraceReader::new("Hello\n".as_bytes());
+        assert!(matches!(result, Err(TraceError::NotATrace)));
+
+        let result = TraceReader::new("".as_bytes());
+        assert!(matches!(result, Err(TraceError::NotATrace)));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let trace = "loadviz-trace 1\nt=1000 cpu=1,2,3\nt=2000 cpu=1,2\n";
+        let mut reader = TraceReader::new(trace.as_bytes()).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(TraceError::Malformed { line_number: 3, .. }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_unknown_fields_ignored() {
+        let trace = "loadviz-trace 1\nt=1000 cpu=1,2,3 future=stuff\n";
+        let mut reader = TraceReader::new(trace.as_bytes()).unwrap();
+        let sample = reader.next().unwrap().unwrap();
+        assert_eq!(1, sample.cpus.len());
+    }
+
+    /// CPUs can be hot plugged, and traces can be concatenated from different
+    /// machines
+    #[test]
+    fn test_core_count_change() {
+        let samples = vec![sample(1000, 2), sample(2000, 4), sample(3000, 4)];
+        let trace = write_trace(&samples);
+
+        let read: Vec<Sample> = TraceReader::new(trace.as_slice())
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(samples, read);
+
+        // Diffing across the change should give us the new core count, with
+        // zero load
+        let loads = cpuload::diff(&read[0].cpus, &read[1].cpus);
+        assert_eq!(4, loads.len());
+        assert_eq!(0.0, loads[0].user_0_to_1);
+
+        let loads = cpuload::diff(&read[1].cpus, &read[2].cpus);
+        assert_eq!(4, loads.len());
+        assert!(loads[0].user_0_to_1 > 0.0);
+    }
+}
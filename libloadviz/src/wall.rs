@@ -0,0 +1,285 @@
+use std::time::Instant;
+
+use crate::load_reader::LoadReader;
+use crate::load_source::LoadSource;
+use crate::physics::DisplayedState;
+use crate::renderer::Renderer;
+
+mod label;
+
+/// Drawn between panels, so that neighboring machines don't blend together
+static SEPARATOR_COLOR_RGB: &[u8; 3] = &[0x00, 0x00, 0x00];
+
+/// Panels showing the same load would flicker in lockstep without this
+static PANEL_TIME_OFFSET_SECONDS: f32 = 7.3;
+
+/// Labels get about this much of the panel height, but are never drawn smaller
+/// than the font's native size
+static LABEL_HEIGHT_0_TO_1: f32 = 0.1;
+
+/// Several machines side by side in one image.
+///
+/// Each panel gets its own load source, its own animation state and a label.
+pub struct Wall {
+    panels: Vec<Panel>,
+    renderer: Renderer,
+
+    /// When this object was created
+    t0: Instant,
+
+    width: usize,
+    height: usize,
+
+    /// Size: 3 * width * height. Format: RGBRGBRGB...
+    pixels: Vec<u8>,
+}
+
+struct Panel {
+    label: String,
+    load_reader: LoadReader,
+    displayed: DisplayedState,
+
+    /// This panel rendered on its own, before being copied into the wall
+    pixels: Vec<u8>,
+}
+
+/// Where in the wall image a panel goes, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for Wall {
+    fn default() -> Self {
+        return Self {
+            panels: vec![],
+            renderer: Default::default(),
+            t0: Instant::now(),
+            width: 0,
+            height: 0,
+            pixels: vec![],
+        };
+    }
+}
+
+impl Wall {
+    /// Panels are laid out in the order they are added
+    pub fn add_panel(&mut self, label: &str, source: Box<dyn LoadSource>) {
+        self.panels.push(Panel {
+            label: label.to_string(),
+            load_reader: LoadReader::new(source),
+            displayed: DisplayedState::new(),
+            pixels: vec![],
+        });
+    }
+
+    pub fn panel_count(&self) -> usize {
+        return self.panels.len();
+    }
+
+    /// Returns `3 * width * height` bytes, RGBRGBRGB...
+    pub fn render(&mut self, width: usize, height: usize) -> &[u8] {
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            self.pixels = vec![0; width * height * 3];
+        }
+
+        let t = self.t0.elapsed().as_secs_f32();
+        let rects = layout(self.panels.len(), width, height);
+        for (index, (panel, rect)) in self.panels.iter_mut().zip(rects.iter()).enumerate() {
+            panel.pixels.resize(rect.width * rect.height * 3, 0);
+
+            if panel.load_reader.get_loads().is_empty() {
+                // Nothing to show yet, but still show which machine this is
+                panel.pixels.fill(0);
+            } else {
+                panel.displayed.update(&mut panel.load_reader);
+                self.renderer.render_image(
+                    &panel.displayed.loads,
+                    &panel.displayed.weather,
+                    rect.width,
+                    rect.height,
+                    t + index as f32 * PANEL_TIME_OFFSET_SECONDS,
+                    &mut panel.pixels,
+                );
+            }
+
+            blit(&mut self.pixels, width, rect, &panel.pixels);
+            draw_separators(&mut self.pixels, width, rect);
+
+            let label_height = (rect.height as f32 * LABEL_HEIGHT_0_TO_1) as usize;
+            let scale = (label_height / label::LABEL_HEIGHT).max(1);
+            label::draw_label(&mut self.pixels, width, rect, &panel.label, scale);
+        }
+
+        return &self.pixels;
+    }
+}
+
+/// Split a `width` x `height` image into `panel_count` panels.
+///
+/// Panels are laid out in a grid, left to right and top to bottom, with the
+/// number of columns picked to make the panels as square as possible.
+pub fn layout(panel_count: usize, width: usize, height: usize) -> Vec<PanelRect> {
+    if panel_count == 0 {
+        return vec![];
+    }
+
+    let squareness = |columns: usize| -> f32 {
+        let rows = panel_count.div_ceil(columns);
+        let panel_width = width as f32 / columns as f32;
+        let panel_height = height as f32 / rows as f32;
+        return -(panel_width / panel_height).ln().abs();
+    };
+    let columns = (1..=panel_count)
+        .max_by(|a, b| squareness(*a).total_cmp(&squareness(*b)))
+        .unwrap();
+    let rows = panel_count.div_ceil(columns);
+
+    // Integer math so that the panels cover every pixel
+    return (0..panel_count)
+        .map(|index| {
+            let column = index % columns;
+            let row = index / columns;
+            let x0 = width * column / columns;
+            let x1 = width * (column + 1) / columns;
+            let y0 = height * row / rows;
+            let y1 = height * (row + 1) / rows;
+            return PanelRect {
+                x: x0,
+                y: y0,
+                width: x1 - x0,
+                height: y1 - y0,
+            };
+        })
+        .collect();
+}
+
+/// Copy a panel's pixels into the wall image
+fn blit(pixels: &mut [u8], image_width: usize, rect: &PanelRect, panel_pixels: &[u8]) {
+    let row_bytes = rect.width * 3;
+    for y in 0..rect.height {
+        let to = 3 * ((rect.y + y) * image_width + rect.x);
+        let from = y * row_bytes;
+        pixels[to..to + row_bytes].copy_from_slice(&panel_pixels[from..from + row_bytes]);
+    }
+}
+
+/// Separate the panel from its neighbors to the left and above
+fn draw_separators(pixels: &mut [u8], image_width: usize, rect: &PanelRect) {
+    if rect.x > 0 {
+        for y in rect.y..rect.y + rect.height {
+            let i = 3 * (y * image_width + rect.x);
+            pixels[i..i + 3].copy_from_slice(SEPARATOR_COLOR_RGB);
+        }
+    }
+    if rect.y > 0 {
+        for x in rect.x..rect.x + rect.width {
+            let i = 3 * (rect.y * image_width + x);
+            pixels[i..i + 3].copy_from_slice(SEPARATOR_COLOR_RGB);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Scenario, ScenarioSource};
+
+    /// Every pixel should belong to exactly one panel
+    fn assert_covers(rects: &[PanelRect], width: usize, height: usize) {
+        let mut owners = vec![0; width * height];
+        for rect in rects {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    owners[y * width + x] += 1;
+                }
+            }
+        }
+        assert!(owners.iter().all(|count| *count == 1));
+    }
+
+    #[test]
+    fn test_layout_single() {
+        assert_eq!(
+            layout(1, 100, 50),
+            vec![PanelRect {
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 50,
+            }]
+        );
+        assert!(layout(0, 100, 50).is_empty());
+    }
+
+    #[test]
+    fn test_layout_grid() {
+        // Square image, four panels: 2x2
+        let rects = layout(4, 100, 100);
+        assert_eq!(50, rects[3].x);
+        assert_eq!(50, rects[3].y);
+        assert_covers(&rects, 100, 100);
+
+        // Wide image, three panels: side by side
+        let rects = layout(3, 300, 100);
+        assert!(rects.iter().all(|rect| rect.y == 0 && rect.height == 100));
+        assert_covers(&rects, 300, 100);
+    }
+
+    #[test]
+    fn test_layout_uneven() {
+        // Sizes that don't divide evenly, and an incomplete last row
+        let rects = layout(5, 101, 77);
+        assert_eq!(5, rects.len());
+        let mut owners = vec![0; 101 * 77];
+        for rect in &rects {
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    owners[y * 101 + x] += 1;
+                }
+            }
+        }
+        assert!(owners.iter().all(|count| *count <= 1));
+    }
+
+    #[test]
+    fn test_render_panels() {
+        let mut wall: Wall = Default::default();
+        wall.add_panel(
+            "busy",
+            Box::new(ScenarioSource::new(
+                Scenario::preset("all-cores-busy").unwrap(),
+            )),
+        );
+        wall.add_panel(
+            "idle",
+            Box::new(ScenarioSource::new(Scenario::parse("all user 0").unwrap())),
+        );
+        assert_eq!(2, wall.panel_count());
+
+        let width = 60;
+        let height = 20;
+        let pixels = wall.render(width, height);
+        assert_eq!(width * height * 3, pixels.len());
+
+        let pixel = |x: usize, y: usize| -> [u8; 3] {
+            let i = 3 * (y * width + x);
+            return [pixels[i], pixels[i + 1], pixels[i + 2]];
+        };
+
+        // Separator between the panels
+        for y in 0..height {
+            assert_eq!(*SEPARATOR_COLOR_RGB, pixel(30, y));
+        }
+
+        // Top left corner of the "B" in "busy" and of the "I" in "idle". The
+        // "I" starts one font pixel in.
+        assert_eq!([0xff, 0xff, 0xff], pixel(1, 1));
+        assert_eq!([0xff, 0xff, 0xff], pixel(30 + 2, 1));
+    }
+}
@@ -0,0 +1,192 @@
+use super::PanelRect;
+
+static GLYPH_WIDTH: usize = 5;
+static GLYPH_HEIGHT: usize = 7;
+
+/// Space between glyphs and around the label, in font pixels
+static SPACING: usize = 1;
+
+/// Label height including the space around it, in font pixels
+pub(super) static LABEL_HEIGHT: usize = GLYPH_HEIGHT + 2 * SPACING;
+
+static TEXT_COLOR_RGB: &[u8; 3] = &[0xff, 0xff, 0xff];
+static SHADOW_COLOR_RGB: &[u8; 3] = &[0x00, 0x00, 0x00];
+
+/// Shown for characters we don't have glyphs for
+#[rustfmt::skip]
+static UNKNOWN_GLYPH: [&str; 7] = [".###.", "#...#", "....#", "...#.", "..#..", ".....", "..#.."];
+
+/// Enough for host names. Lower case letters are drawn as upper case.
+#[rustfmt::skip]
+static GLYPHS: &[(char, [&str; 7])] = &[
+    (' ', [".....", ".....", ".....", ".....", ".....", ".....", "....."]),
+    ('-', [".....", ".....", ".....", "#####", ".....", ".....", "....."]),
+    ('.', [".....", ".....", ".....", ".....", ".....", ".##..", ".##.."]),
+    ('_', [".....", ".....", ".....", ".....", ".....", ".....", "#####"]),
+    (':', [".....", ".##..", ".##..", ".....", ".##..", ".##..", "....."]),
+    ('/', ["....#", "....#", "...#.", "..#..", ".#...", "#....", "#...."]),
+    ('0', [".###.", "#...#", "#..##", "#.#.#", "##..#", "#...#", ".###."]),
+    ('1', ["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('2', [".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"]),
+    ('3', ["#####", "...#.", "..#..", "...#.", "....#", "#...#", ".###."]),
+    ('4', ["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."]),
+    ('5', ["#####", "#....", "####.", "....#", "....#", "#...#", ".###."]),
+    ('6', ["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."]),
+    ('7', ["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."]),
+    ('8', [".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."]),
+    ('9', [".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."]),
+    ('A', [".###.", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('B', ["####.", "#...#", "#...#", "####.", "#...#", "#...#", "####."]),
+    ('C', [".###.", "#...#", "#....", "#....", "#....", "#...#", ".###."]),
+    ('D', ["###..", "#..#.", "#...#", "#...#", "#...#", "#..#.", "###.."]),
+    ('E', ["#####", "#....", "#....", "####.", "#....", "#....", "#####"]),
+    ('F', ["#####", "#....", "#....", "####.", "#....", "#....", "#...."]),
+    ('G', [".###.", "#...#", "#....", "#.###", "#...#", "#...#", ".####"]),
+    ('H', ["#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('I', [".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('J', ["..###", "...#.", "...#.", "...#.", "...#.", "#..#.", ".##.."]),
+    ('K', ["#...#", "#..#.", "#.#..", "##...", "#.#..", "#..#.", "#...#"]),
+    ('L', ["#....", "#....", "#....", "#....", "#....", "#....", "#####"]),
+    ('M', ["#...#", "##.##", "#.#.#", "#.#.#", "#...#", "#...#", "#...#"]),
+    ('N', ["#...#", "#...#", "##..#", "#.#.#", "#..##", "#...#", "#...#"]),
+    ('O', [".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('P', ["####.", "#...#", "#...#", "####.", "#....", "#....", "#...."]),
+    ('Q', [".###.", "#...#", "#...#", "#...#", "#.#.#", "#..#.", ".##.#"]),
+    ('R', ["####.", "#...#", "#...#", "####.", "#.#..", "#..#.", "#...#"]),
+    ('S', [".####", "#....", "#....", ".###.", "....#", "....#", "####."]),
+    ('T', ["#####", "..#..", "..#..", "..#..", "..#..", "..#..", "..#.."]),
+    ('U', ["#...#", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('V', ["#...#", "#...#", "#...#", "#...#", "#...#", ".#.#.", "..#.."]),
+    ('W', ["#...#", "#...#", "#...#", "#.#.#", "#.#.#", "#.#.#", ".#.#."]),
+    ('X', ["#...#", "#...#", ".#.#.", "..#..", ".#.#.", "#...#", "#...#"]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#..", "..#.."]),
+    ('Z', ["#####", "....#", "...#.", "..#..", ".#...", "#....", "#####"]),
+];
+
+/// Draw `text` into the top left corner of `rect`, clipped to `rect`.
+///
+/// `image_width` is the width of the whole image in pixels, `scale` is how many
+/// image pixels wide each font pixel is.
+pub(super) fn draw_label(
+    pixels: &mut [u8],
+    image_width: usize,
+    rect: &PanelRect,
+    text: &str,
+    scale: usize,
+) {
+    let mut glyph_x = SPACING;
+    for c in text.chars() {
+        let glyph = get_glyph(c);
+
+        // Shadow first, one font pixel down and to the right
+        draw_glyph(
+            pixels,
+            image_width,
+            rect,
+            glyph,
+            glyph_x + 1,
+            SPACING + 1,
+            scale,
+            SHADOW_COLOR_RGB,
+        );
+        draw_glyph(
+            pixels,
+            image_width,
+            rect,
+            glyph,
+            glyph_x,
+            SPACING,
+            scale,
+            TEXT_COLOR_RGB,
+        );
+
+        glyph_x += GLYPH_WIDTH + SPACING;
+    }
+}
+
+fn get_glyph(c: char) -> &'static [&'static str; 7] {
+    let c = c.to_ascii_uppercase();
+    return GLYPHS
+        .iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .map(|(_, glyph)| glyph)
+        .unwrap_or(&UNKNOWN_GLYPH);
+}
+
+/// `font_x` and `font_y` are relative to the top left corner of `rect`, in
+/// font pixels
+#[allow(clippy::too_many_arguments)]
+fn draw_glyph(
+    pixels: &mut [u8],
+    image_width: usize,
+    rect: &PanelRect,
+    glyph: &[&str; 7],
+    font_x: usize,
+    font_y: usize,
+    scale: usize,
+    color: &[u8; 3],
+) {
+    for (row, line) in glyph.iter().enumerate().take(GLYPH_HEIGHT) {
+        for (column, bit) in line.chars().enumerate() {
+            if bit != '#' {
+                continue;
+            }
+
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let x = (font_x + column) * scale + dx;
+                    let y = (font_y + row) * scale + dy;
+                    if x >= rect.width || y >= rect.height {
+                        continue;
+                    }
+
+                    let i = 3 * ((rect.y + y) * image_width + rect.x + x);
+                    pixels[i..i + 3].copy_from_slice(color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glyph_sizes() {
+        for (c, glyph) in GLYPHS.iter() {
+            for line in glyph.iter() {
+                assert_eq!(GLYPH_WIDTH, line.len(), "Glyph {:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_label_clipped() {
+        let width = 20;
+        let height = 10;
+        let mut pixels = vec![0x80u8; width * height * 3];
+        let rect = PanelRect {
+            x: 10,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+
+        // Long enough to need clipping, should not panic or draw outside rect
+        draw_label(&mut pixels, width, &rect, "build-01", 1);
+
+        for y in 0..height {
+            for x in 0..width {
+                let i = 3 * (y * width + x);
+                if x < 10 {
+                    assert_eq!([0x80, 0x80, 0x80], pixels[i..i + 3]);
+                }
+            }
+        }
+
+        // Top left pixel of the "B" is lit
+        let i = 3 * (SPACING * width + 10 + SPACING);
+        assert_eq!(TEXT_COLOR_RGB[..], pixels[i..i + 3]);
+    }
+}
@@ -0,0 +1,101 @@
+//! Builds `c_api_test.c` against the static library and runs it, under
+//! valgrind if that's installed. Set `LOADVIZ_REQUIRE_VALGRIND=1` to fail
+//! rather than run without valgrind, CI does.
+
+#![allow(clippy::needless_return)]
+#![cfg(target_os = "linux")]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// `target/debug/deps`, where we are, and where `cargo test` puts
+/// `liblibloadviz-HASH.a`
+fn deps_directory() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    return exe.parent().unwrap().to_path_buf();
+}
+
+/// There can be several, from different builds. The newest one is the one
+/// built for this test run.
+fn find_library(deps_dir: &Path) -> Option<PathBuf> {
+    return std::fs::read_dir(deps_dir)
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            return name.starts_with("liblibloadviz-") && name.ends_with(".a");
+        })
+        .max_by_key(|entry| {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .map(|entry| entry.path());
+}
+
+fn has_valgrind() -> bool {
+    return Command::new("valgrind").arg("--version").output().is_ok();
+}
+
+#[test]
+fn test_c_api() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let deps_dir = deps_directory();
+    let library = find_library(&deps_dir).expect("liblibloadviz-*.a not found");
+
+    let binary = deps_dir.join(format!("c_api_test-{}", std::process::id()));
+    let status = Command::new("cc")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-g")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/c_api_test.c"))
+        .arg(&library)
+        .args([
+            "-lpthread",
+            "-ldl",
+            "-lm",
+            "-lrt",
+            "-lutil",
+            "-lgcc_s",
+            "-lc",
+        ])
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .expect("Failed to run cc");
+    assert!(status.success(), "Compiling c_api_test.c failed");
+
+    let trace = std::env::temp_dir().join(format!("c_api_test-{}.trace", std::process::id()));
+    let valgrind_required = std::env::var_os("LOADVIZ_REQUIRE_VALGRIND").is_some();
+    let mut command = if has_valgrind() {
+        let mut valgrind = Command::new("valgrind");
+        valgrind.args([
+            "--error-exitcode=1",
+            "--leak-check=full",
+            "--errors-for-leak-kinds=definite",
+        ]);
+        valgrind.arg(&binary);
+        valgrind
+    } else {
+        assert!(
+            !valgrind_required,
+            "LOADVIZ_REQUIRE_VALGRIND is set, but valgrind is not installed"
+        );
+
+        // Straight to stderr, eprintln!() output is hidden for passing tests
+        let _ = writeln!(
+            std::io::stderr(),
+            "valgrind not found, running c_api_test without it, leaks and memory errors go unnoticed"
+        );
+        Command::new(&binary)
+    };
+    let status = command.arg(&trace).status().unwrap();
+
+    let _ = std::fs::remove_file(&binary);
+    let _ = std::fs::remove_file(&trace);
+    assert!(status.success(), "c_api_test failed");
+}
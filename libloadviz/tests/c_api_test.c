@@ -0,0 +1,319 @@
+// Exercises the whole C API lifecycle. Built and run by tests/c_api.rs.
+//
+// Usage: c_api_test <recording.trace>
+
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "loadviz.h"
+
+static int failures = 0;
+
+#define CHECK(condition)                                                      \
+  do {                                                                        \
+    if (!(condition)) {                                                       \
+      fprintf(stderr, "%s:%d: Check failed: %s\n", __FILE__, __LINE__,        \
+              #condition);                                                    \
+      __atomic_fetch_add(&failures, 1, __ATOMIC_SEQ_CST);                     \
+    }                                                                         \
+  } while (0)
+
+/// Counts calls in user_data, and says all columns are half busy
+static uintptr_t half_busy(void *user_data, CpuLoad *loads, uintptr_t capacity) {
+  int *calls = user_data;
+  (*calls)++;
+
+  uintptr_t count = capacity < 3 ? capacity : 3;
+  for (uintptr_t i = 0; i < count; i++) {
+    loads[i].user_0_to_1 = 0.5f;
+    loads[i].system_0_to_1 = 0.0f;
+  }
+  return count;
+}
+
+/// Says the one column is more than fully busy
+static uintptr_t overloaded(void *user_data, CpuLoad *loads, uintptr_t capacity) {
+  (void)user_data;
+  (void)capacity;
+  loads[0].user_0_to_1 = 2.0f;
+  loads[0].system_0_to_1 = 0.0f;
+  return 1;
+}
+
+#define STRESS_THREADS 4
+#define STRESS_ROUNDS 100
+#define HOLD_EVERY 10
+
+/// Renders frames at its own size, and checks that frames it holds on to don't
+/// change while the other threads render
+static void *stress(void *argument) {
+  const SharedLoadViz *shared = argument;
+
+  // Different size for each thread
+  static int next_index = 0;
+  int index = __atomic_fetch_add(&next_index, 1, __ATOMIC_SEQ_CST);
+  uintptr_t width = 3 + index;
+  uintptr_t height = 7 - index;
+  uintptr_t size = width * height * 3;
+
+  const Frame *held[STRESS_ROUNDS / HOLD_EVERY];
+  uint8_t *copies[STRESS_ROUNDS / HOLD_EVERY];
+  int held_count = 0;
+
+  for (int round = 0; round < STRESS_ROUNDS; round++) {
+    const Frame *frame = loadviz_render_frame(shared, width, height);
+    CHECK(frame != NULL);
+    CHECK(loadviz_frame_width(frame) == width);
+    CHECK(loadviz_frame_height(frame) == height);
+
+    const Frame *latest = loadviz_latest_frame(shared);
+    CHECK(latest != NULL);
+    CHECK(loadviz_frame_sequence(latest) >= loadviz_frame_sequence(frame));
+    loadviz_release_frame(latest);
+
+    if (round % HOLD_EVERY == 0) {
+      held[held_count] = frame;
+      copies[held_count] = malloc(size);
+      memcpy(copies[held_count], loadviz_frame_pixels(frame), size);
+      held_count++;
+    } else {
+      loadviz_release_frame(frame);
+    }
+  }
+
+  for (int i = 0; i < held_count; i++) {
+    CHECK(memcmp(copies[i], loadviz_frame_pixels(held[i]), size) == 0);
+    free(copies[i]);
+    loadviz_release_frame(held[i]);
+  }
+  return NULL;
+}
+
+/// Switches to the visualization named in user_data, and checks that failing
+/// leaves a message behind
+static LoadVizStatus set_visualization(void *user_data, LoadViz *loadviz) {
+  LoadVizStatus status = loadviz_set_visualization(loadviz, user_data);
+  CHECK((status == LoadVizStatus_Ok) == (loadviz_last_error(loadviz) == NULL));
+  return status;
+}
+
+/// Counts frames delivered to the callback, user_data is an int
+static void count_frame(void *user_data, const Frame *frame) {
+  CHECK(loadviz_frame_width(frame) == 5);
+  __atomic_fetch_add((int *)user_data, 1, __ATOMIC_SEQ_CST);
+}
+
+int main(int argc, char **argv) {
+  if (argc != 2) {
+    fprintf(stderr, "Usage: %s <recording.trace>\n", argv[0]);
+    return 2;
+  }
+  const char *trace_path = argv[1];
+
+  CHECK(strlen(loadviz_version()) > 0);
+
+  // NULL is handled everywhere
+  free_loadviz(NULL);
+  CHECK(get_image(NULL, 1, 1) == NULL);
+  CHECK(loadviz_last_error(NULL) == NULL);
+  CHECK(loadviz_set_weather_enabled(NULL, true) == LoadVizStatus_NullPointer);
+  CHECK(!loadviz_is_recording(NULL));
+
+  LoadViz *loadviz = new_loadviz();
+  CHECK(loadviz != NULL);
+  CHECK(loadviz_last_error(loadviz) == NULL);
+
+  CHECK(loadviz_set_load_source(loadviz, "scenario:demo") == LoadVizStatus_Ok);
+  CHECK(loadviz_set_sample_interval_ms(loadviz, 10) == LoadVizStatus_Ok);
+  CHECK(loadviz_set_animation_seconds(loadviz, 1.0f, 2.0f) == LoadVizStatus_Ok);
+  CHECK(loadviz_set_weather_enabled(loadviz, false) == LoadVizStatus_Ok);
+  CHECK(loadviz_is_connected(loadviz));
+
+  // Failures leave a message behind, successes clear it
+  CHECK(loadviz_set_sample_interval_ms(loadviz, 0) ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_last_error(loadviz) != NULL);
+  CHECK(loadviz_set_animation_seconds(loadviz, -1.0f, 2.0f) ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_set_load_source(loadviz, "no-such-source") ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(strstr(loadviz_last_error(loadviz), "no-such-source") != NULL);
+  CHECK(loadviz_set_load_source(loadviz, NULL) == LoadVizStatus_NullPointer);
+  CHECK(loadviz_start_recording(loadviz, "/no/such/directory/x.trace") ==
+        LoadVizStatus_IoError);
+  CHECK(loadviz_set_weather_enabled(loadviz, true) == LoadVizStatus_Ok);
+  CHECK(loadviz_last_error(loadviz) == NULL);
+
+  CHECK(loadviz_start_recording(loadviz, trace_path) == LoadVizStatus_Ok);
+  CHECK(loadviz_is_recording(loadviz));
+
+  // The first frames can be black, until there are two samples to compare
+  unsigned int sum = 0;
+  for (int i = 0; i < 10; i++) {
+    const uint8_t *pixels = get_image(loadviz, 16, 8);
+    CHECK(pixels != NULL);
+
+    // Touch every byte so that valgrind notices if any are uninitialized
+    sum = 0;
+    for (int j = 0; j < 16 * 8 * 3; j++) {
+      sum += pixels[j];
+    }
+    usleep(20 * 1000);
+  }
+  CHECK(sum > 0);
+
+  // Resizing
+  CHECK(get_image(loadviz, 3, 2) != NULL);
+
+  // Our own buffer: a 4x4 BGRA image with padded rows, drawing into the 2x2
+  // square in the middle only
+  uint8_t buffer[4 * (4 * 4 + 8)];
+  uintptr_t stride = 4 * 4 + 8;
+  memset(buffer, 0xaa, sizeof(buffer));
+  CHECK(loadviz_render_to(loadviz, buffer + stride + 4, 2, 2, stride,
+                          PixelFormat_Bgra8888) == LoadVizStatus_Ok);
+  CHECK(buffer[0] == 0xaa);
+  CHECK(buffer[stride + 4 + 3] == 0xff);
+  CHECK(buffer[stride + 3 * 4] == 0xaa);
+
+  // Transparent background
+  CHECK(loadviz_set_transparent_background(loadviz, true) == LoadVizStatus_Ok);
+  CHECK(loadviz_render_to(loadviz, buffer, 4, 4, stride,
+                          PixelFormat_Rgba8888) == LoadVizStatus_Ok);
+  CHECK(loadviz_set_transparent_background(loadviz, false) == LoadVizStatus_Ok);
+
+  // Themes
+  CHECK(loadviz_set_theme(loadviz, "light-panel") == LoadVizStatus_Ok);
+  CHECK(loadviz_set_theme(loadviz, "/no/such/theme") ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_last_error(loadviz) != NULL);
+  CHECK(loadviz_set_theme(loadviz, "classic") == LoadVizStatus_Ok);
+
+  // Visualizations
+  CHECK(loadviz_set_visualization(loadviz, "bars") == LoadVizStatus_Ok);
+  CHECK(loadviz_render_to(loadviz, buffer, 4, 4, stride,
+                          PixelFormat_Rgba8888) == LoadVizStatus_Ok);
+  CHECK(loadviz_set_visualization(loadviz, "sparkles") ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_set_visualization(loadviz, "classic") == LoadVizStatus_Ok);
+
+  CHECK(loadviz_render_to(loadviz, buffer, 4, 4, 4,
+                          PixelFormat_Rgb565) == LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_render_to(loadviz, NULL, 4, 4, stride,
+                          PixelFormat_Rgb565) == LoadVizStatus_NullPointer);
+  CHECK(loadviz_render_to(loadviz, buffer, SIZE_MAX / 2, 4, SIZE_MAX,
+                          PixelFormat_Rgba8888) ==
+        LoadVizStatus_InvalidArgument);
+
+  CHECK(loadviz_stop_recording(loadviz) == LoadVizStatus_Ok);
+  CHECK(!loadviz_is_recording(loadviz));
+
+  // Replay what we just recorded
+  char spec[4096];
+  snprintf(spec, sizeof(spec), "replay:%s", trace_path);
+  CHECK(loadviz_set_load_source(loadviz, spec) == LoadVizStatus_Ok);
+  CHECK(get_image(loadviz, 16, 8) != NULL);
+
+  // Loads pushed from C
+  CpuLoad loads[2] = {{0.5f, 0.25f}, {0.0f, 1.0f}};
+  CHECK(loadviz_push_loads(loadviz, loads, 2) == LoadVizStatus_Ok);
+  CHECK(get_image(loadviz, 16, 8) != NULL);
+  loads[0].user_0_to_1 = 2.0f;
+  CHECK(loadviz_push_loads(loadviz, loads, 2) == LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_push_loads(loadviz, NULL, 2) == LoadVizStatus_NullPointer);
+  CHECK(loadviz_push_loads(loadviz, NULL, 0) == LoadVizStatus_Ok);
+
+  // Loads pulled through a callback
+  int calls = 0;
+  CHECK(loadviz_set_load_callback(loadviz, NULL, NULL) ==
+        LoadVizStatus_NullPointer);
+  CHECK(loadviz_set_load_callback(loadviz, half_busy, &calls) ==
+        LoadVizStatus_Ok);
+  for (int i = 0; i < 3; i++) {
+    CHECK(get_image(loadviz, 16, 8) != NULL);
+    usleep(20 * 1000);
+  }
+  CHECK(calls > 0);
+  CHECK(loadviz_load_callback_error(loadviz) == NULL);
+
+  // Out of range callback loads are skipped, and say why
+  CHECK(loadviz_set_load_callback(loadviz, overloaded, NULL) ==
+        LoadVizStatus_Ok);
+  CHECK(get_image(loadviz, 16, 8) != NULL);
+  CHECK(loadviz_load_callback_error(loadviz) != NULL);
+  CHECK(loadviz_set_load_source(loadviz, "scenario:demo") == LoadVizStatus_Ok);
+  CHECK(loadviz_load_callback_error(loadviz) == NULL);
+
+  free_loadviz(loadviz);
+
+  // Several threads sharing one LoadViz
+  loadviz = new_loadviz();
+  CHECK(loadviz_set_load_source(loadviz, "scenario:demo") == LoadVizStatus_Ok);
+  CHECK(loadviz_share(NULL) == NULL);
+  SharedLoadViz *shared = loadviz_share(loadviz);
+  CHECK(loadviz_latest_frame(shared) == NULL);
+
+  // Still configurable once shared
+  CHECK(loadviz_shared_configure(NULL, set_visualization, "bars") ==
+        LoadVizStatus_NullPointer);
+  CHECK(loadviz_shared_configure(shared, NULL, NULL) ==
+        LoadVizStatus_NullPointer);
+  CHECK(loadviz_shared_configure(shared, set_visualization, "sparkles") ==
+        LoadVizStatus_InvalidArgument);
+  CHECK(loadviz_shared_configure(shared, set_visualization, "bars") ==
+        LoadVizStatus_Ok);
+
+  pthread_t threads[STRESS_THREADS];
+  for (int i = 0; i < STRESS_THREADS; i++) {
+    CHECK(pthread_create(&threads[i], NULL, stress, shared) == 0);
+  }
+  for (int i = 0; i < STRESS_THREADS; i++) {
+    CHECK(pthread_join(threads[i], NULL) == 0);
+  }
+
+  // Rendering on a thread of its own
+  CHECK(loadviz_start_producer(NULL, 5, 5, 100.0f) == NULL);
+  FrameProducer *producer = loadviz_start_producer(shared, 5, 5, 200.0f);
+  CHECK(loadviz_producer_set_fps(producer, -1.0f) ==
+        LoadVizStatus_InvalidArgument);
+  int frame_count = 0;
+  CHECK(loadviz_producer_set_callback(producer, count_frame, &frame_count) ==
+        LoadVizStatus_Ok);
+  for (int i = 0; i < 500 && __atomic_load_n(&frame_count, __ATOMIC_SEQ_CST) < 5;
+       i++) {
+    usleep(10 * 1000);
+  }
+  CHECK(__atomic_load_n(&frame_count, __ATOMIC_SEQ_CST) >= 5);
+  CHECK(loadviz_producer_set_callback(producer, NULL, NULL) == LoadVizStatus_Ok);
+
+  CHECK(loadviz_producer_set_size(producer, 2, 2) == LoadVizStatus_Ok);
+  const Frame *produced = NULL;
+  for (int i = 0; i < 500; i++) {
+    produced = loadviz_producer_latest_frame(producer);
+    if (loadviz_frame_width(produced) == 2) {
+      break;
+    }
+    loadviz_release_frame(produced);
+    produced = NULL;
+    usleep(10 * 1000);
+  }
+  CHECK(produced != NULL);
+  loadviz_release_frame(produced);
+  free_frame_producer(producer);
+
+  // Frames outlive the shared LoadViz
+  const Frame *last = loadviz_latest_frame(shared);
+  CHECK(loadviz_frame_sequence(last) >= STRESS_THREADS * STRESS_ROUNDS);
+  free_shared_loadviz(shared);
+  CHECK(loadviz_frame_pixels(last) != NULL);
+  loadviz_release_frame(last);
+
+  if (failures > 0) {
+    fprintf(stderr, "%d checks failed\n", failures);
+    return 1;
+  }
+  return 0;
+}
@@ -0,0 +1,328 @@
+//! Renders reference loads at several sizes and times, and compares the
+//! results to the images in `tests/golden/`.
+//!
+//! Small differences, from floating point rounding on different machines for
+//! example, are accepted. On failure, the rendered image and an image showing
+//! the differences in red are written to `target/tmp/golden/`.
+//!
+//! After changing the looks on purpose, run with `LOADVIZ_UPDATE_GOLDEN=1` to
+//! update the reference images, and review them before committing.
+
+#![allow(clippy::needless_return)]
+
+use std::ffi::c_int;
+use std::path::{Path, PathBuf};
+
+use libloadviz::battery::BatteryState;
+use libloadviz::renderer::{srgb, Renderer, Weather};
+use libloadviz::CpuLoad;
+use libwebp_sys::*;
+
+/// Fixed, so that the flames always have the same shape
+static NOISE_SEED: u64 = 1337;
+
+static SIZES: &[(usize, usize)] = &[(24, 16), (97, 61), (160, 100)];
+
+static TIMES_SECONDS: &[f32] = &[0.0, 12.5];
+
+/// CIE76 color difference at which we start counting pixels as different. 2.3
+/// is about what people can notice.
+static MAX_DELTA_E: f32 = 2.3;
+
+/// This many different pixels are still fine
+static MAX_DIFFERENT_PIXELS_0_TO_1: f32 = 0.005;
+
+struct LoadSet {
+    name: &'static str,
+    loads: Vec<CpuLoad>,
+    weather: Weather,
+}
+
+fn load_sets() -> Vec<LoadSet> {
+    return vec![
+        LoadSet {
+            name: "idle",
+            loads: vec![CpuLoad::new(0.05, 0.05), CpuLoad::new(0.02, 0.03)],
+            weather: Default::default(),
+        },
+        LoadSet {
+            name: "mixed",
+            loads: vec![
+                CpuLoad::new(0.9, 0.1),
+                CpuLoad::new(0.3, 0.6),
+                CpuLoad::new(0.5, 0.3),
+            ],
+            weather: Default::default(),
+        },
+        LoadSet {
+            name: "stormy",
+            loads: vec![
+                CpuLoad::new(0.6, 0.3),
+                CpuLoad::new(0.7, 0.2),
+                CpuLoad::new(0.2, 0.5),
+            ],
+            weather: Weather {
+                rain_0_to_1: 0.8,
+                wind_m1_to_1: 0.5,
+                wind_distance: 3.0,
+                glow_0_to_1: 0.7,
+                battery: Some(BatteryState {
+                    charge_0_to_1: 0.3,
+                    charging: true,
+                }),
+                disconnected: false,
+            },
+        },
+    ];
+}
+
+/// RGB pixels
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+fn encode_webp(image: &Image) -> Vec<u8> {
+    let mut data: *mut u8 = std::ptr::null_mut();
+    unsafe {
+        let size = WebPEncodeLosslessRGB(
+            image.pixels.as_ptr(),
+            image.width as c_int,
+            image.height as c_int,
+            3 * image.width as c_int,
+            &mut data,
+        );
+        assert!(size > 0, "Encoding failed");
+        let webp = std::slice::from_raw_parts(data, size).to_vec();
+        WebPFree(data as *mut _);
+        return webp;
+    }
+}
+
+fn decode_webp(webp: &[u8]) -> Option<Image> {
+    let mut width: c_int = 0;
+    let mut height: c_int = 0;
+    unsafe {
+        let data = WebPDecodeRGB(webp.as_ptr(), webp.len(), &mut width, &mut height);
+        if data.is_null() {
+            return None;
+        }
+        let size = 3 * width as usize * height as usize;
+        let pixels = std::slice::from_raw_parts(data, size).to_vec();
+        WebPFree(data as *mut _);
+        return Some(Image {
+            width: width as usize,
+            height: height as usize,
+            pixels,
+        });
+    }
+}
+
+/// CIE L*a*b*, for a D65 white point
+fn to_lab(rgb: &[u8]) -> [f32; 3] {
+    let [red, green, blue] = [rgb[0], rgb[1], rgb[2]].map(srgb::to_linear);
+    let x = (0.4124 * red + 0.3576 * green + 0.1805 * blue) / 0.9505;
+    let y = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+    let z = (0.0193 * red + 0.1192 * green + 0.9505 * blue) / 1.089;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    return [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)];
+}
+
+fn delta_e(rgb1: &[u8], rgb2: &[u8]) -> f32 {
+    let lab1 = to_lab(rgb1);
+    let lab2 = to_lab(rgb2);
+    return (0..3)
+        .map(|i| (lab1[i] - lab2[i]) * (lab1[i] - lab2[i]))
+        .sum::<f32>()
+        .sqrt();
+}
+
+/// Returns a description of what's wrong and a diff image, or `None` if the
+/// images are close enough. In the diff image, different pixels are red and
+/// the rest is a dimmed gray version of the expected image.
+fn compare(expected: &Image, actual: &Image) -> Option<(String, Image)> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Some((
+            format!(
+                "Expected {}x{}, got {}x{}",
+                expected.width, expected.height, actual.width, actual.height
+            ),
+            Image {
+                width: actual.width,
+                height: actual.height,
+                pixels: vec![0xff; actual.pixels.len()],
+            },
+        ));
+    }
+
+    let mut diff = Vec::with_capacity(expected.pixels.len());
+    let mut different_pixels = 0;
+    let mut worst_delta_e: f32 = 0.0;
+    for (expected, actual) in expected.pixels.chunks(3).zip(actual.pixels.chunks(3)) {
+        let delta_e = delta_e(expected, actual);
+        worst_delta_e = worst_delta_e.max(delta_e);
+        if delta_e > MAX_DELTA_E {
+            different_pixels += 1;
+            diff.extend_from_slice(&[0xff, 0x00, 0x00]);
+        } else {
+            let gray = ((expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 9) as u8;
+            diff.extend_from_slice(&[gray, gray, gray]);
+        }
+    }
+
+    let pixel_count = expected.width * expected.height;
+    if different_pixels as f32 <= MAX_DIFFERENT_PIXELS_0_TO_1 * pixel_count as f32 {
+        return None;
+    }
+
+    return Some((
+        format!(
+            "{} of {} pixels differ, worst delta E is {:.1}",
+            different_pixels, pixel_count, worst_delta_e
+        ),
+        Image {
+            width: expected.width,
+            height: expected.height,
+            pixels: diff,
+        },
+    ));
+}
+
+fn render(load_set: &LoadSet, width: usize, height: usize, dt_seconds: f32) -> Image {
+    let mut renderer: Renderer = Default::default();
+    renderer.set_noise_seed(NOISE_SEED);
+
+    let mut pixels = vec![0; width * height * 3];
+    renderer.render_image(
+        &load_set.loads,
+        &load_set.weather,
+        width,
+        height,
+        dt_seconds,
+        &mut pixels,
+    );
+    return Image {
+        width,
+        height,
+        pixels,
+    };
+}
+
+fn golden_directory() -> PathBuf {
+    return Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+}
+
+fn failure_directory() -> PathBuf {
+    return Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+}
+
+#[test]
+fn test_golden_images() {
+    let update = std::env::var_os("LOADVIZ_UPDATE_GOLDEN").is_some();
+    let mut failures: Vec<String> = vec![];
+
+    for load_set in load_sets() {
+        for (width, height) in SIZES {
+            for dt_seconds in TIMES_SECONDS {
+                let name = format!("{}-{}x{}-t{}", load_set.name, width, height, dt_seconds);
+                let golden_path = golden_directory().join(format!("{}.webp", name));
+                let actual = render(&load_set, *width, *height, *dt_seconds);
+
+                if update {
+                    std::fs::create_dir_all(golden_directory()).unwrap();
+                    std::fs::write(&golden_path, encode_webp(&actual)).unwrap();
+                    continue;
+                }
+
+                let expected = std::fs::read(&golden_path)
+                    .ok()
+                    .and_then(|webp| decode_webp(&webp));
+                let Some(expected) = expected else {
+                    failures.push(format!(
+                        "{}: Missing or unreadable, run with LOADVIZ_UPDATE_GOLDEN=1 to create it",
+                        golden_path.display()
+                    ));
+                    continue;
+                };
+
+                let Some((problem, diff)) = compare(&expected, &actual) else {
+                    continue;
+                };
+                std::fs::create_dir_all(failure_directory()).unwrap();
+                let actual_path = failure_directory().join(format!("{}.webp", name));
+                let diff_path = failure_directory().join(format!("{}-diff.webp", name));
+                std::fs::write(&actual_path, encode_webp(&actual)).unwrap();
+                std::fs::write(&diff_path, encode_webp(&diff)).unwrap();
+                failures.push(format!(
+                    "{}: {}\n  Rendered: {}\n  Diff: {}",
+                    name,
+                    problem,
+                    actual_path.display(),
+                    diff_path.display()
+                ));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Golden image mismatches, run with LOADVIZ_UPDATE_GOLDEN=1 if the changes are intended:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_compare() {
+    let gray = Image {
+        width: 20,
+        height: 10,
+        pixels: vec![0x80; 20 * 10 * 3],
+    };
+    assert!(compare(&gray, &gray).is_none());
+
+    // Too small to see
+    let mut barely = gray.pixels.clone();
+    barely[0] = 0x81;
+    let barely = Image {
+        pixels: barely,
+        ..gray
+    };
+    assert!(compare(&gray, &barely).is_none());
+
+    // One red pixel is fine, two are not
+    let mut one_red = barely.pixels.clone();
+    one_red[0..3].copy_from_slice(&[0xff, 0x00, 0x00]);
+    let one_red = Image {
+        pixels: one_red,
+        ..barely
+    };
+    assert!(compare(&gray, &one_red).is_none());
+
+    let mut two_red = one_red.pixels.clone();
+    two_red[3..6].copy_from_slice(&[0xff, 0x00, 0x00]);
+    let two_red = Image {
+        pixels: two_red,
+        ..one_red
+    };
+    let (problem, diff) = compare(&gray, &two_red).unwrap();
+    assert!(problem.starts_with("2 of 200 pixels differ"), "{}", problem);
+    assert_eq!([0xff, 0x00, 0x00], diff.pixels[0..3]);
+    assert_ne!([0xff, 0x00, 0x00], diff.pixels[6..9]);
+}
+
+#[test]
+fn test_webp_round_trip() {
+    let image = render(&load_sets()[1], 24, 16, 0.0);
+    let decoded = decode_webp(&encode_webp(&image)).unwrap();
+    assert_eq!((24, 16), (decoded.width, decoded.height));
+    assert_eq!(image.pixels, decoded.pixels);
+}